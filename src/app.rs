@@ -16,14 +16,23 @@ use render::RenderPlugin;
 use resources::camera::*;
 
 use resources::actions::Actions;
+use resources::dataset_manifest::DatasetInfo;
+use resources::lod_iterator::LodStrategy;
 // use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 use bevy::prelude::*;
 use bevy_gizmos::*;
 use bevy_inspector_egui::InspectorPlugin;
 use bevy_obj::ObjPlugin;
-use bevy_ply::PlyPlugin;
 use bevy_stl::StlPlugin;
 
+use crate::support::http_cache::HttpCache;
+use crate::support::loaders::{
+    bgeo::BgeoPlugin, gltf::GltfFramePlugin, numpy::NumpyPlugin, ply::PlyPlugin,
+    point_cloud::PointCloudPlugin, vtk::VtkPlugin, vtk_xml::VtkXmlPlugin,
+};
+use crate::support::mesh_cache::{MeshCache, MeshCachePlugin};
+
+use std::path::Path;
 use structopt::StructOpt;
 
 pub type Real = f32;
@@ -46,6 +55,64 @@ pub struct AppOptions {
     load_mesh: Option<String>,
     #[structopt(short, long, default_value = "100")]
     load_max: usize,
+    /// Maximum number of frames to keep in memory, 0 for unlimited
+    #[structopt(long, default_value = "0")]
+    cache_max_frames: usize,
+    /// Maximum megabytes of frames to keep in memory, 0 for unlimited
+    #[structopt(long, default_value = "0")]
+    cache_max_megabytes: usize,
+    /// Which frames to load at each LOD: midpoint, stride, window or list
+    #[structopt(long, default_value = "midpoint")]
+    lod_strategy: LodStrategy,
+    /// The stride of the first LOD, for the stride strategy
+    #[structopt(long, default_value = "10")]
+    lod_stride: usize,
+    /// The frame to start from, for the window strategy
+    #[structopt(long, default_value = "0")]
+    lod_window_start: usize,
+    /// A file listing the frames to load, for the list strategy
+    #[structopt(long)]
+    lod_frame_list: Option<String>,
+    /// Watch the dataset for new frames, as a running simulation writes them
+    #[structopt(short, long)]
+    watch: bool,
+    /// Keep each parsed frame in a binary cache, read instead of the frame until it changes
+    #[structopt(long)]
+    mesh_cache: bool,
+    /// Where the binary cache of parsed frames is kept
+    #[structopt(long, default_value = "assets/mesh-cache")]
+    mesh_cache_dir: String,
+    /// A dataset served over HTTP, the URL of a JSON list of its files or of a directory listing.
+    /// May be given several times
+    #[structopt(short, long)]
+    url: Vec<String>,
+    /// Where the frames of datasets served over HTTP are kept
+    #[structopt(long, default_value = "assets/url-cache")]
+    url_cache_dir: String,
+    /// Maximum megabytes of frames to keep in the URL cache, 0 for unlimited
+    #[structopt(long, default_value = "4096")]
+    url_cache_megabytes: u64,
+}
+
+impl AppOptions {
+    pub fn mesh_cache(&self) -> Option<MeshCache> {
+        if self.mesh_cache {
+            Some(MeshCache::new(Path::new(&self.mesh_cache_dir)))
+        } else {
+            None
+        }
+    }
+
+    /// Only when there are datasets served over HTTP, so no cache dir is made otherwise
+    pub fn http_cache(&self) -> Option<HttpCache> {
+        if self.url.is_empty() {
+            return None;
+        }
+        Some(HttpCache::new(
+            Path::new(&self.url_cache_dir),
+            self.url_cache_megabytes * 1024 * 1024,
+        ))
+    }
 }
 
 #[derive(Default)]
@@ -70,7 +137,8 @@ impl Plugin for GamePlugin {
             .add_plugin(GizmosPlugin)
             // Inspectable
             .add_plugin(InspectorPlugin::<Actions>::new())
-            .add_plugin(InspectorPlugin::<CameraSystem>::new());
+            .add_plugin(InspectorPlugin::<CameraSystem>::new())
+            .add_plugin(InspectorPlugin::<DatasetInfo>::new());
 
         // .add_plugin(FrameTimeDiagnosticsPlugin::default())
         // .add_plugin(LogDiagnosticsPlugin::default())
@@ -78,7 +146,14 @@ impl Plugin for GamePlugin {
         // 3d Format Loaders
         app.add_plugin(ObjPlugin)
             .add_plugin(StlPlugin)
-            .add_plugin(PlyPlugin);
+            .add_plugin(PlyPlugin)
+            .add_plugin(VtkPlugin)
+            .add_plugin(VtkXmlPlugin)
+            .add_plugin(PointCloudPlugin)
+            .add_plugin(BgeoPlugin)
+            .add_plugin(NumpyPlugin)
+            .add_plugin(GltfFramePlugin)
+            .add_plugin(MeshCachePlugin);
 
         app.add_system(persistent_gizmos.system());
         app.add_startup_system(initialize_state.system());
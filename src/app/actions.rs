@@ -36,7 +36,7 @@ fn state_plumbing(
         );
     }
 
-    if actions.max_particles_render != mesh_pool.sample_size {
-        mesh_pool.sample_size = actions.max_particles_render;
+    if actions.particle_sample_size() != mesh_pool.sample_size {
+        mesh_pool.sample_size = actions.particle_sample_size();
     }
 }
@@ -32,7 +32,7 @@ fn set_movement_actions(
     controllers: Query<&FpsCameraController>,
     keyboard: Res<Input<KeyCode>>,
     mut events: EventWriter<ControlEvent>,
-    meshes: Res<Assets<Mesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
     render_cache: Res<RenderCache>,
 ) {
     if keyboard_input.just_pressed(KeyCode::T) {
@@ -50,26 +50,24 @@ fn set_movement_actions(
     if actions.paused {
         let material = materials.get_handle(fluid_assets.material.id);
         if keyboard_input.pressed(KeyCode::Left) {
-            mesh_pool.despawn_mesh(&mut commands, &*meshes);
             mesh_pool.retreat();
             mesh_pool.spawn_mesh(
                 &*fluid_assets,
                 material.clone(),
                 &mut commands,
-                &*meshes,
+                &mut *meshes,
                 &*render_cache,
                 actions.particle_render_style,
             )
         }
 
         if keyboard_input.pressed(KeyCode::Right) {
-            mesh_pool.despawn_mesh(&mut commands, &*meshes);
             mesh_pool.advance();
             mesh_pool.spawn_mesh(
                 &*fluid_assets,
                 material.clone(),
                 &mut commands,
-                &*meshes,
+                &mut *meshes,
                 &*render_cache,
                 actions.particle_render_style,
             )
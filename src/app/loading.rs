@@ -6,17 +6,18 @@ use std::time::Duration;
 use super::resources::background_meshes::BackgroundMeshes;
 use super::resources::mesh_pool::MeshPool;
 use super::GameState;
-use super::{
-    loading::paths::PATHS, resources::lod_midpoint_iterator::MidpointIterator, AppOptions,
-};
+use super::{loading::paths::PATHS, resources::lod_iterator::LodOptions, AppOptions};
 use crate::app::inspector::vec_as_dropdown::VecAsDropdown;
 use crate::app::resources::actions::Actions;
 use crate::app::resources::asset_load_checker::{AssetLoadChecker, LoadingSource};
 use crate::app::resources::glob_or_dir_loader::GlobOrDirLoader;
 use crate::app::resources::load_manager::LoadManager;
+use crate::support::asset_io::asset_path;
+use crate::support::http_cache::HttpCache;
 use crate::support::loader_fu::render::{FeatureAwareRenderer, RenderCache};
 use bevy::asset::LoadState;
 use bevy::prelude::*;
+use bevy::render::pipeline::PipelineDescriptor;
 use bevy_kira_audio::AudioSource;
 
 pub struct LoadingPlugin;
@@ -56,8 +57,19 @@ pub struct LoadingState {
 pub struct MeshAssets {
     pub loaded: Vec<(String, Handle<Mesh>)>,
     pub loading: Vec<(String, HandleUntyped)>,
+    pub failed: Vec<(String, String)>,
     pub material: Handle<StandardMaterial>,
 }
+
+impl MeshAssets {
+    /// Drops the handles to the current dataset's meshes, so they can be freed
+    pub fn clear(&mut self) {
+        self.loaded.clear();
+        self.loading.clear();
+        self.failed.clear();
+    }
+}
+
 pub struct FontAssets {
     pub fira_sans: Handle<Font>,
 }
@@ -73,21 +85,34 @@ pub struct TextureAssets {
 fn register_initial_resources(
     mut commands: Commands,
     config: Res<AppOptions>,
+    http_cache: Option<Res<HttpCache>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut state: ResMut<State<GameState>>,
     mut meshes: ResMut<Assets<Mesh>>,
+    mut pipelines: ResMut<Assets<PipelineDescriptor>>,
+    mut shaders: ResMut<Assets<Shader>>,
     asset_server: Res<AssetServer>,
     actions: Res<Actions>,
 ) {
     // load files
     let load_files: Vec<String> = Vec::new();
-    let load_iterator = MidpointIterator::new(load_files, actions.initial_lod);
-    let load_manager = LoadManager::new(load_iterator);
+    let lod_options = LodOptions {
+        strategy: config.lod_strategy,
+        stride: config.lod_stride,
+        window_start: config.lod_window_start,
+        frame_list: config.lod_frame_list.clone().unwrap_or_default(),
+    };
+    let load_iterator = lod_options.iterator(load_files, actions.initial_lod);
+    let mut load_manager = LoadManager::new(load_iterator, lod_options.clone());
+    load_manager.set_mesh_cache(config.mesh_cache());
     let mut glob_or_dir_loader = GlobOrDirLoader::new(
         load_manager,
         config.file_glob.clone(),
         config.dataset_dir.clone(),
     );
+    if let Some(http_cache) = http_cache {
+        glob_or_dir_loader = glob_or_dir_loader.with_urls(config.url.clone(), http_cache.clone());
+    }
 
     glob_or_dir_loader.update(config.file_glob.clone(), None, &asset_server);
 
@@ -113,15 +138,19 @@ fn register_initial_resources(
 
     let load_manager = glob_or_dir_loader.load_manager();
     let mut actions = Actions::default();
+    actions.cache_max_frames = config.cache_max_frames;
+    actions.cache_max_megabytes = config.cache_max_megabytes;
+    actions.lod_options = lod_options;
+    actions.watch_for_new_frames = config.watch;
     actions.load_number_of_frames = VecAsDropdown::new(load_manager.load_iterator.get_lods());
 
     let mut dataset_dirs: Vec<String> = vec![String::from("Choose Datadir")];
-    if let Some(entries) = glob_or_dir_loader.dirs_from_load_dir() {
+    if let Some(entries) = glob_or_dir_loader.datasets() {
         dataset_dirs.extend(entries);
     }
 
     let particle_radius = actions.particle_radius;
-    let particle_sample_size = actions.max_particles_render;
+    let particle_sample_size = actions.particle_sample_size();
     actions.datasets = VecAsDropdown::new(dataset_dirs);
 
     commands.insert_resource(actions);
@@ -129,6 +158,7 @@ fn register_initial_resources(
     commands.insert_resource(MeshAssets {
         loaded: load_manager.loaded.clone(),
         loading: load_manager.loading.clone(),
+        failed: load_manager.failed.clone(),
         material: material,
     });
 
@@ -143,6 +173,7 @@ fn register_initial_resources(
     // This cache is for the PointRenderer code, to either render spheres, or directional arrows
     let mut render_cache = RenderCache::new(particle_radius);
     FeatureAwareRenderer::cache_meshes(&mut *meshes, particle_radius, &mut render_cache);
+    FeatureAwareRenderer::cache_pipelines(&mut *pipelines, &mut *shaders, &mut render_cache);
 
     commands.insert_resource(render_cache);
 
@@ -160,9 +191,13 @@ fn load_mesh_assets(
         .load_assets(&asset_server);
 
     if let Some(load_mesh) = config.load_mesh.clone() {
-        let handle =
-            asset_server.load_untyped(Path::new(&load_mesh).strip_prefix("assets/").unwrap());
-        (*background_meshes).loading_mut().push(handle);
+        match asset_path(&load_mesh) {
+            Ok(load_mesh) => {
+                let handle = asset_server.load_untyped(Path::new(&load_mesh));
+                (*background_meshes).loading_mut().push(handle);
+            }
+            Err(e) => error!("Couldn't load background mesh: {}", e),
+        }
     }
 }
 
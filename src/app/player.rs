@@ -1,19 +1,26 @@
-use std::time::Duration;
+use std::{collections::HashMap, path::Path, time::Duration};
 
-use crate::support::loader_fu::render::RenderCache;
+use crate::support::asset_io::asset_path;
+use crate::support::loader_fu::{
+    attributes::point_arrays, classes::ParticleClasses, render::RenderCache,
+};
 
 use super::inspector::vec_as_dropdown::VecAsDropdown;
-use super::resources::actions::{Actions, State as AppState};
+use super::resources::actions::{Actions, FrameDirection, PlaybackMode, State as AppState};
 use super::resources::asset_load_checker::AssetLoadChecker;
 use super::resources::background_meshes::BackgroundMeshes;
 use super::resources::camera::CameraSystem;
+use super::resources::dataset_manifest::{DatasetInfo, DatasetManifest};
 use super::resources::glob_or_dir_loader::GlobOrDirLoader;
+use super::resources::load_manager::LoadManager;
 use super::resources::mesh_pool::MeshPool;
 use super::GameState;
 use super::{loading::MeshAssets, AppOptions};
 use bevy::app::Events;
 use bevy::window::WindowFocused;
-use bevy::{pbr::AmbientLight, prelude::*, render::camera::PerspectiveProjection};
+use bevy::{
+    pbr::AmbientLight, prelude::*, render::camera::PerspectiveProjection, tasks::IoTaskPool,
+};
 use bevy_inspector_egui::bevy_egui::EguiContext;
 use smooth_bevy_cameras::controllers::fps::{
     default_mouse_input_map, FpsCameraBundle, FpsCameraControlPlugin, FpsCameraController,
@@ -182,8 +189,10 @@ fn handle_actions(
     config: Res<AppOptions>,
     mut camera_system: ResMut<CameraSystem>,
     materials: ResMut<Assets<StandardMaterial>>,
-    meshes: Res<Assets<Mesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
     render_cache: Res<RenderCache>,
+    mut background_meshes: ResMut<BackgroundMeshes>,
+    mut dataset_info: ResMut<DatasetInfo>,
 ) {
     mesh_pool.advance_every = Duration::from_secs_f32(actions.advance_every);
     mesh_pool.frame_direction = actions.frame_direction.clone();
@@ -197,41 +206,109 @@ fn handle_actions(
             &*mesh_assets,
             material,
             &*render_cache,
-            &*meshes,
+            &mut *meshes,
             actions.particle_render_style,
         );
         actions.reset = false;
     }
 
+    if actions.use_file_colors != mesh_pool.vertex_colors
+        || actions.batch_particles != mesh_pool.batch_points
+        || actions.attribute_mapping != mesh_pool.attribute_mapping
+        || actions.particle_classes != mesh_pool.classes
+    {
+        mesh_pool.vertex_colors = actions.use_file_colors;
+        mesh_pool.batch_points = actions.batch_particles;
+        mesh_pool.attribute_mapping = actions.attribute_mapping.clone();
+        mesh_pool.classes = actions.particle_classes.clone();
+        let material = materials.get_handle(mesh_assets.material.id);
+        mesh_pool.redraw(
+            &mut commands,
+            &*mesh_assets,
+            material,
+            &*render_cache,
+            &mut *meshes,
+            actions.particle_render_style,
+        );
+    }
+
+    // the arrays of the frame on screen, and what each is taken to be
+    let current_mesh = mesh_pool
+        .current_mesh(&*mesh_assets)
+        .and_then(|(_, handle)| meshes.get(handle.clone()));
+    if let Some(mesh) = current_mesh {
+        let described = actions.attribute_mapping.describe(&point_arrays(mesh));
+        if described != dataset_info.point_arrays {
+            dataset_info.point_arrays = described;
+        }
+    }
+
     mesh_pool.num_fluids = mesh_assets.loaded.len();
 
+    if actions.playback_mode == PlaybackMode::SimulatedTime {
+        if let Some(advance_every) = simulated_advance_every(
+            &*mesh_pool,
+            &*mesh_assets,
+            glob_or_dir_loader.load_manager(),
+            actions.realtime_ratio,
+        ) {
+            mesh_pool.advance_every = advance_every;
+        }
+    }
+
     let load_manager = glob_or_dir_loader.load_manager_mut();
     // if the user has chosen a higer asset load lod
     let wanted_lod_len = actions.load_number_of_frames.selected_value();
     if let Some(wanted_lod_len) = wanted_lod_len {
-        if wanted_lod_len > (load_manager.loaded.len() + load_manager.loading.len())
-            && load_manager.fully_loaded()
-        {
+        // prefetched & evicted frames don't count towards the lod
+        if wanted_lod_len > load_manager.load_iterator.len() && load_manager.fully_loaded() {
             load_manager.next_lod_and_reload(&asset_server);
             mesh_assets.loading = load_manager.loading.clone();
         }
     }
 
-    if actions.initial_lod != load_manager.load_iterator.first_lod {
-        load_manager.load_iterator.first_lod = actions.initial_lod;
+    let budget = &mut load_manager.frame_cache.budget;
+    budget.max_frames = Some(actions.cache_max_frames).filter(|&max| max > 0);
+    budget.max_megabytes = Some(actions.cache_max_megabytes).filter(|&max| max > 0);
+
+    if actions.initial_lod != load_manager.load_iterator.first_lod() {
+        load_manager
+            .load_iterator
+            .set_first_lod(actions.initial_lod);
+    }
+
+    if actions.lod_options != load_manager.lod_options {
+        load_manager.set_lod_options(actions.lod_options.clone(), &asset_server);
+        actions.load_number_of_frames = VecAsDropdown::new(load_manager.load_iterator.get_lods());
     }
 
     // if the user has chosen a different data dir
     if actions.datasets.changed() {
         glob_or_dir_loader.load_manager_mut().clear();
+        mesh_assets.clear();
         glob_or_dir_loader.update(
             config.file_glob.clone(),
             actions.datasets.selected_value(),
             &asset_server,
         );
+        actions.missing_frames = glob_or_dir_loader.missing_frames();
         let load_manager = glob_or_dir_loader.load_manager();
         actions.load_number_of_frames = VecAsDropdown::new(load_manager.load_iterator.get_lods());
 
+        let manifest = glob_or_dir_loader.manifest().cloned();
+        *dataset_info = manifest.as_ref().map(DatasetInfo::from).unwrap_or_default();
+        background_meshes.clear_dataset_meshes(&mut commands);
+        mesh_pool.dataset_transform = Transform::identity();
+        if let (Some(manifest), Some(dataset)) = (&manifest, actions.datasets.selected_value()) {
+            mesh_pool.dataset_transform = manifest.transform();
+            load_manifest_meshes(
+                manifest,
+                &glob_or_dir_loader.dataset_dir(&dataset),
+                &mut *background_meshes,
+                &asset_server,
+            );
+        }
+
         let material = materials.get_handle(mesh_assets.material.id);
 
         mesh_pool.clear(&mut commands, &*meshes);
@@ -240,22 +317,34 @@ fn handle_actions(
             &*mesh_assets,
             material,
             &*render_cache,
-            &*meshes,
+            &mut *meshes,
             actions.particle_render_style,
         );
 
+        // the new dataset's classes are listed as its frames load, unless its config has them
+        actions.particle_classes = ParticleClasses::default();
         if let Some(dataset) = actions.datasets.selected_value() {
             if let Ok(dir) = std::env::current_dir() {
-                let dataset_dir = dir.join(dataset);
+                let dataset_dir = dir.join(glob_or_dir_loader.dataset_dir(&dataset));
                 if let Ok(config) = std::fs::read_to_string(dataset_dir.join("mr-config.ron")) {
                     if let Ok(config) = ron::from_str::<Actions>(config.as_str()) {
                         println!("got config");
                         // *actions = config;
                         actions.fluid_color = config.fluid_color;
+                        actions.use_file_colors = config.use_file_colors;
+                        actions.batch_particles = config.batch_particles;
+                        actions.attribute_mapping = config.attribute_mapping;
+                        actions.color_by = config.color_by;
+                        actions.color_scalar = config.color_scalar;
+                        actions.colormap = config.colormap;
+                        actions.color_range = config.color_range;
+                        actions.show_legend = config.show_legend;
+                        actions.particle_classes = config.particle_classes;
                         actions.spot_lighting = config.spot_lighting;
                         actions.lighting_intensity = config.lighting_intensity;
                         actions.opacity = config.opacity;
                         actions.material_roughness = config.material_roughness;
+                        actions.lod_options = config.lod_options;
                     }
                 }
                 if let Ok(config) =
@@ -271,12 +360,70 @@ fn handle_actions(
     }
 }
 
+/// Loads the background meshes listed in a dataset's manifest, in the dataset's transform
+fn load_manifest_meshes(
+    manifest: &DatasetManifest,
+    dir: &Path,
+    background_meshes: &mut BackgroundMeshes,
+    server: &AssetServer,
+) {
+    let transform = manifest.transform();
+    for mesh in manifest.background_meshes.iter() {
+        match asset_path(dir.join(&mesh.file)) {
+            Ok(path) => background_meshes.load_for_dataset(
+                server.load_untyped(Path::new(&path)),
+                mesh.color,
+                transform,
+            ),
+            Err(e) => error!("Couldn't load background mesh: {}", e),
+        }
+    }
+}
+
+/// How long to show the current frame for, the simulated time until the next frame over the
+/// real-time ratio. None when either frame has no time, or when playback wraps around
+fn simulated_advance_every(
+    pool: &MeshPool,
+    mesh_assets: &MeshAssets,
+    load_manager: &LoadManager,
+    realtime_ratio: f32,
+) -> Option<Duration> {
+    let len = mesh_assets.loaded.len();
+    let current = pool.current_mesh_index;
+    if len < 2 || current >= len || realtime_ratio <= 0. {
+        return None;
+    }
+    let next = match pool.frame_direction {
+        FrameDirection::Forward if current + 1 < len => current + 1,
+        FrameDirection::Back if current > 0 => current - 1,
+        _ => return None,
+    };
+    let current_time = load_manager.frame_time(&mesh_assets.loaded[current].0)?;
+    let next_time = load_manager.frame_time(&mesh_assets.loaded[next].0)?;
+    Some(Duration::from_secs_f64(
+        (next_time - current_time).abs() / realtime_ratio as f64,
+    ))
+}
+
 fn check_for_reload(
     mut actions: ResMut<Actions>,
     config: Res<AppOptions>,
     mut glob_or_dir_loader: ResMut<GlobOrDirLoader>,
     asset_server: Res<AssetServer>,
 ) {
+    if actions.retry_failed {
+        glob_or_dir_loader
+            .load_manager_mut()
+            .retry_failed(&asset_server);
+        actions.retry_failed = false;
+    }
+
+    let watch = (actions.watch_for_new_frames, actions.reload_changed_frames);
+    if watch != glob_or_dir_loader.watching() {
+        glob_or_dir_loader.watch(watch.0, watch.1);
+    }
+    glob_or_dir_loader.update_watched(&asset_server);
+
     if !actions.reload {
         return;
     }
@@ -287,6 +434,7 @@ fn check_for_reload(
         &asset_server,
     );
 
+    actions.missing_frames = glob_or_dir_loader.missing_frames();
     actions.load_number_of_frames = VecAsDropdown::new_with_selected(
         glob_or_dir_loader.load_manager().load_iterator.get_lods(),
         actions.load_number_of_frames.selected_index(),
@@ -306,7 +454,9 @@ fn check_mesh_assets(
     mut glob_or_dir_loader: ResMut<GlobOrDirLoader>,
     mut background_meshes: ResMut<BackgroundMeshes>,
     load_checker: Res<AssetLoadChecker<Mesh>>,
-    meshes: Res<Assets<Mesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    render_cache: Res<RenderCache>,
+    io_pool: Res<IoTaskPool>,
     mut query: Query<(&mut FpsCameraController, &mut LookTransform)>,
 ) {
     load_checker.update(&mut *background_meshes, &*asset_server);
@@ -314,18 +464,101 @@ fn check_mesh_assets(
 
     let load_manager = glob_or_dir_loader.load_manager_mut();
     load_manager.update_load_state(&asset_server);
+    load_manager.store_in_mesh_cache(&*meshes, &*io_pool);
+    let reloaded = load_manager.take_reloaded();
+
+    let current_file = pool.current_mesh(&*fluid_assets).map(|(f, _)| f.clone());
+    load_manager.enforce_budget(&*meshes, current_file.as_deref());
+    load_manager.reload_evicted_near(current_file.as_deref(), &asset_server);
+    // frames ahead are only worth loading first while playing towards them
+    if !actions.paused {
+        load_manager.prefetch(
+            current_file.as_deref(),
+            &actions.frame_direction,
+            actions.prefetch_frames,
+            &asset_server,
+        );
+    }
+    actions.frames_resident = load_manager.loaded.len();
+    actions.megabytes_resident = load_manager.resident_megabytes();
 
-    fluid_assets.loaded = load_manager.loaded.clone();
-    fluid_assets
-        .loaded
-        .sort_by(|(a, _), (b, _)| alphanumeric_sort::compare_str(a.as_str(), b.as_str()));
+    fluid_assets.loaded = load_manager.loaded_in_order();
 
     fluid_assets.loading = load_manager.loading.clone();
+    fluid_assets.failed = load_manager.failed.clone();
 
-    actions.fluids_loaded = fluid_assets.loaded.len();
-    actions.fluids_loaded_percent = (fluid_assets.loaded.len().max(1) as f32
-        / (fluid_assets.loaded.len() + load_manager.loading.len()) as f32)
+    actions.fluids_loaded = load_manager.loaded.len();
+    actions.fluids_failed = load_manager.failed.len();
+    actions.fluids_loaded_percent = (load_manager.loaded.len().max(1) as f32
+        / load_manager.requested_len().max(1) as f32)
         * 100.;
+
+    if !actions.skip_failed_frames {
+        hold_previous_for_failed(&mut *fluid_assets, &load_manager.playback_order());
+    }
+
+    // frames being loaded or evicted shift the current frame's index
+    if let Some(current_file) = current_file {
+        if let Some(index) = fluid_assets
+            .loaded
+            .iter()
+            .position(|(f, _)| *f == current_file)
+        {
+            pool.current_mesh_index = index;
+        }
+    }
+
+    // frames overwritten on disk are redrawn straight away, if they are on screen
+    let current_reloaded = pool
+        .current_mesh(&*fluid_assets)
+        .map_or(false, |(f, _)| reloaded.contains(f));
+    if current_reloaded {
+        let material = materials.get_handle(fluid_assets.material.id);
+        pool.redraw(
+            &mut commands,
+            &*fluid_assets,
+            material,
+            &*render_cache,
+            &mut *meshes,
+            actions.particle_render_style,
+        );
+    }
+
+    let newest = fluid_assets.loaded.len().saturating_sub(1);
+    if actions.pin_to_newest && !fluid_assets.loaded.is_empty() && pool.current_mesh_index != newest
+    {
+        pool.current_mesh_index = newest;
+        let material = materials.get_handle(fluid_assets.material.id);
+        pool.spawn_mesh(
+            &*fluid_assets,
+            material,
+            &mut commands,
+            &mut *meshes,
+            &*render_cache,
+            actions.particle_render_style,
+        );
+    }
+}
+
+/// Fills the slot of each failed frame with the frame before it, so playback timing is kept
+fn hold_previous_for_failed(
+    fluid_assets: &mut MeshAssets,
+    order: &HashMap<&str, usize>,
+) {
+    let index = |file: &str| order.get(file).copied().unwrap_or(usize::MAX);
+    for (failed, _) in fluid_assets.failed.iter() {
+        let position = fluid_assets
+            .loaded
+            .iter()
+            .position(|(loaded, _)| index(loaded.as_str()) > index(failed.as_str()));
+        let position = position.unwrap_or(fluid_assets.loaded.len());
+        if position > 0 {
+            let previous = fluid_assets.loaded[position - 1].1.clone();
+            fluid_assets
+                .loaded
+                .insert(position, (failed.clone(), previous));
+        }
+    }
 }
 
 fn remove_player(
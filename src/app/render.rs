@@ -1,14 +1,21 @@
-use crate::support::loader_fu::render::RenderCache;
+use std::collections::HashSet;
+
+use crate::support::loader_fu::attributes::{AttributeMapping, AttributeRole};
+use crate::support::loader_fu::classes::ParticleClasses;
+use crate::support::loader_fu::colormap::{ColorBy, ColorRange, Coloring, Colormap};
+use crate::support::loader_fu::features::Features;
+use crate::support::loader_fu::render::{FeatureAwareRenderer, RenderCache};
 
 use super::loading::MeshAssets;
 use super::resources::actions::Actions;
 use super::resources::camera::*;
+use super::resources::color_range::{ColorRangeTracker, ColorSource};
 use super::resources::glob_or_dir_loader::GlobOrDirLoader;
-use super::resources::lod_midpoint_iterator::MidpointIterator;
 use super::resources::mesh_lookat_estimator::MeshLookAtEstimator;
 use super::resources::mesh_pool::MeshPool;
 use super::GameState;
 use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContext};
 use smooth_bevy_cameras::controllers::fps::FpsCameraController;
 use smooth_bevy_cameras::LookTransform;
 
@@ -21,9 +28,12 @@ impl Plugin for RenderPlugin {
         &self,
         app: &mut AppBuilder,
     ) {
-        app.add_system_set(
+        app.init_resource::<ColorRangeTracker>().add_system_set(
             SystemSet::on_update(GameState::Playing)
                 .with_system(update_mesh.system().label("update_mesh"))
+                .with_system(update_coloring.system().before("update_mesh"))
+                .with_system(discover_particle_classes.system().before("update_mesh"))
+                .with_system(color_legend.system())
                 .with_system(camera_timeline_system.system().before("update_mesh")),
         );
     }
@@ -37,7 +47,7 @@ fn update_mesh(
     camera_system: ResMut<CameraSystem>,
     fluid_assets: ResMut<MeshAssets>,
     loader: ResMut<GlobOrDirLoader>,
-    meshes: Res<Assets<Mesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
     mut transform_query: Query<(&mut FpsCameraController, &mut LookTransform, &mut Transform)>,
     time: Res<Time>,
     render_cache: Res<RenderCache>,
@@ -47,14 +57,18 @@ fn update_mesh(
             if let Some(mesh) = meshes.get(current_mesh.1.clone()) {
                 if let Ok((_, mut transform, _)) = transform_query.single_mut() {
                     if let Some((eye, target)) = MeshLookAtEstimator::eye_target(mesh) {
-                        transform.eye = eye;
-                        transform.target = target;
+                        transform.eye = pool.dataset_transform.mul_vec3(eye);
+                        transform.target = pool.dataset_transform.mul_vec3(target);
                     }
                 }
             }
         }
         actions.current_file = current_mesh.0.clone();
         actions.current_frame = pool.current_mesh_index;
+        actions.current_time = loader
+            .load_manager()
+            .frame_time(&current_mesh.0)
+            .unwrap_or_default() as f32;
     }
 
     // println!("update_mesh");
@@ -85,9 +99,9 @@ fn update_mesh(
             material,
             time.delta(),
             &*render_cache,
-            &*meshes,
+            &mut *meshes,
             actions.particle_render_style,
-            actions.max_particles_render,
+            actions.particle_sample_size(),
         );
 
         if let Some(current_mesh) = pool.current_mesh(&fluid_assets) {
@@ -96,18 +110,236 @@ fn update_mesh(
     }
 }
 
+/// Summarizes each loaded frame's values for the range they're colored over, redrawing the
+/// current frame whenever what it is colored by (or the range) changes
+fn update_coloring(
+    mut commands: Commands,
+    actions: Res<Actions>,
+    mut pool: ResMut<MeshPool>,
+    fluid_assets: Res<MeshAssets>,
+    mut tracker: ResMut<ColorRangeTracker>,
+    mut render_cache: ResMut<RenderCache>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut palette: Local<Option<(Colormap, f32, f32)>>,
+    mut class_materials: Local<Option<(ParticleClasses, f32, f32)>>,
+    mut color_range: Local<Option<ColorRange>>,
+) {
+    let palette_key = (
+        actions.colormap,
+        actions.opacity,
+        actions.material_roughness,
+    );
+    if *palette != Some(palette_key) {
+        FeatureAwareRenderer::cache_palette(
+            &mut *materials,
+            actions.colormap,
+            actions.opacity,
+            actions.material_roughness,
+            &mut *render_cache,
+        );
+        *palette = Some(palette_key);
+    }
+    let class_materials_key = (
+        actions.particle_classes.clone(),
+        actions.opacity,
+        actions.material_roughness,
+    );
+    if *class_materials != Some(class_materials_key.clone()) {
+        FeatureAwareRenderer::cache_class_materials(
+            &mut *materials,
+            &actions.particle_classes,
+            actions.opacity,
+            actions.material_roughness,
+            &mut *render_cache,
+        );
+        *class_materials = Some(class_materials_key);
+    }
+
+    tracker.set_source(ColorSource {
+        dataset: actions.datasets.selected_value(),
+        by: actions.color_by,
+        scalar: actions.color_scalar.clone(),
+        mapping: actions.attribute_mapping.clone(),
+        transform: pool.dataset_transform,
+    });
+    if !matches!(actions.color_by, ColorBy::Uniform | ColorBy::Class) {
+        for (file, handle) in fluid_assets.loaded.iter() {
+            if tracker.contains(file) {
+                continue;
+            }
+            if let Some(mesh) = meshes.get(handle.clone()) {
+                let features = Features::new(mesh).with_mapping(&actions.attribute_mapping);
+                let values = Coloring::values(
+                    actions.color_by,
+                    &actions.color_scalar,
+                    &features,
+                    pool.dataset_transform,
+                );
+                if let Some(values) = values {
+                    tracker.insert(file.clone(), &values);
+                }
+            }
+        }
+    }
+
+    let range = match actions.color_by {
+        ColorBy::Uniform => None,
+        // classes have colors of their own rather than a range
+        ColorBy::Class => Some((0., 0.)),
+        _ => tracker.range(&actions.color_range),
+    };
+    let coloring = range.map(|(min, max)| Coloring {
+        by: actions.color_by,
+        scalar: actions.color_scalar.clone(),
+        colormap: actions.colormap,
+        min,
+        max,
+    });
+    // each frame loaded may widen the range a little, which isn't worth redrawing for until they
+    // have all loaded. Anything the user changes is redrawn straight away
+    let changed = match (&coloring, &pool.coloring) {
+        (Some(new), Some(old)) => {
+            new.by != old.by
+                || new.scalar != old.scalar
+                || new.colormap != old.colormap
+                || *color_range != Some(actions.color_range.clone())
+        }
+        _ => true,
+    };
+    let settled = fluid_assets.loading.is_empty();
+    if coloring != pool.coloring && (changed || settled) {
+        pool.coloring = coloring;
+        *color_range = Some(actions.color_range.clone());
+        let material = materials.get_handle(fluid_assets.material.id);
+        pool.redraw(
+            &mut commands,
+            &*fluid_assets,
+            material,
+            &*render_cache,
+            &mut *meshes,
+            actions.particle_render_style,
+        );
+    }
+}
+
+/// The frames whose particles' classes have been listed, of a dataset and mapping
+#[derive(Default)]
+struct ScannedFrames {
+    source: Option<(Option<String>, AttributeMapping)>,
+    files: HashSet<String>,
+}
+
+/// Lists the classes of each newly loaded frame's particles in particle_classes, with the colors
+/// and visibility of the classes already listed left as they are
+fn discover_particle_classes(
+    mut actions: ResMut<Actions>,
+    fluid_assets: Res<MeshAssets>,
+    meshes: Res<Assets<Mesh>>,
+    mut scanned: Local<ScannedFrames>,
+) {
+    let source = Some((
+        actions.datasets.selected_value(),
+        actions.attribute_mapping.clone(),
+    ));
+    if scanned.source != source {
+        scanned.source = source;
+        scanned.files.clear();
+    }
+    for (file, handle) in fluid_assets.loaded.iter() {
+        if scanned.files.contains(file) {
+            continue;
+        }
+        if let Some(mesh) = meshes.get(handle.clone()) {
+            let features = Features::new(mesh).with_mapping(&actions.attribute_mapping);
+            if let Some(classes) = features.role_scalars(AttributeRole::Class) {
+                let mut particle_classes = actions.particle_classes.clone();
+                if particle_classes.discover(classes) {
+                    actions.particle_classes = particle_classes;
+                }
+            }
+            scanned.files.insert(file.clone());
+        }
+    }
+}
+
+/// The colormap, and the values at its ends, while frames are colored by a value. Or each
+/// shown class's color, while they're colored by class
+fn color_legend(
+    egui_context: Res<EguiContext>,
+    actions: Res<Actions>,
+    pool: Res<MeshPool>,
+) {
+    let coloring = match &pool.coloring {
+        Some(coloring) if actions.show_legend => coloring,
+        _ => return,
+    };
+    egui::Window::new("Legend")
+        .resizable(false)
+        .show(egui_context.ctx(), |ui| {
+            ui.label(coloring.label());
+            if coloring.by == ColorBy::Class {
+                for class in actions.particle_classes.classes.iter() {
+                    if !class.visible {
+                        continue;
+                    }
+                    ui.horizontal(|ui| {
+                        let (rect, _) =
+                            ui.allocate_exact_size(egui::vec2(16., 16.), egui::Sense::hover());
+                        let [r, g, b, _] = class.color.as_rgba_f32();
+                        ui.painter().rect_filled(
+                            rect,
+                            0.,
+                            egui::Color32::from_rgb(
+                                (r * 255.).round() as u8,
+                                (g * 255.).round() as u8,
+                                (b * 255.).round() as u8,
+                            ),
+                        );
+                        ui.label(class.name.as_str());
+                    });
+                }
+                return;
+            }
+            let (rect, _) = ui.allocate_exact_size(egui::vec2(200., 16.), egui::Sense::hover());
+            let steps = 64;
+            for step in 0..steps {
+                let [r, g, b] = coloring.colormap.sample((step as f32 + 0.5) / steps as f32);
+                let left = rect.left() + rect.width() * step as f32 / steps as f32;
+                let right = rect.left() + rect.width() * (step + 1) as f32 / steps as f32;
+                ui.painter().rect_filled(
+                    egui::Rect::from_min_max(
+                        egui::pos2(left, rect.top()),
+                        egui::pos2(right, rect.bottom()),
+                    ),
+                    0.,
+                    egui::Color32::from_rgb(
+                        (r * 255.).round() as u8,
+                        (g * 255.).round() as u8,
+                        (b * 255.).round() as u8,
+                    ),
+                );
+            }
+            ui.horizontal(|ui| {
+                ui.label(format!("{:.3}", coloring.min));
+                ui.label(format!("{:.3}", (coloring.min + coloring.max) / 2.));
+                ui.label(format!("{:.3}", coloring.max));
+            });
+        });
+}
+
 fn update_camera_system_transform(
     camera_system: &CameraSystem,
     camera_controller: &mut FpsCameraController,
     transform: &mut LookTransform,
     bevy_transform: &mut Transform,
-    load_iterator: &MidpointIterator<String>,
-    pool: &MeshPool,
+    current_frame: usize,
 ) {
     if camera_system.follow_camera {
-        if let Some(timeline_transform) = camera_system.enabled_timeline().and_then(|ctl| {
-            ctl.transform_at_frame(load_iterator.full_index_from_lod_index(pool.current_mesh_index))
-        }) {
+        if let Some(timeline_transform) = camera_system
+            .enabled_timeline()
+            .and_then(|ctl| ctl.transform_at_frame(current_frame))
+        {
             let camera_transform = CameraFrame::isometry_to_transform(timeline_transform);
             (*bevy_transform) = camera_transform;
 
@@ -137,32 +369,28 @@ fn camera_timeline_system(
     // time: Res<Time>,
 ) {
     // println!("camera-timeline-system");
+    let current_frame = current_full_index(&*loader, &*pool, &*fluid_assets);
     if camera_system.record_mode {
         // we need the highest LOD
         let load_manager = (&mut *loader).load_manager_mut();
         load_manager.reload(&*asset_server);
-        let load_iterator = &loader.load_manager().load_iterator;
 
         if let Some(timeline) = camera_system.enabled_timeline_mut() {
             if keyboard_input.just_pressed(KeyCode::C) && !keyboard_input.pressed(KeyCode::LControl)
             {
                 if let Ok((_, _, transform)) = transform_query.single_mut() {
-                    timeline.add_frame(
-                        load_iterator.full_index_from_lod_index(pool.current_mesh_index),
-                        *transform,
-                    );
+                    timeline.add_frame(current_frame, *transform);
                 }
             }
         }
     }
-    let load_iterator = &loader.load_manager().load_iterator;
 
     // fixme move to config_save_system
     if keyboard_input.pressed(KeyCode::LControl) {
         if keyboard_input.just_pressed(KeyCode::S) {
             if let Some(data_dir) = actions.datasets.selected_value() {
                 if let Ok(root) = std::env::current_dir() {
-                    let dir_path = root.join(data_dir);
+                    let dir_path = root.join(loader.dataset_dir(&data_dir));
                     println!("dir_path: {}", dir_path.to_string_lossy());
                     if let Ok(config) = ron::ser::to_string_pretty(&*actions, Default::default()) {
                         match std::fs::write(dir_path.join("mr-config.ron"), config) {
@@ -192,8 +420,8 @@ fn camera_timeline_system(
             if let Some(mesh) = meshes.get(current_mesh.1.clone()) {
                 if let Ok((_, mut transform, _)) = transform_query.single_mut() {
                     if let Some((eye, target)) = MeshLookAtEstimator::eye_target(mesh) {
-                        transform.eye = eye;
-                        transform.target = target;
+                        transform.eye = pool.dataset_transform.mul_vec3(eye);
+                        transform.target = pool.dataset_transform.mul_vec3(target);
                     }
                 }
             }
@@ -207,7 +435,7 @@ fn camera_timeline_system(
         visualization.despawn(&*camera_system, &mut commands);
         visualization.spawn(
             &*camera_system,
-            load_iterator.full_index_from_lod_index(pool.current_mesh_index),
+            current_frame,
             &mut commands,
             materials,
             meshes,
@@ -218,14 +446,24 @@ fn camera_timeline_system(
 
     if let Ok((mut controller, mut transform, mut bevy_transform)) = transform_query.single_mut() {
         // println!("updating camera");
-        let load_iterator = &loader.load_manager().load_iterator;
         update_camera_system_transform(
             &*camera_system,
             &mut *controller,
             &mut *transform,
             &mut *bevy_transform,
-            load_iterator,
-            &*pool,
+            current_frame,
         );
     }
 }
+
+/// Camera timelines are keyed on the index of the frame within every file of the dataset,
+/// whichever LOD (or prefetched frames) are loaded
+fn current_full_index(
+    loader: &GlobOrDirLoader,
+    pool: &MeshPool,
+    fluid_assets: &MeshAssets,
+) -> usize {
+    pool.current_mesh(fluid_assets)
+        .and_then(|(file, _)| loader.load_manager().load_iterator.full_index_of(file))
+        .unwrap_or(0)
+}
@@ -2,9 +2,21 @@ pub mod actions;
 pub mod asset_load_checker;
 pub mod background_meshes;
 pub mod camera;
+pub mod color_range;
+pub mod dataset_manifest;
+pub mod dataset_watcher;
+pub mod file_sequence;
+pub mod frame_cache;
+pub mod frame_times;
 pub mod glob_or_dir_loader;
 pub mod load_manager;
+pub mod lod_fixed_stride_iterator;
+pub mod lod_frame_list_iterator;
+pub mod lod_iterator;
 pub mod lod_midpoint_iterator;
+pub mod lod_tier_iterator;
+pub mod lod_window_iterator;
 pub mod mesh_aabb_estimator;
 pub mod mesh_lookat_estimator;
 pub mod mesh_pool;
+pub mod paraview_collection;
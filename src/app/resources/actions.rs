@@ -1,33 +1,103 @@
-use crate::support::loader_fu::render::PointRenderOptions;
+use crate::support::loader_fu::{
+    attributes::AttributeMapping,
+    classes::ParticleClasses,
+    colormap::{ColorBy, ColorRange, Colormap},
+    render::PointRenderOptions,
+};
 
 use super::{
     super::inspector::vec_as_dropdown::VecAsDropdown, background_meshes::BackgroundMeshes,
+    lod_iterator::LodOptions,
 };
 use bevy::prelude::*;
 use bevy_inspector_egui::Inspectable;
 use serde::*;
 
 #[derive(Inspectable, Debug, Serialize, Deserialize)]
+// configs saved before a field existed still load
+#[serde(default)]
 pub struct Actions {
     pub current_frame: usize,
+    /// Simulated time of the current frame, when the dataset has times
+    #[inspectable(label = "Simulation Time")]
+    pub current_time: f32,
     pub frame_direction: FrameDirection,
     #[inspectable(min = 0.0, max = 1.0, speed = 0.01)]
     pub advance_every: f32,
+    pub playback_mode: PlaybackMode,
+    /// Simulated seconds played per second, in SimulatedTime playback
+    #[inspectable(label = "Simulated Seconds per Second", min = 0.0001, speed = 0.01)]
+    pub realtime_ratio: f32,
     pub reset: bool,
     pub paused: bool,
     pub fluids_loaded: usize,
     pub fluids_loaded_percent: f32,
+    pub fluids_failed: usize,
+    /// Frames missing from the numbering of the dataset's files
+    pub missing_frames: usize,
+    #[inspectable(label = "Retry Failed Frames")]
+    pub retry_failed: bool,
+    /// When false, a failed frame holds the previous good frame on screen instead
+    pub skip_failed_frames: bool,
+    #[inspectable(label = "Max Frames in Memory (0 = unlimited)")]
+    pub cache_max_frames: usize,
+    #[inspectable(label = "Max MB in Memory (0 = unlimited)")]
+    pub cache_max_megabytes: usize,
+    pub frames_resident: usize,
+    #[inspectable(label = "# Frames to Prefetch at Full Resolution")]
+    pub prefetch_frames: usize,
+    pub megabytes_resident: f32,
     pub reload: bool,
+    /// Live mode, appends frames as a running simulation writes them
+    #[inspectable(label = "Watch for New Frames")]
+    pub watch_for_new_frames: bool,
+    /// Keeps the newest frame on screen as frames are added
+    #[inspectable(label = "Pin to Newest Frame")]
+    pub pin_to_newest: bool,
+    /// Reloads frames that are overwritten on disk, i.e. by re-running post-processing
+    #[inspectable(label = "Reload Changed Frames")]
+    pub reload_changed_frames: bool,
     pub fluid_color: Color,
+    /// Draws meshes with their own vertex colors (or materials, for glTF) instead of fluid_color
+    #[inspectable(label = "Use Colors from Files")]
+    pub use_file_colors: bool,
     pub particle_render_style: PointRenderOptions,
+    /// Merges the particles into a few meshes, rather than an entity each, so that many more of
+    /// them can be drawn
+    #[inspectable(label = "Batch Particles")]
+    pub batch_particles: bool,
+    /// Which of the frames' arrays are positions, velocities, densities..., blank to guess by name
+    #[inspectable(label = "Attribute Mapping")]
+    pub attribute_mapping: AttributeMapping,
+    /// Colors frames by a value per vertex through the colormap, instead of fluid_color
+    #[inspectable(label = "Color By")]
+    pub color_by: ColorBy,
+    /// The array colored by, when coloring by Scalar
+    #[inspectable(label = "Color Scalar")]
+    pub color_scalar: String,
+    pub colormap: Colormap,
+    /// The range the colormap spans, held across the sequence
+    #[inspectable(label = "Color Range")]
+    pub color_range: ColorRange,
+    #[inspectable(label = "Show Color Legend")]
+    pub show_legend: bool,
+    /// The color of each phase or object id particles are tagged with, and whether it's shown
+    #[inspectable(label = "Particle Classes")]
+    pub particle_classes: ParticleClasses,
     #[inspectable(min = 0.0, max = 5.0, speed = 0.01)]
     pub particle_radius: f32,
-    // #[inspectable(min = 100, max = 10000, speed = 10)]
+    /// Particles drawn at most, an entity each, a random sample of them when there are more
+    #[inspectable(label = "Max Particles Drawn", min = 1)]
     pub max_particles_render: usize,
+    /// Particles drawn at most when they are batched, which draws far more of them
+    #[inspectable(label = "Max Batched Particles Drawn", min = 1)]
+    pub max_batched_particles_render: usize,
     #[inspectable(min = 0.0, max = 1.0, speed = 0.01)]
     pub opacity: f32,
     #[inspectable(label = "# Frames to Initially Load")]
     pub initial_lod: usize,
+    #[inspectable(label = "Frames to Load at each LOD")]
+    pub lod_options: LodOptions,
     #[inspectable(label = "# of Frames to Load")]
     #[serde(skip)]
     pub load_number_of_frames: VecAsDropdown<usize>,
@@ -48,17 +118,34 @@ impl Default for Actions {
     fn default() -> Self {
         Self {
             current_frame: 0,
+            current_time: 0.,
             advance_every: 0.1,
+            playback_mode: Default::default(),
+            realtime_ratio: 1.0,
             // last_time_drawn: Instant::now(),
             paused: true,
             reset: false,
             frame_direction: Default::default(),
             fluids_loaded: 0,
             fluids_loaded_percent: 0.,
+            fluids_failed: 0,
+            missing_frames: 0,
+            retry_failed: false,
+            skip_failed_frames: true,
+            cache_max_frames: 0,
+            cache_max_megabytes: 0,
+            frames_resident: 0,
+            prefetch_frames: 10,
+            megabytes_resident: 0.,
             reload: false,
+            watch_for_new_frames: false,
+            pin_to_newest: false,
+            reload_changed_frames: true,
             fluid_color: Color::rgb(95. / 255., 133. / 255., 194. / 255.),
+            use_file_colors: false,
             opacity: 1.0,
             initial_lod: 100,
+            lod_options: LodOptions::default(),
             load_number_of_frames: VecAsDropdown::default(),
             datasets: VecAsDropdown::default(),
             current_file: String::from(""),
@@ -68,12 +155,32 @@ impl Default for Actions {
             material_roughness: 0.089,
             particle_radius: 0.05,
             particle_render_style: PointRenderOptions::Sphere,
+            batch_particles: true,
+            attribute_mapping: AttributeMapping::default(),
+            color_by: ColorBy::default(),
+            color_scalar: String::new(),
+            colormap: Colormap::default(),
+            color_range: ColorRange::default(),
+            show_legend: true,
+            particle_classes: ParticleClasses::default(),
             max_particles_render: 1000,
+            max_batched_particles_render: 1_000_000,
             focus_on_mesh: false,
         }
     }
 }
 
+impl Actions {
+    /// Particles sampled from each frame, whether or not they are batched
+    pub fn particle_sample_size(&self) -> usize {
+        if self.batch_particles {
+            self.max_batched_particles_render
+        } else {
+            self.max_particles_render
+        }
+    }
+}
+
 #[derive(Inspectable, Debug, Clone, Serialize, Deserialize)]
 pub enum FrameDirection {
     Forward,
@@ -86,6 +193,20 @@ impl Default for FrameDirection {
     }
 }
 
+#[derive(Inspectable, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PlaybackMode {
+    // Every frame is shown for `advance_every` seconds
+    FixedFrameRate,
+    // Frames are shown for their simulated timestep, scaled by `realtime_ratio`
+    SimulatedTime,
+}
+
+impl Default for PlaybackMode {
+    fn default() -> Self {
+        PlaybackMode::FixedFrameRate
+    }
+}
+
 pub struct State {
     pub spot_lights: Option<Vec<Entity>>,
     pub background_meshes: BackgroundMeshes,
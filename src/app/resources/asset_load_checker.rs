@@ -38,7 +38,14 @@ where
 
         let still_loading: Vec<HandleUntyped> = loading
             .iter()
-            .filter(|&h| !(LoadState::Loaded == server.get_load_state(h)))
+            .filter(|&h| match server.get_load_state(h) {
+                LoadState::Loaded => false,
+                LoadState::Failed => {
+                    warn!("Failed to load {:?}", server.get_handle_path(h));
+                    false
+                }
+                _ => true,
+            })
             .cloned()
             .collect();
         loading.clear();
@@ -9,6 +9,10 @@ pub struct BackgroundMeshes {
     loaded: Vec<Handle<Mesh>>,
     displayed: Vec<Handle<Mesh>>,
     m2mat: HashMap<Handle<Mesh>, Color>,
+    m2transform: HashMap<Handle<Mesh>, Transform>,
+    /// meshes from the dataset's manifest, removed when the dataset changes
+    from_dataset: Vec<Handle<Mesh>>,
+    entities: HashMap<Handle<Mesh>, Entity>,
     default_material_color: Color,
 }
 
@@ -26,15 +30,51 @@ impl BackgroundMeshes {
                 .entry(mesh.clone())
                 .or_insert(self.default_material_color);
             let material = materials.add(color.into());
-            commands.spawn_bundle(PbrBundle {
-                mesh: mesh.clone(),
-                material: material,
-                ..Default::default()
-            });
+            let transform = self.m2transform.get(mesh).cloned().unwrap_or_default();
+            let entity = commands
+                .spawn_bundle(PbrBundle {
+                    mesh: mesh.clone(),
+                    material: material,
+                    transform,
+                    ..Default::default()
+                })
+                .id();
+            self.entities.insert(mesh.clone(), entity);
         }
         self.displayed.extend(to_display);
     }
 
+    /// Loads a mesh listed in the dataset's manifest
+    pub fn load_for_dataset(
+        &mut self,
+        handle: HandleUntyped,
+        color: Color,
+        transform: Transform,
+    ) {
+        let mesh: Handle<Mesh> = handle.clone().typed();
+        self.m2mat.insert(mesh.clone(), color);
+        self.m2transform.insert(mesh.clone(), transform);
+        self.from_dataset.push(mesh);
+        self.loading.push(handle);
+    }
+
+    /// Despawns & forgets the meshes of the previous dataset's manifest
+    pub fn clear_dataset_meshes(
+        &mut self,
+        commands: &mut Commands,
+    ) {
+        for mesh in self.from_dataset.drain(..) {
+            if let Some(entity) = self.entities.remove(&mesh) {
+                commands.entity(entity).despawn_recursive();
+            }
+            self.loading.retain(|h| h.id != mesh.id);
+            self.loaded.retain(|l| *l != mesh);
+            self.displayed.retain(|d| *d != mesh);
+            self.m2mat.remove(&mesh);
+            self.m2transform.remove(&mesh);
+        }
+    }
+
     fn available_but_not_displayed_yet(&self) -> Vec<Handle<Mesh>> {
         self.loaded
             .iter()
@@ -61,6 +101,9 @@ impl Default for BackgroundMeshes {
             loaded: Vec::new(),
             displayed: Vec::new(),
             m2mat: HashMap::new(),
+            m2transform: HashMap::new(),
+            from_dataset: Vec::new(),
+            entities: HashMap::new(),
             default_material_color: Color::rgb(81. / 255., 41. / 255., 0.),
         }
     }
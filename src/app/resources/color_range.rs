@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use bevy::prelude::Transform;
+
+use crate::support::loader_fu::{
+    attributes::AttributeMapping,
+    colormap::{ColorBy, ColorRange, RangeMode},
+};
+
+// Color Range
+// The range frames are colored over, held across the whole sequence rather than per frame, so a
+// value is the same color in every frame. Each frame's values are summarized once, when it has
+// loaded, to its smallest and largest value and an evenly strided sample for percentiles
+
+/// Values of each frame kept for percentiles
+const SAMPLE_VALUES: usize = 4096;
+
+/// What the values are, the summaries are thrown away when it changes
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorSource {
+    /// frames are keyed by file name, which another dataset may share
+    pub dataset: Option<String>,
+    pub by: ColorBy,
+    pub scalar: String,
+    pub mapping: AttributeMapping,
+    /// heights are in the dataset's transform
+    pub transform: Transform,
+}
+
+struct FrameSummary {
+    min: f32,
+    max: f32,
+    sample: Vec<f32>,
+}
+
+#[derive(Default)]
+pub struct ColorRangeTracker {
+    source: Option<ColorSource>,
+    frames: HashMap<String, FrameSummary>,
+    /// the range last asked for, and what it came to, until frames are inserted or forgotten
+    cached: Option<(ColorRange, Option<(f32, f32)>)>,
+}
+
+impl ColorRangeTracker {
+    /// Forgets every frame's values if they were of another source, true if they were
+    pub fn set_source(
+        &mut self,
+        source: ColorSource,
+    ) -> bool {
+        if self.source.as_ref() == Some(&source) {
+            return false;
+        }
+        self.source = Some(source);
+        self.frames.clear();
+        self.cached = None;
+        true
+    }
+
+    pub fn contains(
+        &self,
+        file: &str,
+    ) -> bool {
+        self.frames.contains_key(file)
+    }
+
+    /// Summarizes a frame's values, ignoring NaNs and infinities. Frames without any are left out
+    pub fn insert(
+        &mut self,
+        file: String,
+        values: &[f32],
+    ) {
+        let finite: Vec<f32> = values.iter().copied().filter(|v| v.is_finite()).collect();
+        if finite.is_empty() {
+            return;
+        }
+        let (min, max) = finite.iter().fold((f32::MAX, f32::MIN), |(min, max), &v| {
+            (min.min(v), max.max(v))
+        });
+        let stride = (finite.len() / SAMPLE_VALUES).max(1);
+        let sample = finite.into_iter().step_by(stride).collect();
+        self.frames.insert(file, FrameSummary { min, max, sample });
+        self.cached = None;
+    }
+
+    /// The range to color over, None until a frame has been summarized (unless it is fixed).
+    /// Only worked out again once frames have been inserted, or the range is changed
+    pub fn range(
+        &mut self,
+        range: &ColorRange,
+    ) -> Option<(f32, f32)> {
+        match &self.cached {
+            Some((cached_range, values)) if cached_range == range => *values,
+            _ => {
+                let values = self.compute_range(range);
+                self.cached = Some((range.clone(), values));
+                values
+            }
+        }
+    }
+
+    fn compute_range(
+        &self,
+        range: &ColorRange,
+    ) -> Option<(f32, f32)> {
+        match range.mode {
+            RangeMode::Fixed => Some((range.min, range.max)),
+            RangeMode::Auto => self.frames.values().fold(None, |acc, frame| match acc {
+                Some((min, max)) => Some((frame.min.min(min), frame.max.max(max))),
+                None => Some((frame.min, frame.max)),
+            }),
+            RangeMode::Percentile => {
+                let mut sample: Vec<f32> = self
+                    .frames
+                    .values()
+                    .flat_map(|frame| frame.sample.iter().copied())
+                    .collect();
+                if sample.is_empty() {
+                    return None;
+                }
+                sample.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                let at = |percentile: f32| {
+                    let rank = percentile.max(0.).min(100.) / 100. * (sample.len() - 1) as f32;
+                    sample[rank.round() as usize]
+                };
+                let (low, high) = (at(range.low_percentile), at(range.high_percentile));
+                Some((low.min(high), low.max(high)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range() {
+        let mut tracker = ColorRangeTracker::default();
+        let source = ColorSource {
+            dataset: None,
+            by: ColorBy::Speed,
+            scalar: String::new(),
+            mapping: AttributeMapping::default(),
+            transform: Transform::identity(),
+        };
+        assert!(tracker.set_source(source.clone()));
+        assert_eq!(tracker.range(&ColorRange::default()), None);
+
+        let values: Vec<f32> = (0..=100).map(|v| v as f32).collect();
+        tracker.insert(String::from("fluid_0.ply"), &values);
+        tracker.insert(String::from("fluid_1.ply"), &[-5., f32::NAN, 50.]);
+        assert!(tracker.contains("fluid_1.ply"));
+        assert_eq!(tracker.range(&ColorRange::default()), Some((-5., 100.)));
+        // the cached range is worked out again for new frames
+        tracker.insert(String::from("fluid_2.ply"), &[200.]);
+        assert_eq!(tracker.range(&ColorRange::default()), Some((-5., 200.)));
+
+        let percentile = ColorRange {
+            mode: RangeMode::Percentile,
+            low_percentile: 10.,
+            high_percentile: 90.,
+            ..Default::default()
+        };
+        let (low, high) = tracker.range(&percentile).unwrap();
+        assert!(low > -5. && low < 15.);
+        assert!(high > 85. && high < 100.);
+
+        let fixed = ColorRange {
+            mode: RangeMode::Fixed,
+            min: 1.,
+            max: 2.,
+            ..Default::default()
+        };
+        assert_eq!(tracker.range(&fixed), Some((1., 2.)));
+
+        // the same source keeps its frames, another forgets them
+        assert!(!tracker.set_source(source.clone()));
+        assert!(tracker.contains("fluid_0.ply"));
+        assert!(tracker.set_source(ColorSource {
+            by: ColorBy::Height,
+            ..source
+        }));
+        assert!(!tracker.contains("fluid_0.ply"));
+    }
+}
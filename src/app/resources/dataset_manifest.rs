@@ -0,0 +1,314 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    f32::consts::FRAC_PI_2,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use bevy::{asset::LoadContext, prelude::*};
+use bevy_inspector_egui::Inspectable;
+use serde::*;
+
+use super::file_sequence::compare_frames;
+use crate::support::loaders::{gltf::GltfMeshes, point_cloud::ColumnMapping};
+
+pub const MANIFEST_FILE: &str = "mr-dataset.ron";
+
+// Dataset Manifest
+// An optional mr-dataset.ron in a dataset directory, describing the dataset so it opens the same
+// for everyone it is shared with:
+// ```
+// (
+//     name: "dam break",
+//     files: ["fluid_0000.ply", "fluid_0001.ply"], // or pattern: Some("fluid_*.ply"),
+//     times: [0.0, 0.0125],
+//     up_axis: Z,
+//     unit_scale: 0.001,
+//     transform: (translation: (0.0, 1.0, 0.0), rotation: (0.0, 90.0, 0.0), scale: 1.0),
+//     background_meshes: [(file: "tank.obj", color: Rgba(red: 0.3, green: 0.3, blue: 0.3, alpha: 1.0))],
+//     metadata: {"viscosity": 0.01, "solver": "dfsph"},
+//     columns: Some((position: Some(("px", "py", "pz")))), // of .csv/.xyz/.pts frames
+//     gltf_meshes: All, // of .gltf/.glb frames, the first mesh (Primary) by default
+// )
+// ```
+// Paths are relative to the manifest's directory
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatasetManifest {
+    pub name: String,
+    pub description: String,
+    /// Files of the sequence, in frame order
+    pub files: Vec<String>,
+    /// A glob of the files of the sequence, when `files` is empty
+    pub pattern: Option<String>,
+    /// Simulated time of each frame, in frame order
+    pub times: Vec<f64>,
+    pub up_axis: UpAxis,
+    /// i.e. 0.001 for a dataset in millimetres
+    pub unit_scale: f32,
+    pub transform: ManifestTransform,
+    pub background_meshes: Vec<ManifestMesh>,
+    /// Solver parameters, or anything else worth sharing with the dataset
+    pub metadata: BTreeMap<String, ron::Value>,
+    /// Which columns of text point clouds are what, read by their loader
+    pub columns: Option<ColumnMapping>,
+    /// Which meshes of glTF frames are shown, read by their loader
+    pub gltf_meshes: GltfMeshes,
+}
+
+impl Default for DatasetManifest {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            description: String::new(),
+            files: Vec::new(),
+            pattern: None,
+            times: Vec::new(),
+            up_axis: UpAxis::Y,
+            unit_scale: 1.0,
+            transform: ManifestTransform::default(),
+            background_meshes: Vec::new(),
+            metadata: BTreeMap::new(),
+            columns: None,
+            gltf_meshes: GltfMeshes::default(),
+        }
+    }
+}
+
+#[derive(Inspectable, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum UpAxis {
+    X,
+    Y,
+    Z,
+}
+
+impl Default for UpAxis {
+    fn default() -> Self {
+        UpAxis::Y
+    }
+}
+
+impl UpAxis {
+    /// Rotates this axis onto Y, which is up in bevy
+    pub fn rotation(&self) -> Quat {
+        match self {
+            UpAxis::X => Quat::from_rotation_z(FRAC_PI_2),
+            UpAxis::Y => Quat::IDENTITY,
+            UpAxis::Z => Quat::from_rotation_x(-FRAC_PI_2),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ManifestTransform {
+    pub translation: Vec3,
+    /// Euler angles in degrees, applied in X, Y, Z order
+    pub rotation: Vec3,
+    pub scale: f32,
+}
+
+impl Default for ManifestTransform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+            scale: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestMesh {
+    pub file: String,
+    #[serde(default = "default_mesh_color")]
+    pub color: Color,
+}
+
+fn default_mesh_color() -> Color {
+    Color::rgb(81. / 255., 41. / 255., 0.)
+}
+
+#[derive(Debug)]
+pub enum ManifestError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, ron::Error),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            ManifestError::Io(path, e) => write!(f, "couldn't read {}: {}", path.display(), e),
+            ManifestError::Parse(path, e) => write!(f, "couldn't parse {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+impl DatasetManifest {
+    /// The manifest in `dir`, None when there isn't one
+    pub fn read(dir: &Path) -> Option<Result<Self, ManifestError>> {
+        let path = dir.join(MANIFEST_FILE);
+        if !path.is_file() {
+            return None;
+        }
+        let manifest = std::fs::read_to_string(&path)
+            .map_err(|e| ManifestError::Io(path.clone(), e))
+            .and_then(|contents| {
+                ron::from_str(&contents).map_err(|e| ManifestError::Parse(path.clone(), e))
+            });
+        Some(manifest)
+    }
+
+    /// The manifest beside a frame being loaded, for the options of its format. The default when
+    /// there is none, or it can't be parsed
+    pub async fn beside(load_context: &LoadContext<'_>) -> Self {
+        let path = match load_context.path().parent() {
+            Some(dir) => dir.join(MANIFEST_FILE),
+            None => return Self::default(),
+        };
+        match load_context.read_asset_bytes(&path).await {
+            Ok(bytes) => ron::de::from_bytes(&bytes).unwrap_or_else(|e| {
+                warn!("Ignoring {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// The files of the sequence, in the order listed, or in frame order when matched by the
+    /// pattern. None when the manifest doesn't say
+    pub fn files(
+        &self,
+        dir: &Path,
+    ) -> Option<Vec<PathBuf>> {
+        if !self.files.is_empty() {
+            return Some(self.files.iter().map(|f| dir.join(f)).collect());
+        }
+        let pattern = self.pattern.as_ref()?;
+        let pattern = dir.join(pattern).to_string_lossy().to_string();
+        match glob::glob(&pattern) {
+            Ok(paths) => {
+                let mut files: Vec<PathBuf> = paths.filter_map(|p| p.ok()).collect();
+                files.sort_by(|a, b| compare_frames(&a.to_string_lossy(), &b.to_string_lossy()));
+                Some(files)
+            }
+            Err(e) => {
+                warn!("Bad pattern {} in {}: {}", pattern, MANIFEST_FILE, e);
+                None
+            }
+        }
+    }
+
+    /// `times` keyed by file, `files` being in frame order
+    pub fn frame_times(
+        &self,
+        files: &[String],
+    ) -> HashMap<String, f64> {
+        files
+            .iter()
+            .cloned()
+            .zip(self.times.iter().copied())
+            .collect()
+    }
+
+    /// Up axis, then unit scale, then the global transform
+    pub fn transform(&self) -> Transform {
+        let t = &self.transform;
+        let global = Transform {
+            translation: t.translation,
+            rotation: Quat::from_rotation_x(t.rotation.x.to_radians())
+                * Quat::from_rotation_y(t.rotation.y.to_radians())
+                * Quat::from_rotation_z(t.rotation.z.to_radians()),
+            scale: Vec3::splat(t.scale),
+        };
+        let local = Transform {
+            rotation: self.up_axis.rotation(),
+            scale: Vec3::splat(self.unit_scale),
+            ..Default::default()
+        };
+        global * local
+    }
+}
+
+/// The manifest of the chosen dataset, for browsing in the inspector
+#[derive(Inspectable, Debug, Default)]
+pub struct DatasetInfo {
+    pub name: String,
+    pub description: String,
+    pub up_axis: UpAxis,
+    pub unit_scale: f32,
+    /// key: value
+    pub metadata: Vec<String>,
+    /// The per point arrays of the frame on screen, with the role each plays
+    pub point_arrays: Vec<String>,
+}
+
+impl From<&DatasetManifest> for DatasetInfo {
+    fn from(manifest: &DatasetManifest) -> Self {
+        Self {
+            name: manifest.name.clone(),
+            description: manifest.description.clone(),
+            up_axis: manifest.up_axis,
+            unit_scale: manifest.unit_scale,
+            metadata: manifest
+                .metadata
+                .iter()
+                .map(|(key, value)| {
+                    let value = ron::to_string(value).unwrap_or_else(|e| e.to_string());
+                    format!("{}: {}", key, value)
+                })
+                .collect(),
+            point_arrays: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let manifest: DatasetManifest = ron::from_str(
+            r#"(
+                name: "dam break",
+                files: ["fluid_0.ply", "fluid_1.ply"],
+                times: [0.0, 0.5],
+                up_axis: Z,
+                unit_scale: 0.001,
+                metadata: {"viscosity": 0.01, "solver": "dfsph"},
+            )"#,
+        )
+        .unwrap();
+        assert_eq!(manifest.up_axis, UpAxis::Z);
+        assert_eq!(manifest.transform.scale, 1.0);
+        assert_eq!(
+            manifest.files(Path::new("run")),
+            Some(vec![
+                PathBuf::from("run/fluid_0.ply"),
+                PathBuf::from("run/fluid_1.ply")
+            ])
+        );
+        let times = manifest.frame_times(&[String::from("a"), String::from("b")]);
+        assert_eq!(times.get("b"), Some(&0.5));
+        let info = DatasetInfo::from(&manifest);
+        assert_eq!(info.metadata.len(), 2);
+    }
+
+    #[test]
+    fn test_z_up_transform() {
+        let manifest = DatasetManifest {
+            up_axis: UpAxis::Z,
+            unit_scale: 0.001,
+            ..Default::default()
+        };
+        let up = manifest.transform().mul_vec3(Vec3::new(0., 0., 1000.));
+        assert!((up - Vec3::Y).length() < 1e-5);
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// How long notify waits for a file's events to stop, before telling us about it
+const DEBOUNCE: Duration = Duration::from_millis(500);
+/// How long a new file's size has to stay the same, before it's considered fully written
+const SETTLE: Duration = Duration::from_secs(1);
+
+/// Watches a dataset's directory for frames written by a running simulation
+pub struct DatasetWatcher {
+    dir: PathBuf,
+    /// held, as dropping it stops the watch
+    _watcher: Mutex<RecommendedWatcher>,
+    events: Mutex<Receiver<DebouncedEvent>>,
+    settling: Mutex<Settling>,
+}
+
+impl DatasetWatcher {
+    pub fn new(dir: &Path) -> notify::Result<Self> {
+        let (sender, events) = channel();
+        let mut watcher = watcher(sender, DEBOUNCE)?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            _watcher: Mutex::new(watcher),
+            events: Mutex::new(events),
+            settling: Mutex::new(Settling::default()),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Files that have been created or written to, and have since stopped changing.
+    /// Paths are relative to the watched dir as it was given, not absolute as notify gives them
+    pub fn ready_files(&self) -> Vec<PathBuf> {
+        let mut settling = self.settling.lock().unwrap();
+        if let Ok(events) = self.events.lock() {
+            for event in events.try_iter() {
+                match event {
+                    DebouncedEvent::Create(path)
+                    | DebouncedEvent::Write(path)
+                    | DebouncedEvent::Rename(_, path) => {
+                        if let Some(file_name) = path.file_name() {
+                            settling.observe(self.dir.join(file_name));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        settling.ready(Instant::now(), SETTLE)
+    }
+}
+
+/// Files, their size when last seen, and when it last changed
+#[derive(Default, Debug)]
+pub struct Settling {
+    files: HashMap<PathBuf, (u64, Instant)>,
+}
+
+impl Settling {
+    pub fn observe(
+        &mut self,
+        path: PathBuf,
+    ) {
+        self.files.insert(path, (u64::MAX, Instant::now()));
+    }
+
+    /// Files whose size hasn't changed for `settle`
+    pub fn ready(
+        &mut self,
+        now: Instant,
+        settle: Duration,
+    ) -> Vec<PathBuf> {
+        let mut ready = Vec::new();
+        self.files.retain(|path, (size, since)| {
+            let current_size = match std::fs::metadata(path) {
+                Ok(metadata) if metadata.is_file() => metadata.len(),
+                // removed, or a directory
+                _ => return false,
+            };
+            if current_size != *size {
+                *size = current_size;
+                *since = now;
+                true
+            } else if now.duration_since(*since) >= settle {
+                ready.push(path.clone());
+                false
+            } else {
+                true
+            }
+        });
+        ready.sort();
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ready_once_size_settles() {
+        let path = std::env::temp_dir().join("mesh-ripper-settling-test.ply");
+        std::fs::write(&path, "ply").unwrap();
+        let settle = Duration::from_secs(1);
+        let start = Instant::now();
+
+        let mut settling = Settling::default();
+        settling.observe(path.clone());
+        assert!(settling.ready(start, settle).is_empty());
+
+        // still growing
+        std::fs::write(&path, "ply\nformat").unwrap();
+        assert!(settling
+            .ready(start + Duration::from_millis(900), settle)
+            .is_empty());
+        assert!(settling
+            .ready(start + Duration::from_millis(1500), settle)
+            .is_empty());
+
+        assert_eq!(
+            settling.ready(start + Duration::from_millis(2000), settle),
+            vec![path.clone()]
+        );
+        assert!(settling.files.is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::support::compression::strip_compression;
+
+// File Sequence
+// Groups the files of a directory by their name pattern, prefix + frame number + suffix, so
+// interleaved series in the same directory play as separate sequences:
+// ```
+// fluid_0001.ply, boundary_0001.ply, fluid_0002.ply => fluid_*.ply [1, 2], boundary_*.ply [1]
+// ```
+// The frame number is the last run of digits before the extension. Files without one are each a
+// sequence of their own. Compression suffixes are ignored, fluid_0001.ply.gz is a frame of
+// fluid_*.ply
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileSequence {
+    pub prefix: String,
+    pub suffix: String,
+    pub numbered: bool,
+    /// (frame number, path), sorted by frame number
+    pub frames: Vec<(u64, PathBuf)>,
+}
+
+impl FileSequence {
+    /// i.e. fluid_*.ply
+    pub fn name(&self) -> String {
+        if self.numbered {
+            format!("{}*{}", self.prefix, self.suffix)
+        } else {
+            self.prefix.clone()
+        }
+    }
+
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.frames.iter().map(|(_, path)| path.clone()).collect()
+    }
+
+    /// The usual difference between frame numbers, so a sequence saved every 10th frame has no gaps
+    pub fn step(&self) -> u64 {
+        let mut counts: HashMap<u64, usize> = HashMap::new();
+        for pair in self.frames.windows(2) {
+            let diff = pair[1].0 - pair[0].0;
+            if diff > 0 {
+                *counts.entry(diff).or_default() += 1;
+            }
+        }
+        counts
+            .into_iter()
+            .max_by_key(|&(diff, count)| (count, std::cmp::Reverse(diff)))
+            .map_or(1, |(diff, _)| diff)
+    }
+
+    /// Inclusive ranges of frame numbers missing from the sequence
+    pub fn gaps(&self) -> Vec<(u64, u64)> {
+        let step = self.step();
+        self.frames
+            .windows(2)
+            .filter(|pair| pair[1].0 - pair[0].0 > step)
+            .map(|pair| (pair[0].0 + step, pair[1].0 - step))
+            .collect()
+    }
+
+    pub fn missing_frames(&self) -> usize {
+        let step = self.step();
+        self.gaps()
+            .iter()
+            .map(|(from, to)| ((to - from) / step + 1) as usize)
+            .sum()
+    }
+}
+
+/// Splits a file name into (prefix, frame number, suffix). The frame number may be a decimal, as
+/// in fluid_t0.01250.ply, for solvers that name frames by simulated time
+pub fn split_frame_number(file_name: &str) -> Option<(&str, &str, &str)> {
+    let uncompressed = strip_compression(file_name);
+    let stem_end = uncompressed
+        .rfind('.')
+        .unwrap_or_else(|| uncompressed.len());
+    let stem = &file_name[..stem_end];
+    let digits_end = stem.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start_of_run = |end: usize| {
+        stem[..end]
+            .rfind(|c: char| !c.is_ascii_digit())
+            .map_or(0, |i| i + 1)
+    };
+    let mut digits_start = start_of_run(digits_end);
+    if digits_start >= 2
+        && stem[..digits_start].ends_with('.')
+        && stem[..digits_start - 1].ends_with(|c: char| c.is_ascii_digit())
+    {
+        digits_start = start_of_run(digits_start - 1);
+    }
+    Some((
+        &file_name[..digits_start],
+        &file_name[digits_start..digits_end],
+        &file_name[digits_end..],
+    ))
+}
+
+/// The name of the sequence a file belongs to, i.e. fluid_0012.ply => fluid_*.ply
+pub fn sequence_name(file_name: &str) -> String {
+    match split_frame_number(file_name) {
+        Some((prefix, _, suffix)) => format!("{}*{}", prefix, strip_compression(suffix)),
+        None => file_name.to_string(),
+    }
+}
+
+/// Orders files by sequence, then by frame number as a FileSequence does, so fluid_t0.0125.ply
+/// comes before fluid_t0.1.ply, which it doesn't in alphanumeric order
+pub fn compare_frames(
+    a: &str,
+    b: &str,
+) -> Ordering {
+    let (sequence_a, number_a) = frame_key(a);
+    let (sequence_b, number_b) = frame_key(b);
+    alphanumeric_sort::compare_str(&sequence_a, &sequence_b)
+        .then_with(|| number_a.partial_cmp(&number_b).unwrap_or(Ordering::Equal))
+        .then_with(|| alphanumeric_sort::compare_str(a, b))
+}
+
+/// i.e. (run/fluid_*.ply, 12) for run/fluid_0012.ply
+fn frame_key(file: &str) -> (String, f64) {
+    let path = Path::new(file);
+    let file_name = match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => return (file.to_string(), 0.),
+    };
+    let number = split_frame_number(&file_name)
+        .and_then(|(_, number, _)| number.parse().ok())
+        .unwrap_or(0.);
+    let sequence = path.with_file_name(sequence_name(&file_name));
+    (sequence.to_string_lossy().to_string(), number)
+}
+
+/// Groups `paths` into sequences, sorted by name
+pub fn detect_sequences<I>(paths: I) -> Vec<FileSequence>
+where
+    I: IntoIterator<Item = PathBuf>,
+{
+    let mut sequences: BTreeMap<String, (FileSequence, Vec<(String, PathBuf)>)> = BTreeMap::new();
+    for path in paths {
+        let file_name = match path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => continue,
+        };
+        let (prefix, number, suffix, numbered) = match split_frame_number(&file_name) {
+            Some((prefix, number, suffix)) => (
+                prefix.to_string(),
+                number.to_string(),
+                strip_compression(suffix).to_string(),
+                true,
+            ),
+            None => (file_name.clone(), String::new(), String::new(), false),
+        };
+        let sequence = FileSequence {
+            prefix,
+            suffix,
+            numbered,
+            frames: Vec::new(),
+        };
+        sequences
+            .entry(sequence.name())
+            .or_insert((sequence, Vec::new()))
+            .1
+            .push((number, path));
+    }
+    sequences
+        .into_iter()
+        .map(|(_, (mut sequence, numbers))| {
+            sequence.frames = number_frames(numbers);
+            sequence
+        })
+        .collect()
+}
+
+/// Integer frame numbers are kept, decimal ones (times) are numbered in order
+fn number_frames(numbers: Vec<(String, PathBuf)>) -> Vec<(u64, PathBuf)> {
+    let mut frames: Vec<(u64, PathBuf)>;
+    if numbers.iter().all(|(n, _)| n.parse::<u64>().is_ok()) {
+        frames = numbers
+            .into_iter()
+            .map(|(n, path)| (n.parse().unwrap(), path))
+            .collect();
+        frames.sort();
+    } else {
+        let mut times: Vec<(f64, PathBuf)> = numbers
+            .into_iter()
+            .map(|(n, path)| (n.parse().unwrap_or(0.), path))
+            .collect();
+        times.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        frames = times
+            .into_iter()
+            .enumerate()
+            .map(|(i, (_, path))| (i as u64, path))
+            .collect();
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(|n| PathBuf::from("run").join(n)).collect()
+    }
+
+    #[test]
+    fn test_split_frame_number() {
+        assert_eq!(
+            split_frame_number("fluid_0012.ply"),
+            Some(("fluid_", "0012", ".ply"))
+        );
+        assert_eq!(
+            split_frame_number("data.0003.vtk"),
+            Some(("data.", "0003", ".vtk"))
+        );
+        assert_eq!(split_frame_number("42"), Some(("", "42", "")));
+        assert_eq!(
+            split_frame_number("fluid_t0.01250.ply"),
+            Some(("fluid_t", "0.01250", ".ply"))
+        );
+        assert_eq!(split_frame_number("mesh.obj"), None);
+        assert_eq!(sequence_name("fluid_0012.ply"), "fluid_*.ply");
+    }
+
+    #[test]
+    fn test_interleaved_sequences() {
+        let sequences = detect_sequences(paths(&[
+            "fluid_0002.ply",
+            "boundary_0001.ply",
+            "fluid_0001.ply",
+            "particles_0001.ply",
+            "fluid_0010.ply",
+            "scene.obj",
+        ]));
+        let names: Vec<String> = sequences.iter().map(|s| s.name()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "boundary_*.ply",
+                "fluid_*.ply",
+                "particles_*.ply",
+                "scene.obj"
+            ]
+        );
+        assert_eq!(
+            sequences[1].paths(),
+            paths(&["fluid_0001.ply", "fluid_0002.ply", "fluid_0010.ply"])
+        );
+    }
+
+    #[test]
+    fn test_compressed_frames_are_one_sequence() {
+        let sequences = detect_sequences(paths(&[
+            "fluid_0002.ply.gz",
+            "fluid_0001.ply",
+            "fluid_0003.ply.zst",
+        ]));
+        assert_eq!(sequences.len(), 1);
+        assert_eq!(sequences[0].name(), "fluid_*.ply");
+        assert_eq!(
+            sequences[0].paths(),
+            paths(&["fluid_0001.ply", "fluid_0002.ply.gz", "fluid_0003.ply.zst"])
+        );
+        assert_eq!(sequence_name("fluid_0012.vtk.xz"), "fluid_*.vtk");
+    }
+
+    #[test]
+    fn test_gaps() {
+        let sequences = detect_sequences(paths(&[
+            "f_0.ply", "f_1.ply", "f_2.ply", "f_5.ply", "f_6.ply", "f_8.ply",
+        ]));
+        assert_eq!(sequences[0].gaps(), vec![(3, 4), (7, 7)]);
+        assert_eq!(sequences[0].missing_frames(), 3);
+
+        // saved every 10th frame
+        let sequences = detect_sequences(paths(&["f_0.ply", "f_10.ply", "f_20.ply", "f_50.ply"]));
+        assert_eq!(sequences[0].gaps(), vec![(30, 40)]);
+        assert_eq!(sequences[0].missing_frames(), 2);
+    }
+
+    #[test]
+    fn test_decimal_times_are_one_sequence() {
+        let sequences = detect_sequences(paths(&[
+            "fluid_t1.00000.ply",
+            "fluid_t0.01250.ply",
+            "fluid_t0.50000.ply",
+        ]));
+        assert_eq!(sequences.len(), 1);
+        assert_eq!(
+            sequences[0].paths(),
+            paths(&[
+                "fluid_t0.01250.ply",
+                "fluid_t0.50000.ply",
+                "fluid_t1.00000.ply"
+            ])
+        );
+        assert!(sequences[0].gaps().is_empty());
+    }
+
+    #[test]
+    fn test_compare_frames() {
+        let mut files = vec![
+            "run/fluid_t0.1.ply",
+            "run/boundary_t0.1.ply",
+            "run/fluid_t0.0125.ply",
+            "run/fluid_t0.05.ply.gz",
+            "run/fluid_t1.ply",
+        ];
+        files.sort_by(|a, b| compare_frames(a, b));
+        assert_eq!(
+            files,
+            [
+                "run/boundary_t0.1.ply",
+                "run/fluid_t0.0125.ply",
+                "run/fluid_t0.05.ply.gz",
+                "run/fluid_t0.1.ply",
+                "run/fluid_t1.ply",
+            ]
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+
+use bevy::{prelude::Mesh, render::mesh::Indices};
+
+use crate::support::loader_fu::features::attribute_names;
+
+/// How much of a dataset we allow to be resident at once, `None` meaning unlimited
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameBudget {
+    pub max_frames: Option<usize>,
+    pub max_megabytes: Option<usize>,
+    /// frames this close to the current frame are never evicted, and are reloaded if they were
+    pub resident_radius: usize,
+}
+
+impl Default for FrameBudget {
+    fn default() -> Self {
+        Self {
+            max_frames: None,
+            max_megabytes: None,
+            resident_radius: 10,
+        }
+    }
+}
+
+impl FrameBudget {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_frames.is_none() && self.max_megabytes.is_none()
+    }
+
+    /// The radius can't be larger than the budget allows
+    pub fn radius(&self) -> usize {
+        match self.max_frames {
+            Some(max_frames) => self.resident_radius.min(max_frames.saturating_sub(1) / 2),
+            None => self.resident_radius,
+        }
+    }
+}
+
+/// Keeps track of when each frame was last displayed, and how big it is, and decides which
+/// frames to evict when over budget.
+/// Frames are evicted least recently used first, frames never displayed being the furthest
+/// from the current frame first
+#[derive(Clone, Debug, Default)]
+pub struct FrameCache {
+    pub budget: FrameBudget,
+    tick: u64,
+    last_used: HashMap<String, u64>,
+    sizes: HashMap<String, usize>,
+}
+
+impl FrameCache {
+    pub fn new(budget: FrameBudget) -> Self {
+        Self {
+            budget,
+            ..Default::default()
+        }
+    }
+
+    pub fn touch(
+        &mut self,
+        file: &str,
+    ) {
+        self.tick += 1;
+        self.last_used.insert(file.to_string(), self.tick);
+    }
+
+    pub fn has_size(
+        &self,
+        file: &str,
+    ) -> bool {
+        self.sizes.contains_key(file)
+    }
+
+    pub fn record_size(
+        &mut self,
+        file: &str,
+        bytes: usize,
+    ) {
+        self.sizes.insert(file.to_string(), bytes);
+    }
+
+    pub fn forget(
+        &mut self,
+        file: &str,
+    ) {
+        self.last_used.remove(file);
+        self.sizes.remove(file);
+    }
+
+    pub fn clear(&mut self) {
+        self.last_used.clear();
+        self.sizes.clear();
+    }
+
+    pub fn resident_bytes(
+        &self,
+        resident: &[String],
+    ) -> usize {
+        resident.iter().filter_map(|f| self.sizes.get(f)).sum()
+    }
+
+    /// `resident` is in playback order, `current` indexes into it
+    pub fn to_evict(
+        &self,
+        resident: &[String],
+        current: Option<usize>,
+    ) -> Vec<String> {
+        self.to_evict_keeping(resident, current, &[])
+    }
+
+    /// As `to_evict`, never evicting the frames in `keep` either
+    pub fn to_evict_keeping(
+        &self,
+        resident: &[String],
+        current: Option<usize>,
+        keep: &[String],
+    ) -> Vec<String> {
+        if self.budget.is_unlimited() {
+            return Vec::new();
+        }
+        let radius = self.budget.radius();
+        let distance = |idx: usize| match current {
+            Some(current) => (idx as isize - current as isize).abs() as usize,
+            None => idx,
+        };
+
+        let mut candidates: Vec<(usize, &String)> = resident
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| current.is_none() || distance(*idx) > radius)
+            .filter(|(_, file)| !keep.contains(file))
+            .collect();
+        candidates.sort_by(|(a_idx, a), (b_idx, b)| {
+            let a_used = self.last_used.get(*a).unwrap_or(&0);
+            let b_used = self.last_used.get(*b).unwrap_or(&0);
+            a_used
+                .cmp(b_used)
+                .then(distance(*b_idx).cmp(&distance(*a_idx)))
+        });
+
+        let max_frames = self.budget.max_frames.unwrap_or(usize::MAX);
+        let max_bytes = self
+            .budget
+            .max_megabytes
+            .map(|mb| mb * 1024 * 1024)
+            .unwrap_or(usize::MAX);
+
+        let mut frames = resident.len();
+        let mut bytes = self.resident_bytes(resident);
+        let mut evict = Vec::new();
+        for (_, file) in candidates {
+            if frames <= max_frames && bytes <= max_bytes {
+                break;
+            }
+            frames -= 1;
+            bytes -= self.sizes.get(file).unwrap_or(&0);
+            evict.push(file.clone());
+        }
+        evict
+    }
+
+    /// Approximate memory used by a mesh, in bytes, point data arrays included
+    pub fn mesh_bytes(mesh: &Mesh) -> usize {
+        let attributes: usize = attribute_names(mesh)
+            .into_iter()
+            .filter_map(|name| mesh.attribute(name))
+            .map(|values| values.get_bytes().len())
+            .sum();
+        let indices = match mesh.indices() {
+            Some(Indices::U16(indices)) => indices.len() * 2,
+            Some(Indices::U32(indices)) => indices.len() * 4,
+            None => 0,
+        };
+        attributes + indices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::support::loader_fu::features::set_point_data;
+    use bevy::render::pipeline::PrimitiveTopology;
+
+    fn files(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("{}.ply", i)).collect()
+    }
+
+    #[test]
+    fn test_unlimited_never_evicts() {
+        let cache = FrameCache::default();
+        assert!(cache.to_evict(&files(100), Some(0)).is_empty());
+    }
+
+    #[test]
+    fn test_evicts_furthest_unused_first() {
+        let cache = FrameCache::new(FrameBudget {
+            max_frames: Some(5),
+            resident_radius: 1,
+            ..Default::default()
+        });
+        let evict = cache.to_evict(&files(8), Some(2));
+        assert_eq!(evict, vec!["7.ply", "6.ply", "5.ply"]);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_first() {
+        let mut cache = FrameCache::new(FrameBudget {
+            max_frames: Some(4),
+            resident_radius: 0,
+            ..Default::default()
+        });
+        let resident = files(6);
+        for file in ["5.ply", "4.ply", "0.ply", "1.ply", "2.ply", "3.ply"].iter() {
+            cache.touch(file);
+        }
+        assert_eq!(cache.to_evict(&resident, Some(3)), vec!["5.ply", "4.ply"]);
+    }
+
+    #[test]
+    fn test_never_evicts_within_radius() {
+        let cache = FrameCache::new(FrameBudget {
+            max_frames: Some(3),
+            resident_radius: 1,
+            ..Default::default()
+        });
+        let evict = cache.to_evict(&files(5), Some(2));
+        assert_eq!(evict, vec!["0.ply", "4.ply"]);
+    }
+
+    #[test]
+    fn test_megabyte_budget() {
+        let mut cache = FrameCache::new(FrameBudget {
+            max_megabytes: Some(2),
+            resident_radius: 0,
+            ..Default::default()
+        });
+        let resident = files(4);
+        for file in &resident {
+            cache.record_size(file, 1024 * 1024);
+        }
+        assert_eq!(cache.to_evict(&resident, Some(0)), vec!["3.ply", "2.ply"]);
+    }
+    #[test]
+    fn test_mesh_bytes_counts_point_data() {
+        let mut mesh = Mesh::new(PrimitiveTopology::PointList);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0f32; 3]; 10]);
+        assert_eq!(FrameCache::mesh_bytes(&mesh), 120);
+        set_point_data(&mut mesh, "velocity", vec![[0f32; 3]; 10]);
+        set_point_data(&mut mesh, "pressure", vec![0f32; 10]);
+        assert_eq!(FrameCache::mesh_bytes(&mesh), 120 + 120 + 40);
+    }
+}
@@ -0,0 +1,162 @@
+use std::{collections::HashMap, path::Path};
+
+use bevy::prelude::warn;
+
+use super::file_sequence::split_frame_number;
+
+// Frame Times
+// The simulated time of each frame, from a sidecar file in the dataset dir:
+// ```
+// # times.csv, a time per line in frame order, or a file name (or frame index) and a time
+// fluid_0000.ply, 0.0
+// fluid_0001.ply, 0.0125
+//
+// # times.json, an array of times in frame order, or an object of file name to time
+// { "fluid_0000.ply": 0.0, "fluid_0001.ply": 0.0125 }
+// ```
+// or, without one, from file names with a decimal frame number i.e. fluid_t0.01250.ply
+
+pub const SIDECAR_FILES: [&str; 2] = ["times.csv", "times.json"];
+
+/// Times of `files` (sorted, in frame order), keyed by file
+pub fn frame_times(
+    dir: Option<&Path>,
+    files: &[String],
+) -> HashMap<String, f64> {
+    if let Some(dir) = dir {
+        for sidecar in SIDECAR_FILES.iter() {
+            let path = dir.join(sidecar);
+            if let Ok(contents) = std::fs::read_to_string(&path) {
+                let times = if sidecar.ends_with(".json") {
+                    parse_json(&contents, files)
+                } else {
+                    Ok(parse_csv(&contents, files))
+                };
+                match times {
+                    Ok(times) => return times,
+                    Err(e) => warn!("Couldn't read frame times from {}: {}", path.display(), e),
+                }
+            }
+        }
+    }
+    files
+        .iter()
+        .filter_map(|f| time_from_file_name(f).map(|time| (f.clone(), time)))
+        .collect()
+}
+
+/// fluid_t0.01250.ply => 0.0125, integer frame numbers are not times
+pub fn time_from_file_name(file: &str) -> Option<f64> {
+    let file_name = Path::new(file).file_name()?.to_string_lossy();
+    let (_, number, _) = split_frame_number(&file_name)?;
+    if number.contains('.') {
+        number.parse().ok()
+    } else {
+        None
+    }
+}
+
+pub fn parse_csv(
+    contents: &str,
+    files: &[String],
+) -> HashMap<String, f64> {
+    let mut times = HashMap::new();
+    let mut frame = 0;
+    for line in contents.lines().map(|l| l.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let columns: Vec<&str> = line.split(',').map(|c| c.trim()).collect();
+        let (file, time) = match columns.as_slice() {
+            [time] => (files.get(frame), time),
+            [frame_or_file, time, ..] => (find_file(frame_or_file, files), time),
+            _ => continue,
+        };
+        // skips headers
+        if let Ok(time) = time.parse::<f64>() {
+            if let Some(file) = file {
+                times.insert(file.clone(), time);
+            }
+            frame += 1;
+        }
+    }
+    times
+}
+
+pub fn parse_json(
+    contents: &str,
+    files: &[String],
+) -> Result<HashMap<String, f64>, serde_json::Error> {
+    let value: serde_json::Value = serde_json::from_str(contents)?;
+    let times = match value {
+        serde_json::Value::Array(times) => files
+            .iter()
+            .zip(times.iter())
+            .filter_map(|(file, time)| time.as_f64().map(|time| (file.clone(), time)))
+            .collect(),
+        serde_json::Value::Object(times) => times
+            .iter()
+            .filter_map(|(frame_or_file, time)| {
+                Some((find_file(frame_or_file, files)?.clone(), time.as_f64()?))
+            })
+            .collect(),
+        _ => HashMap::new(),
+    };
+    Ok(times)
+}
+
+/// A file by its path, file name or index
+fn find_file<'a>(
+    frame_or_file: &str,
+    files: &'a [String],
+) -> Option<&'a String> {
+    if let Ok(index) = frame_or_file.parse::<usize>() {
+        return files.get(index);
+    }
+    files.iter().find(|f| {
+        *f == frame_or_file
+            || Path::new(f)
+                .file_name()
+                .map_or(false, |name| name.to_string_lossy() == frame_or_file)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files() -> Vec<String> {
+        vec![
+            String::from("run/fluid_0.ply"),
+            String::from("run/fluid_1.ply"),
+            String::from("run/fluid_2.ply"),
+        ]
+    }
+
+    #[test]
+    fn test_time_from_file_name() {
+        assert_eq!(time_from_file_name("run/fluid_t0.01250.ply"), Some(0.0125));
+        assert_eq!(time_from_file_name("run/fluid_0001.ply"), None);
+    }
+
+    #[test]
+    fn test_csv() {
+        let times = parse_csv("0.0\n0.5\n", &files());
+        assert_eq!(times.get("run/fluid_1.ply"), Some(&0.5));
+
+        let times = parse_csv("file,time\nfluid_2.ply, 2.5\n0, 0.1\n", &files());
+        assert_eq!(times.get("run/fluid_2.ply"), Some(&2.5));
+        assert_eq!(times.get("run/fluid_0.ply"), Some(&0.1));
+        assert_eq!(times.len(), 2);
+    }
+
+    #[test]
+    fn test_json() {
+        let times = parse_json("[0.0, 0.25, 0.75]", &files()).unwrap();
+        assert_eq!(times.get("run/fluid_2.ply"), Some(&0.75));
+
+        let times = parse_json(r#"{"fluid_1.ply": 0.3}"#, &files()).unwrap();
+        assert_eq!(times.get("run/fluid_1.ply"), Some(&0.3));
+        assert!(parse_json("not json", &files()).is_err());
+    }
+}
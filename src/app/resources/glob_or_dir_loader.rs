@@ -1,18 +1,58 @@
-use std::{fs::read_dir, path::Path};
+use std::{
+    collections::HashMap,
+    fs::read_dir,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use bevy::prelude::AssetServer;
+use bevy::prelude::*;
 use walkdir::WalkDir;
 
-use super::load_manager::LoadManager;
+use super::{
+    dataset_manifest::{DatasetManifest, MANIFEST_FILE},
+    dataset_watcher::DatasetWatcher,
+    file_sequence::{compare_frames, detect_sequences, sequence_name, FileSequence},
+    frame_times::SIDECAR_FILES,
+    load_manager::LoadManager,
+    paraview_collection::{is_collection, ParaViewCollection},
+};
+use crate::support::{
+    archive::{entries, is_archive},
+    asset_io::{asset_path, DatasetPathError},
+    compression::frame_extension,
+    http_cache::{is_url, HttpCache},
+};
 
 #[derive(Clone)]
 pub struct GlobOrDirLoader {
     load_manager: LoadManager,
     glob: Option<String>,
     load_dirs: String,
+    /// datasets served over HTTP, listed besides those in `load_dirs`
+    urls: Vec<String>,
+    http_cache: Option<HttpCache>,
+    /// Frames missing from the numbering of the chosen dataset
+    missing_frames: usize,
+    /// mr-dataset.ron of the chosen dataset
+    manifest: Option<DatasetManifest>,
+    /// the .pvd chosen as the dataset
+    collection: Option<ParaViewCollection>,
+    /// the tier of each frame, when the dataset chosen is packaged in several archives
+    tier_of: HashMap<String, usize>,
+    /// the dataset dir chosen, None when loading from the glob
+    chosen: Option<String>,
+    /// watches for frames being written into the dataset's directory, in live mode, and for
+    /// frames being overwritten
+    watcher: Option<Arc<DatasetWatcher>>,
+    watch_new_frames: bool,
+    watch_changed_frames: bool,
 }
 
-const FILE_EXTENSIONS: [&str; 3] = ["obj", "ply", "stl"];
+/// Compressed variants of each (.ply.gz, .obj.zst, .vtk.xz...) are frames too
+const FILE_EXTENSIONS: [&str; 14] = [
+    "obj", "ply", "stl", "vtk", "vtu", "vtp", "csv", "xyz", "pts", "bgeo", "npy", "npz", "gltf",
+    "glb",
+];
 
 impl GlobOrDirLoader {
     pub fn new(
@@ -24,9 +64,31 @@ impl GlobOrDirLoader {
             load_manager,
             glob,
             load_dirs,
+            urls: Vec::new(),
+            http_cache: None,
+            missing_frames: 0,
+            manifest: None,
+            collection: None,
+            tier_of: HashMap::new(),
+            chosen: None,
+            watcher: None,
+            watch_new_frames: false,
+            watch_changed_frames: false,
         }
     }
 
+    /// Lists datasets served over HTTP too, their frames fetched into `http_cache`
+    pub fn with_urls(
+        mut self,
+        urls: Vec<String>,
+        http_cache: HttpCache,
+    ) -> Self {
+        self.urls = urls;
+        self.load_manager.set_http_cache(Some(http_cache.clone()));
+        self.http_cache = Some(http_cache);
+        self
+    }
+
     pub fn update(
         &mut self,
         glob: Option<String>,
@@ -34,20 +96,200 @@ impl GlobOrDirLoader {
         server: &AssetServer,
     ) {
         let mut files = Vec::new();
+        self.missing_frames = 0;
+        self.manifest = None;
+        self.collection = None;
+        self.tier_of.clear();
+        self.chosen = load_dir_chosen.clone();
+        self.glob = glob.clone();
+        let dataset_dir = load_dir_chosen.as_deref().map(|d| self.dataset_dir(d));
         // load_dir_chosen takes precedence over glob passed via command line
         if let Some(dir_chosen) = load_dir_chosen {
             if let Some(new_files) = self.get_files_from_load_dir(dir_chosen) {
                 files.extend(new_files);
             }
         } else if let Some(glob) = glob {
-            files.extend(Self::files_from_glob(glob))
+            match Self::files_from_glob(glob) {
+                Ok(new_files) => files.extend(new_files),
+                Err(e) => error!("Couldn't load files from glob: {}", e),
+            }
         }
         // self.load_manager.clear();
-        self.load_manager.add_new_assets(files);
-        self.load_manager.load_assets(server)
+        self.load_manager
+            .set_tiers(std::mem::take(&mut self.tier_of));
+        self.load_manager.add_new_assets(files.clone());
+        match (
+            self.manifest.as_ref().filter(|m| !m.times.is_empty()),
+            &self.collection,
+        ) {
+            (Some(manifest), _) => self.load_manager.frame_times = manifest.frame_times(&files),
+            (None, Some(collection)) => self.load_manager.frame_times = collection.frame_times(),
+            (None, None) => self.load_manager.update_frame_times(dataset_dir.as_deref()),
+        }
+        self.load_manager.load_assets(server);
+
+        // the dataset may have changed, so watch its directory instead
+        self.watcher = None;
+        self.watch(self.watch_new_frames, self.watch_changed_frames);
+    }
+
+    /// Starts, or stops, watching the dataset's directory for new frames, and for frames that
+    /// change on disk. Remembered for datasets chosen later
+    pub fn watch(
+        &mut self,
+        new_frames: bool,
+        changed_frames: bool,
+    ) {
+        self.watch_new_frames = new_frames;
+        self.watch_changed_frames = changed_frames;
+        if !new_frames && !changed_frames {
+            self.watcher = None;
+            return;
+        }
+        if self.watcher.is_some() {
+            return;
+        }
+        let dir = match self.watch_dir() {
+            Some(dir) => dir,
+            None => {
+                info!(
+                    "Nothing to watch yet, choose a dataset, or a glob of files in one directory"
+                );
+                return;
+            }
+        };
+        match DatasetWatcher::new(&dir) {
+            Ok(watcher) => {
+                info!("Watching {} for changes", dir.display());
+                self.watcher = Some(Arc::new(watcher));
+            }
+            Err(e) => error!("Couldn't watch {}: {}", dir.display(), e),
+        }
+    }
+
+    /// (new frames, changed frames) to watch for, once there is a dataset to watch
+    pub fn watching(&self) -> (bool, bool) {
+        (self.watch_new_frames, self.watch_changed_frames)
+    }
+
+    /// Appends frames that have been fully written since the dataset was loaded, and reloads
+    /// those that were overwritten. Returns how many frames were appended
+    pub fn update_watched(
+        &mut self,
+        server: &AssetServer,
+    ) -> usize {
+        let watcher = match &self.watcher {
+            Some(watcher) => watcher.clone(),
+            None => return 0,
+        };
+        let ready: Vec<(PathBuf, String)> = watcher
+            .ready_files()
+            .into_iter()
+            .filter_map(|f| asset_path(&f).ok().map(|asset_path| (f, asset_path)))
+            .collect();
+
+        let (changed, new): (Vec<_>, Vec<_>) = ready
+            .into_iter()
+            .partition(|(_, asset_path)| self.load_manager.load_iterator.contains(asset_path));
+
+        if self.watch_changed_frames && !changed.is_empty() {
+            let changed = changed.into_iter().map(|(_, f)| f).collect();
+            self.load_manager.reload_changed(changed, server);
+        }
+
+        let new_files: Vec<String> = new
+            .into_iter()
+            .filter(|(path, _)| self.watch_new_frames && self.belongs_to_dataset(path))
+            .map(|(_, f)| f)
+            .collect();
+        if new_files.is_empty() {
+            return 0;
+        }
+        self.load_manager
+            .append_new_assets(new_files.clone(), self.in_frame_order(), server);
+        if self.manifest.is_none() && self.collection.is_none() {
+            let dataset_dir = self.chosen.as_deref().map(|d| self.dataset_dir(d));
+            self.load_manager.update_frame_times(dataset_dir.as_deref());
+        }
+        new_files.len()
+    }
+
+    /// Whether the dataset's frames are sorted by frame, rather than listed in an order of their
+    /// own, by a manifest or a collection
+    fn in_frame_order(&self) -> bool {
+        let listed = self
+            .manifest
+            .as_ref()
+            .map_or(false, |m| !m.files.is_empty());
+        !listed && self.collection.is_none()
+    }
+
+    fn watch_dir(&self) -> Option<PathBuf> {
+        if let Some(chosen) = self.chosen.as_deref() {
+            // frames appear in the cache as they're fetched, not as they're written
+            if is_url(chosen) {
+                return None;
+            }
+            return Some(self.dataset_dir(chosen)).filter(|dir| dir.is_dir());
+        }
+        // the glob's directory, as long as it has no wildcards
+        let glob = self.glob.as_deref()?;
+        let dir = Path::new(glob).parent()?;
+        let has_wildcards = dir.to_string_lossy().contains(|c: char| "*?[".contains(c));
+        Some(dir.to_path_buf()).filter(|dir| !has_wildcards && dir.is_dir())
+    }
+
+    /// Whether a new file is part of the sequence being shown
+    fn belongs_to_dataset(
+        &self,
+        file: &Path,
+    ) -> bool {
+        if !Self::is_frame_file(file) {
+            return false;
+        }
+        let file_name = match file.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => return false,
+        };
+        // the collection lists its files
+        if self.collection.is_some() {
+            return false;
+        }
+        if let Some(manifest) = &self.manifest {
+            // a list of files is fixed, a pattern may match new ones
+            return match manifest.pattern.as_deref().map(glob::Pattern::new) {
+                Some(Ok(pattern)) if manifest.files.is_empty() => pattern.matches(&file_name),
+                _ => false,
+            };
+        }
+        match self.chosen.as_deref() {
+            // a sequence within a directory
+            Some(chosen) if !Path::new(chosen).is_dir() => {
+                Path::new(chosen).file_name().map_or(false, |name| {
+                    name.to_string_lossy() == sequence_name(&file_name)
+                })
+            }
+            Some(_) => {
+                // same sequence as what's already there, if anything is
+                let all = self.load_manager.load_iterator.all();
+                all.first()
+                    .and_then(|f| Path::new(f).file_name())
+                    .map_or(true, |first| {
+                        sequence_name(&first.to_string_lossy()) == sequence_name(&file_name)
+                    })
+            }
+            None => match self.glob.as_deref().map(glob::Pattern::new) {
+                Some(Ok(pattern)) => pattern.matches_path(file),
+                _ => false,
+            },
+        }
     }
 
     pub fn dirs_from_load_dir(&self) -> Option<Vec<String>> {
+        if !Path::new(&self.load_dirs).is_dir() {
+            warn!("Dataset dir {} is not a directory", self.load_dirs);
+            return None;
+        }
         let walker = WalkDir::new(self.load_dirs.clone()).follow_links(true);
         let dirs = walker
             .into_iter()
@@ -58,6 +300,69 @@ impl GlobOrDirLoader {
         return Some(dirs);
     }
 
+    /// Every directory under the dataset dir, and when a directory holds several sequences, each
+    /// of those as `dir/prefix*suffix`. ParaView collections (.pvd) are listed in place of the
+    /// directory they are in, and archives (.zip) besides it. Then the datasets served over HTTP
+    pub fn datasets(&self) -> Option<Vec<String>> {
+        let dirs = self.dirs_from_load_dir().unwrap_or_default();
+        let mut datasets = Vec::new();
+        for dir in dirs {
+            // the manifest says what the sequence is
+            if Path::new(&dir).join(MANIFEST_FILE).is_file() {
+                datasets.push(dir);
+                continue;
+            }
+            let collections = Self::collections_in(&dir);
+            if !collections.is_empty() {
+                datasets.extend(collections);
+                continue;
+            }
+            let archives = Self::archives_in(&dir);
+            let sequences = Self::sequences_in(&dir);
+            if sequences.len() > 1 {
+                datasets.extend(
+                    sequences
+                        .iter()
+                        .map(|s| Path::new(&dir).join(s.name()).to_string_lossy().to_string()),
+                );
+            } else if archives.is_empty() || !sequences.is_empty() {
+                datasets.push(dir);
+            }
+            datasets.extend(archives);
+        }
+        datasets.extend(self.urls.iter().cloned());
+        Some(datasets)
+    }
+
+    /// The directory a dataset (a directory, or a sequence or collection within one) is in, where
+    /// its configs are saved. For a dataset served over HTTP, where its frames are cached
+    pub fn dataset_dir(
+        &self,
+        dataset: &str,
+    ) -> PathBuf {
+        if let (true, Some(cache)) = (is_url(dataset), &self.http_cache) {
+            return cache
+                .path_of(dataset)
+                .ok()
+                .and_then(|path| path.parent().map(Path::to_path_buf))
+                .unwrap_or_else(|| cache.root().to_path_buf());
+        }
+        let path = Path::new(dataset);
+        if path.is_dir() {
+            path.to_path_buf()
+        } else {
+            path.parent().map(Path::to_path_buf).unwrap_or_default()
+        }
+    }
+
+    pub fn missing_frames(&self) -> usize {
+        self.missing_frames
+    }
+
+    pub fn manifest(&self) -> Option<&DatasetManifest> {
+        self.manifest.as_ref()
+    }
+
     pub fn load_manager_mut(&mut self) -> &mut LoadManager {
         &mut self.load_manager
     }
@@ -66,51 +371,260 @@ impl GlobOrDirLoader {
         &self.load_manager
     }
 
-    fn files_from_glob(glob: String) -> Vec<String> {
-        glob::glob(glob.as_str())
-            .expect("Loading fluid from assets failed in glob")
-            .map(|entry| {
-                entry
-                    .unwrap()
-                    .strip_prefix("assets/")
-                    .unwrap()
-                    .to_string_lossy()
-                    .to_string()
+    fn files_from_glob(glob: String) -> Result<Vec<String>, DatasetPathError> {
+        let paths = glob::glob(glob.as_str())
+            .map_err(|e| DatasetPathError::BadGlob(glob.clone(), e.to_string()))?;
+        let mut files = Self::asset_paths(paths.filter_map(|entry| entry.ok()));
+        files.sort_by(|a, b| compare_frames(a, b));
+        Ok(files)
+    }
+
+    /// Maps filesystem paths to AssetServer paths, skipping (and reporting) those that can't be
+    fn asset_paths<I>(paths: I) -> Vec<String>
+    where
+        I: Iterator<Item = PathBuf>,
+    {
+        paths
+            .filter_map(|path| match asset_path(&path) {
+                Ok(asset_path) => Some(asset_path),
+                Err(e) => {
+                    warn!("Skipping {}", e);
+                    None
+                }
             })
             .collect()
     }
 
-    fn get_files_from_load_dir(
+    fn read_manifest(dir: &str) -> Option<DatasetManifest> {
+        match DatasetManifest::read(Path::new(dir))? {
+            Ok(manifest) => Some(manifest),
+            Err(e) => {
+                error!("Ignoring dataset manifest, {}", e);
+                None
+            }
+        }
+    }
+
+    /// A file of a format we load, that isn't one of the dataset's sidecar files
+    fn is_frame_file(file: &Path) -> bool {
+        let ext = frame_extension(file).unwrap_or_default();
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+        FILE_EXTENSIONS.iter().any(|e| *e == ext)
+            && !SIDECAR_FILES.iter().any(|sidecar| *sidecar == file_name)
+    }
+
+    fn collections_in(dir: &str) -> Vec<String> {
+        let mut collections: Vec<String> = match read_dir(Path::new(dir)) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|f| f.is_file() && is_collection(f))
+                .map(|f| f.to_string_lossy().to_string())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        collections.sort();
+        collections
+    }
+
+    fn archive_sequences_in(dir: &str) -> Vec<FileSequence> {
+        match read_dir(Path::new(dir)) {
+            Ok(entries) => detect_sequences(
+                entries
+                    .filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|f| f.is_file() && is_archive(f)),
+            ),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// The archives in `dir`, with numbered archives (run-1.zip, run-2.zip...) as one dataset,
+    /// `dir/run-*.zip`, of several tiers
+    fn archives_in(dir: &str) -> Vec<String> {
+        let mut datasets = Vec::new();
+        for sequence in Self::archive_sequences_in(dir) {
+            if sequence.numbered && sequence.frames.len() > 1 {
+                datasets.push(
+                    Path::new(dir)
+                        .join(sequence.name())
+                        .to_string_lossy()
+                        .to_string(),
+                );
+            } else {
+                datasets.extend(
+                    sequence
+                        .paths()
+                        .iter()
+                        .map(|p| p.to_string_lossy().to_string()),
+                );
+            }
+        }
+        datasets
+    }
+
+    /// The frames in an archive, or in every tier of a dataset packaged in several, recording the
+    /// tier of each
+    fn files_from_archives(
+        &mut self,
+        chosen: &Path,
+    ) -> Option<Vec<String>> {
+        let archives = if chosen.is_file() {
+            vec![chosen.to_path_buf()]
+        } else {
+            let dir = chosen.parent()?.to_string_lossy().to_string();
+            let name = chosen.file_name()?.to_string_lossy().to_string();
+            Self::archive_sequences_in(&dir)
+                .into_iter()
+                .find(|s| s.name() == name)?
+                .paths()
+        };
+
+        let mut files = Vec::new();
+        for (tier, archive) in archives.iter().enumerate() {
+            let archive_path = match asset_path(archive) {
+                Ok(archive_path) => archive_path,
+                Err(e) => {
+                    warn!("Skipping {}", e);
+                    continue;
+                }
+            };
+            let mut entries = match entries(archive) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    error!("Skipping {}, {}", archive.display(), e);
+                    continue;
+                }
+            };
+            entries.retain(|entry| Self::is_frame_file(Path::new(entry)));
+            for entry in entries {
+                let file = format!("{}/{}", archive_path, entry);
+                if archives.len() > 1 {
+                    self.tier_of.insert(file.clone(), tier);
+                }
+                files.push((entry, file));
+            }
+        }
+        // the frames of every tier interleave
+        files.sort_by(|(a, _), (b, _)| compare_frames(a, b));
+        Some(files.into_iter().map(|(_, file)| file).collect())
+    }
+
+    fn sequences_in(dir: &str) -> Vec<FileSequence> {
+        match read_dir(Path::new(dir)) {
+            Ok(entries) => detect_sequences(
+                entries
+                    .filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|f| f.is_file() && Self::is_frame_file(f)),
+            ),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// The frames an index served over HTTP lists, as the paths they are cached at
+    fn files_from_url(
         &self,
+        url: &str,
+    ) -> Option<Vec<String>> {
+        let cache = self.http_cache.as_ref()?;
+        let urls = match cache.index(url) {
+            Ok(urls) => urls,
+            Err(e) => {
+                error!("Couldn't open dataset, {}", e);
+                return None;
+            }
+        };
+        let files = urls
+            .iter()
+            .filter(|url| Self::is_frame_file(Path::new(url.as_str())))
+            .filter_map(|url| match cache.path_of(url) {
+                Ok(path) => Some(path.to_string_lossy().to_string()),
+                Err(e) => {
+                    warn!("Skipping {}", e);
+                    None
+                }
+            })
+            .collect();
+        Some(files)
+    }
+
+    fn get_files_from_load_dir(
+        &mut self,
         chosen: String,
     ) -> Option<Vec<String>> {
-        if let Some(dirs) = self.dirs_from_load_dir() {
-            if let Some(loading_from) = dirs.iter().find(|&d| *d == chosen) {
-                if let Ok(entries) = read_dir(Path::new(loading_from)) {
-                    let files: Vec<String> = entries
-                        .filter(|e| e.is_ok())
-                        .map(|e| e.unwrap().path())
-                        .filter(|f| f.is_file())
-                        .filter(|f| {
-                            FILE_EXTENSIONS
-                                .iter()
-                                .find(|ext| {
-                                    let r = &*(f.extension().unwrap_or_default().to_string_lossy());
-                                    &r == *ext
-                                })
-                                .is_some()
-                        })
-                        .map(|e| {
-                            e.strip_prefix("assets/")
-                                .unwrap()
-                                .to_string_lossy()
-                                .to_string()
-                        })
-                        .collect();
-                    return Some(files);
+        if is_url(&chosen) {
+            return self.files_from_url(&chosen);
+        }
+        let dirs = self.dirs_from_load_dir()?;
+        let chosen_path = Path::new(&chosen);
+        if is_collection(chosen_path) {
+            return match ParaViewCollection::read(chosen_path) {
+                Ok(collection) => {
+                    let files = collection.files();
+                    self.collection = Some(collection);
+                    Some(Self::asset_paths(files.into_iter()))
+                }
+                Err(e) => {
+                    error!("Couldn't open dataset, {}", e);
+                    None
+                }
+            };
+        }
+        if is_archive(chosen_path) {
+            let in_dataset_dir = chosen_path
+                .parent()
+                .map_or(false, |dir| dirs.iter().any(|d| Path::new(d) == dir));
+            return if in_dataset_dir {
+                self.files_from_archives(chosen_path)
+            } else {
+                None
+            };
+        }
+        if dirs.iter().any(|d| *d == chosen) {
+            if let Some(manifest) = Self::read_manifest(&chosen) {
+                let files = manifest.files(chosen_path);
+                self.manifest = Some(manifest);
+                if let Some(files) = files {
+                    return Some(Self::asset_paths(files.into_iter()));
                 }
             }
         }
-        None
+        let sequences = if dirs.iter().any(|d| *d == chosen) {
+            Self::sequences_in(&chosen)
+        } else {
+            // a single sequence, from a directory holding several
+            let dir = chosen_path.parent()?.to_string_lossy().to_string();
+            let name = chosen_path.file_name()?.to_string_lossy().to_string();
+            if !dirs.contains(&dir) {
+                return None;
+            }
+            Self::sequences_in(&dir)
+                .into_iter()
+                .filter(|s| s.name() == name)
+                .collect()
+        };
+
+        for sequence in sequences.iter() {
+            let gaps = sequence.gaps();
+            if !gaps.is_empty() {
+                let gaps: Vec<String> = gaps
+                    .iter()
+                    .map(|(from, to)| {
+                        if from == to {
+                            from.to_string()
+                        } else {
+                            format!("{}-{}", from, to)
+                        }
+                    })
+                    .collect();
+                warn!("{} is missing frames {}", sequence.name(), gaps.join(", "));
+                self.missing_frames += sequence.missing_frames();
+            }
+        }
+
+        Some(Self::asset_paths(
+            sequences.iter().flat_map(|s| s.paths().into_iter()),
+        ))
     }
 }
@@ -1,91 +1,499 @@
-use super::lod_midpoint_iterator::MidpointIterator;
+use super::{
+    actions::FrameDirection,
+    file_sequence::compare_frames,
+    frame_cache::FrameCache,
+    frame_times::frame_times,
+    lod_iterator::{LodIterator, LodOptions},
+    lod_tier_iterator::TierIterator,
+};
+use crate::support::{
+    asset_io::{decompressed_path, reload_path, source_file},
+    http_cache::HttpCache,
+    mesh_cache::MeshCache,
+};
 use bevy::{
     asset::LoadState,
-    prelude::{AssetServer, Handle, HandleUntyped, Mesh},
+    prelude::{warn, AssetServer, Assets, Handle, HandleUntyped, Mesh},
+    tasks::IoTaskPool,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::RangeInclusive,
+    path::Path,
 };
-use std::path::Path;
 
 type VecAssetLoading = Vec<(String, HandleUntyped)>;
 type VecAssetLoaded = Vec<(String, Handle<Mesh>)>;
+/// file name, and the reason it failed
+type VecAssetFailed = Vec<(String, String)>;
+
+/// How many loads we hand to the AssetServer at once, the rest wait in `pending`, so that
+/// prefetched frames can jump the queue
+const MAX_IN_FLIGHT: usize = 32;
 
 #[derive(Clone)]
 pub struct LoadManager {
-    //FIXME: make this an LodIterator trait
-    pub load_iterator: MidpointIterator<String>,
+    pub load_iterator: Box<dyn LodIterator>,
+    pub lod_options: LodOptions,
+    /// the tier of each file of a dataset packaged in tiers, which then decide the LODs instead
+    /// of `lod_options`
+    tier_of: HashMap<String, usize>,
     pub loaded: VecAssetLoaded,
     pub loading: VecAssetLoading,
+    pub failed: VecAssetFailed,
+    /// loads that were in flight when cleared, their handles are held until they settle,
+    /// so the AssetServer frees them instead of leaking them
+    cancelled: VecAssetLoading,
+    pub frame_cache: FrameCache,
+    /// frames dropped to stay within the frame cache budget, reloaded when the playhead nears them
+    pub evicted: Vec<String>,
+    /// files waiting to be handed to the AssetServer, highest priority first
+    pending: VecDeque<String>,
+    pub max_in_flight: usize,
+    /// simulated time of each file, when known
+    pub frame_times: HashMap<String, f64>,
+    /// loaded files that changed on disk, being loaded again. Their stale mesh stays in `loaded`
+    /// until the new one is ready
+    reloading: VecAssetLoading,
+    reload_generation: u64,
+    /// files reloaded since `take_reloaded` was last called
+    reloaded: Vec<String>,
+    /// parsed meshes are kept here, when enabled, and read back instead of parsing the file again
+    mesh_cache: Option<MeshCache>,
+    /// files being loaded from the mesh cache
+    from_mesh_cache: Vec<String>,
+    /// files parsed since the mesh cache was last written to
+    to_mesh_cache: Vec<String>,
+    /// where frames of remote datasets are fetched to
+    http_cache: Option<HttpCache>,
 }
 
 impl LoadManager {
-    pub fn new(mut load_iterator: MidpointIterator<String>) -> Self {
-        load_iterator.sort();
+    pub fn new(
+        load_iterator: Box<dyn LodIterator>,
+        lod_options: LodOptions,
+    ) -> Self {
         Self {
             load_iterator,
+            lod_options,
+            tier_of: HashMap::new(),
             loaded: Vec::new(),
             loading: Vec::new(),
+            failed: Vec::new(),
+            cancelled: Vec::new(),
+            frame_cache: FrameCache::default(),
+            evicted: Vec::new(),
+            pending: VecDeque::new(),
+            max_in_flight: MAX_IN_FLIGHT,
+            frame_times: HashMap::new(),
+            reloading: Vec::new(),
+            reload_generation: 0,
+            reloaded: Vec::new(),
+            mesh_cache: None,
+            from_mesh_cache: Vec::new(),
+            to_mesh_cache: Vec::new(),
+            http_cache: None,
         }
     }
 
+    pub fn set_mesh_cache(
+        &mut self,
+        mesh_cache: Option<MeshCache>,
+    ) {
+        self.mesh_cache = mesh_cache;
+    }
+
+    pub fn set_http_cache(
+        &mut self,
+        http_cache: Option<HttpCache>,
+    ) {
+        self.http_cache = http_cache;
+    }
+
     pub fn load_assets(
         &mut self,
         server: &AssetServer,
     ) {
-        let to_load: VecAssetLoading = self
-            .load_iterator
-            .clone()
-            .filter(|path| !self.in_loaded_or_loading(path.clone()))
-            .clone()
-            .map(|fluid_file| {
-                (
-                    fluid_file.clone(),
-                    server.load_untyped(Path::new(&fluid_file)),
-                )
-            })
-            .collect();
-        self.loading.extend(to_load);
+        let to_load: Vec<String> = self.load_iterator.files();
+        self.queue(to_load);
+        self.pump(server);
+    }
+
+    /// Adds files to the back of the pending queue
+    fn queue(
+        &mut self,
+        files: Vec<String>,
+    ) {
+        for file in files {
+            if !self.in_loaded_or_loading(file.clone()) {
+                self.pending.push_back(file);
+            }
+        }
+    }
+
+    /// Adds files to the front of the pending queue, in the order given
+    fn queue_first(
+        &mut self,
+        files: Vec<String>,
+    ) {
+        for file in files.into_iter().rev() {
+            if let Some(position) = self.pending.iter().position(|f| *f == file) {
+                self.pending.remove(position);
+            } else if self.in_loaded_or_loading(file.clone()) {
+                continue;
+            }
+            self.pending.push_front(file);
+        }
     }
 
-    //TODO: pass the asset_manager here and unload files before clearing
+    /// Hands pending files to the AssetServer, while there is room in flight
+    fn pump(
+        &mut self,
+        server: &AssetServer,
+    ) {
+        while self.max_in_flight == 0 || self.loading.len() < self.max_in_flight {
+            if let Some(file) = self.pending.pop_front() {
+                let cached = self.mesh_cache.as_ref().and_then(|c| c.valid_path(&file));
+                let path = match cached {
+                    Some(cached) => {
+                        self.from_mesh_cache.push(file.clone());
+                        cached.to_string_lossy().to_string()
+                    }
+                    None => decompressed_path(&file),
+                };
+                let handle = server.load_untyped(Path::new(&path));
+                self.loading.push((file, handle));
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Loads the `frames` frames ahead of the current one (in the direction of playback) from the
+    /// full list of files, before anything else that is pending
+    pub fn prefetch(
+        &mut self,
+        current_file: Option<&str>,
+        direction: &FrameDirection,
+        frames: usize,
+        server: &AssetServer,
+    ) {
+        let window = self.prefetch_files(current_file, direction, frames);
+        if window.is_empty() {
+            return;
+        }
+        self.evicted.retain(|f| !window.contains(f));
+        self.queue_first(window);
+        self.pump(server);
+    }
+
+    /// The files `prefetch` loads. Within a budget, only those near the current frame, as frames
+    /// further away would be evicted as soon as they loaded, then prefetched again
+    fn prefetch_files(
+        &self,
+        current_file: Option<&str>,
+        direction: &FrameDirection,
+        frames: usize,
+    ) -> Vec<String> {
+        if frames == 0 {
+            return Vec::new();
+        }
+        let all_files = self.load_iterator.all();
+        let current = if let Some(current) =
+            current_file.and_then(|c| all_files.iter().position(|f| f == c))
+        {
+            current
+        } else {
+            return Vec::new();
+        };
+        let mut window = prefetch_window(all_files.len(), current, frames, direction);
+        let budget = &self.frame_cache.budget;
+        if !budget.is_unlimited() {
+            let near = self.near(current);
+            window.retain(|idx| near.contains(idx));
+        }
+        window
+            .into_iter()
+            .map(|idx| all_files[idx].clone())
+            .collect()
+    }
+
+    /// Dropping our strong handles unloads the meshes, as long as nobody else holds them
+    /// (MeshAssets & MeshPool need clearing as well)
     pub fn clear(&mut self) {
         self.load_iterator.clear();
 
-        // unload from asset manager here
         self.loaded.clear();
-        self.loading.clear();
+        self.cancelled.extend(self.loading.drain(..));
+        self.cancelled.extend(self.reloading.drain(..));
+        self.reloaded.clear();
+        self.pending.clear();
+        self.failed.clear();
+        self.evicted.clear();
+        self.frame_cache.clear();
+        self.frame_times.clear();
+        self.from_mesh_cache.clear();
+        self.to_mesh_cache.clear();
     }
 
+    /// Adds files in the order given, the order of the dataset's frames, which only its source
+    /// knows: a manifest's list of files, a collection's times, or a sequence's frame numbers
     pub fn add_new_assets(
         &mut self,
         new_assets: Vec<String>,
     ) {
         for new_asset in new_assets {
-            if !self.load_iterator.contains(new_asset.clone()) {
+            if !self.load_iterator.contains(&new_asset) {
                 self.load_iterator.push(new_asset.clone());
             }
         }
-        self.sort();
         self.load_iterator.clear_indices();
         self.load_iterator.initialize();
     }
 
-    pub fn update_load_state(
+    /// Adds frames that appeared after the dataset was loaded, and loads them straight away.
+    /// The LOD iterator is left as it is, so the chosen LOD, and the index of each frame (which
+    /// camera timelines are keyed on) stay the same. `in_frame_order` is false for datasets
+    /// listed in an order of their own, a manifest's or a collection's, which new frames are
+    /// appended to as given
+    pub fn append_new_assets(
         &mut self,
+        new_assets: Vec<String>,
+        in_frame_order: bool,
         server: &AssetServer,
     ) {
-        let newly_loaded: VecAssetLoaded = self
-            .loading
-            .iter()
-            .filter(|(_, h)| LoadState::Loaded == server.get_load_state(h))
-            .map(|(f, h)| (f.clone(), server.get_handle(h)))
-            .collect();
-        self.loaded.extend(newly_loaded);
+        let new_assets = self.add_appended(new_assets, in_frame_order);
+        self.queue(new_assets);
+        self.pump(server);
+    }
 
-        self.loading = self
-            .loading
-            .iter()
-            .filter(|(_, h)| !(LoadState::Loaded == server.get_load_state(h)))
-            .cloned()
+    /// The frames of `new_assets` that weren't in the dataset, once they've been added to it
+    fn add_appended(
+        &mut self,
+        new_assets: Vec<String>,
+        in_frame_order: bool,
+    ) -> Vec<String> {
+        let mut new_assets: Vec<String> = new_assets
+            .into_iter()
+            .filter(|f| !self.load_iterator.contains(f))
             .collect();
+        if !in_frame_order {
+            for new_asset in new_assets.iter() {
+                self.load_iterator.push(new_asset.clone());
+            }
+            return new_assets;
+        }
+        new_assets.sort_by(|a, b| compare_frames(a, b));
+        let in_order = match (self.load_iterator.all().last(), new_assets.first()) {
+            (Some(last), Some(first)) => compare_frames(last, first).is_lt(),
+            _ => true,
+        };
+        for new_asset in new_assets.iter() {
+            self.load_iterator.push(new_asset.clone());
+        }
+        if !in_order {
+            // a frame written before the last one shifts every index after it
+            self.sort();
+            self.load_iterator.clear_indices();
+            self.load_iterator.initialize();
+        }
+        new_assets
+    }
+
+    /// Reads the times of every file, from a sidecar file in `dir`, or the file names
+    pub fn update_frame_times(
+        &mut self,
+        dir: Option<&Path>,
+    ) {
+        self.frame_times = frame_times(dir, self.load_iterator.all());
+    }
+
+    pub fn frame_time(
+        &self,
+        file: &str,
+    ) -> Option<f64> {
+        self.frame_times.get(file).copied()
+    }
+
+    pub fn update_load_state(
+        &mut self,
+        server: &AssetServer,
+    ) {
+        let mut still_loading = Vec::new();
+        let mut retry = Vec::new();
+        for (file, handle) in std::mem::take(&mut self.loading) {
+            let cached = self.from_mesh_cache.iter().position(|f| *f == file);
+            match server.get_load_state(&handle) {
+                LoadState::Loaded => {
+                    match cached {
+                        Some(position) => {
+                            self.from_mesh_cache.remove(position);
+                        }
+                        None if self.mesh_cache.is_some() => self.to_mesh_cache.push(file.clone()),
+                        None => {}
+                    }
+                    self.loaded.push((file, server.get_handle(&handle)))
+                }
+                LoadState::Failed if cached.is_some() => {
+                    // parse the file itself instead
+                    if let (Some(position), Some(mesh_cache)) = (cached, &self.mesh_cache) {
+                        self.from_mesh_cache.remove(position);
+                        warn!("Dropping the cached mesh of {}, it couldn't be read", file);
+                        let _ = std::fs::remove_file(mesh_cache.path_of(&file));
+                    }
+                    retry.push(file);
+                }
+                LoadState::Failed => {
+                    let reason = match self.likely_failure_reason(&file) {
+                        Some(reason) => {
+                            warn!(
+                                "Failed to load {}, {} (see the loader's error above)",
+                                file, reason
+                            );
+                            reason
+                        }
+                        None => {
+                            warn!("Failed to load {}, see the loader's error above", file);
+                            String::from("unknown, the loader's error is in the log")
+                        }
+                    };
+                    self.failed.push((file, reason));
+                }
+                _ => still_loading.push((file, handle)),
+            }
+        }
+        self.loading = still_loading;
+        self.queue_first(retry);
+
+        let mut still_reloading = Vec::new();
+        for (file, handle) in self.reloading.drain(..) {
+            match server.get_load_state(&handle) {
+                LoadState::Loaded => {
+                    if let Some(slot) = self.loaded.iter_mut().find(|(f, _)| *f == file) {
+                        slot.1 = server.get_handle(&handle);
+                        self.frame_cache.forget(&file);
+                        if self.mesh_cache.is_some() {
+                            self.to_mesh_cache.push(file.clone());
+                        }
+                        self.reloaded.push(file);
+                    }
+                }
+                // keep showing the last good version
+                LoadState::Failed => {
+                    warn!("Failed to reload {}, it may still be being written", file)
+                }
+                _ => still_reloading.push((file, handle)),
+            }
+        }
+        self.reloading = still_reloading;
+
+        // stale loads from a previous dataset never make it into loaded
+        self.cancelled
+            .retain(|(_, handle)| match server.get_load_state(handle) {
+                LoadState::Loaded | LoadState::Failed => false,
+                _ => true,
+            });
+
+        self.pump(server);
+    }
+
+    /// Loads files that changed on disk again, into their existing slot in `loaded`.
+    /// Files that had failed are retried, files not loaded yet will be read fresh anyway
+    pub fn reload_changed(
+        &mut self,
+        files: Vec<String>,
+        server: &AssetServer,
+    ) {
+        for file in files {
+            if self.loaded.iter().any(|(f, _)| *f == file) {
+                // the AssetServer hands back what it already has for a path, so load it by another
+                self.reload_generation += 1;
+                let path = reload_path(&decompressed_path(&file), self.reload_generation);
+                let handle = server.load_untyped(Path::new(&path));
+                if let Some(position) = self.reloading.iter().position(|(f, _)| *f == file) {
+                    let stale = self.reloading.remove(position);
+                    self.cancelled.push(stale);
+                }
+                self.reloading.push((file, handle));
+            } else if let Some(position) = self.failed.iter().position(|(f, _)| *f == file) {
+                self.failed.remove(position);
+                self.queue(vec![file]);
+            }
+        }
+        self.pump(server);
+    }
+
+    /// Writes the meshes parsed since this was last called to the mesh cache, in the background
+    pub fn store_in_mesh_cache(
+        &mut self,
+        meshes: &Assets<Mesh>,
+        io_pool: &IoTaskPool,
+    ) {
+        let mesh_cache = match &self.mesh_cache {
+            Some(mesh_cache) => mesh_cache,
+            None => return,
+        };
+        for file in self.to_mesh_cache.drain(..) {
+            let mesh = self
+                .loaded
+                .iter()
+                .find(|(f, _)| *f == file)
+                .and_then(|(_, handle)| meshes.get(handle));
+            let bytes = match mesh.and_then(|mesh| mesh_cache.encode(&file, mesh)) {
+                Some(bytes) => bytes,
+                None => continue,
+            };
+            let path = mesh_cache.path_of(&file);
+            io_pool
+                .spawn(async move {
+                    // so a half written mesh is never read
+                    let part = path.with_extension("part");
+                    if let Err(e) =
+                        std::fs::write(&part, bytes).and_then(|_| std::fs::rename(&part, &path))
+                    {
+                        warn!("Couldn't cache the mesh of {}: {}", file, e);
+                    }
+                })
+                .detach();
+        }
+    }
+
+    /// Files whose mesh was replaced by a reload, since this was last called
+    pub fn take_reloaded(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.reloaded)
+    }
+
+    /// Queue everything that previously failed to load again
+    pub fn retry_failed(
+        &mut self,
+        server: &AssetServer,
+    ) {
+        let retrying: Vec<String> = self.failed.drain(..).map(|(f, _)| f).collect();
+        self.queue(retrying);
+        self.pump(server);
+    }
+
+    /// The AssetServer doesn't tell us why a load failed, it only logs the loader's error, so this
+    /// is a guess from the file on disk and reads as one. None for a frame within an archive, which
+    /// the archive on disk says nothing about
+    fn likely_failure_reason(
+        &self,
+        file: &str,
+    ) -> Option<String> {
+        let path = source_file(file)?;
+        let url = self
+            .http_cache
+            .as_ref()
+            .and_then(|cache| cache.url_of(&path));
+        Some(match (std::fs::metadata(&path), url) {
+            // fetched frames are only in the cache once they have been
+            (Err(_), Some(url)) => format!("likely couldn't be fetched from {}", url),
+            (Err(e), None) => format!("likely unreadable: {}", e),
+            (Ok(metadata), _) if metadata.len() == 0 => {
+                String::from("likely because the file is empty")
+            }
+            (Ok(_), _) => String::from("perhaps the file is truncated or corrupt"),
+        })
     }
 
     pub fn next_lod_and_reload(
@@ -107,22 +515,68 @@ impl LoadManager {
         self.load_iterator = self.load_iterator.highest_lod();
     }
 
-    pub fn reload(
+    /// Makes each tier of a packaged dataset a LOD, or goes back to `lod_options` when `tier_of`
+    /// is empty. Call before the files are loaded
+    pub fn set_tiers(
+        &mut self,
+        tier_of: HashMap<String, usize>,
+    ) {
+        if tier_of.is_empty() && self.tier_of.is_empty() {
+            return;
+        }
+        self.tier_of = tier_of;
+        let files = self.load_iterator.all().clone();
+        self.load_iterator = self.iterator(files, self.load_iterator.first_lod());
+    }
+
+    fn iterator(
+        &self,
+        files: Vec<String>,
+        first_lod: usize,
+    ) -> Box<dyn LodIterator> {
+        if self.tier_of.is_empty() {
+            self.lod_options.iterator(files, first_lod)
+        } else {
+            Box::new(TierIterator::new(files, self.tier_of.clone(), first_lod))
+        }
+    }
+
+    /// Switches LOD strategy, keeping the same files. Frames that the new strategy doesn't want
+    /// are dropped. A dataset packaged in tiers keeps its tiers
+    pub fn set_lod_options(
         &mut self,
+        lod_options: LodOptions,
         server: &AssetServer,
     ) {
-        let loading: Vec<(String, HandleUntyped)> = self
-            .load_iterator
-            .clone()
-            .filter(|f| !self.in_loaded_or_loading(f.clone()))
-            .map(|f| (f.clone(), server.load_untyped(Path::new(&f))))
-            .collect();
+        self.lod_options = lod_options;
+        let files = self.load_iterator.all().clone();
+        self.load_iterator = self.iterator(files, self.load_iterator.first_lod());
+
+        let wanted = self.load_iterator.files();
+        self.loaded.retain(|(f, _)| wanted.contains(f));
+        self.pending.retain(|f| wanted.contains(f));
+        let (keep, cancel): (VecAssetLoading, VecAssetLoading) = self
+            .loading
+            .drain(..)
+            .partition(|(f, _)| wanted.contains(f));
+        self.loading = keep;
+        self.cancelled.extend(cancel);
+        self.evicted.retain(|f| wanted.contains(f));
 
-        self.loading.extend(loading)
+        self.load_assets(server);
+    }
+
+    pub fn reload(
+        &mut self,
+        server: &AssetServer,
+    ) {
+        let loading: Vec<String> = self.load_iterator.files();
+        self.queue(loading);
+        self.pump(server);
     }
 
     pub fn fully_loaded(&self) -> bool {
-        self.loading.len() == 0
+        self.loading.len() == 0 && self.pending.len() == 0
     }
 
     pub fn data_path(&self) -> String {
@@ -133,18 +587,356 @@ impl LoadManager {
         self.load_iterator.sort();
     }
 
+    /// Drops loaded frames, furthest from the current frame & least recently used first, until
+    /// we are within the frame cache's budget
+    pub fn enforce_budget(
+        &mut self,
+        meshes: &Assets<Mesh>,
+        current_file: Option<&str>,
+    ) {
+        if let Some(current_file) = current_file {
+            self.frame_cache.touch(current_file);
+        }
+        if self.frame_cache.budget.is_unlimited() {
+            return;
+        }
+
+        for (file, handle) in self.loaded.iter() {
+            if !self.frame_cache.has_size(file) {
+                if let Some(mesh) = meshes.get(handle) {
+                    self.frame_cache
+                        .record_size(file, FrameCache::mesh_bytes(mesh));
+                }
+            }
+        }
+
+        let resident = self.resident_files();
+        let current = current_file.and_then(|c| resident.iter().position(|f| f == c));
+        let all_files = self.load_iterator.all();
+        let near = match current_file.and_then(|c| all_files.iter().position(|f| f == c)) {
+            Some(current) => all_files[self.near(current)].to_vec(),
+            None => Vec::new(),
+        };
+        let evict = self.frame_cache.to_evict_keeping(&resident, current, &near);
+        if evict.is_empty() {
+            return;
+        }
+
+        // dropping the handle frees the mesh
+        self.loaded.retain(|(f, _)| !evict.contains(f));
+        for file in evict.iter() {
+            self.frame_cache.forget(file);
+        }
+        self.evicted.extend(evict);
+    }
+
+    /// Reloads evicted frames that are near the current frame
+    pub fn reload_evicted_near(
+        &mut self,
+        current_file: Option<&str>,
+        server: &AssetServer,
+    ) {
+        if self.evicted.is_empty() {
+            return;
+        }
+        let all_files = self.load_iterator.all();
+        let current = if let Some(current) =
+            current_file.and_then(|c| all_files.iter().position(|f| f == c))
+        {
+            current
+        } else {
+            return;
+        };
+        let near = &all_files[self.near(current)];
+
+        let reloading: Vec<String> = self
+            .evicted
+            .iter()
+            .filter(|f| near.contains(f))
+            .cloned()
+            .collect();
+        self.evicted.retain(|f| !reloading.contains(f));
+        self.queue_first(reloading);
+        self.pump(server);
+    }
+
+    /// Indices into `all()` of the frames near the `current` one, which are prefetched, kept
+    /// loaded and reloaded when evicted: those within the budget's radius of it along the LOD
+    /// the player steps through, and any frames between them, which prefetching loads
+    fn near(
+        &self,
+        current: usize,
+    ) -> RangeInclusive<usize> {
+        let mut lod = self.load_iterator.indices();
+        lod.sort_unstable();
+        let radius = self.frame_cache.budget.radius();
+        // the LOD's frames before the current one, then those from it on
+        let split = lod.iter().take_while(|&&idx| idx < current).count();
+        let after = if lod.get(split) == Some(&current) {
+            radius + 1
+        } else {
+            radius
+        };
+        // short of LOD frames on either side, up to the start or end of the dataset
+        let first = if split < radius {
+            0
+        } else {
+            lod.get(split - radius).copied().unwrap_or(current)
+        };
+        let last = match (split + after).checked_sub(1) {
+            Some(idx) => lod
+                .get(idx)
+                .copied()
+                .unwrap_or_else(|| self.load_iterator.all().len().saturating_sub(1)),
+            None => current,
+        };
+        first.min(current)..=last.max(current)
+    }
+
+    /// The index of each file in playback order, which is the load iterator's
+    pub fn playback_order(&self) -> HashMap<&str, usize> {
+        self.load_iterator
+            .all()
+            .iter()
+            .enumerate()
+            .map(|(index, file)| (file.as_str(), index))
+            .collect()
+    }
+
+    /// Loaded files and their meshes, in playback order
+    pub fn loaded_in_order(&self) -> VecAssetLoaded {
+        let order = self.playback_order();
+        let mut loaded = self.loaded.clone();
+        loaded.sort_by_key(|(f, _)| order.get(f.as_str()).copied().unwrap_or(usize::MAX));
+        loaded
+    }
+
+    /// Loaded files, in playback order
+    pub fn resident_files(&self) -> Vec<String> {
+        self.loaded_in_order().into_iter().map(|(f, _)| f).collect()
+    }
+
+    pub fn resident_megabytes(&self) -> f32 {
+        self.frame_cache.resident_bytes(&self.resident_files()) as f32 / (1024. * 1024.)
+    }
+
+    /// Pending, failed & evicted files count too, so they are only loaded again when asked to
     fn in_loaded_or_loading(
         &self,
         path: String,
     ) -> bool {
-        self.loading.iter().any(|f| f.0 == *path) || self.loaded.iter().any(|f| f.0 == *path)
+        self.pending.iter().any(|f| *f == path)
+            || self.loading.iter().any(|f| f.0 == *path)
+            || self.loaded.iter().any(|f| f.0 == *path)
+            || self.failed.iter().any(|f| f.0 == *path)
+            || self.evicted.iter().any(|f| *f == path)
+    }
+
+    /// Number of files the load manager has dealt with, or is dealing with
+    pub fn requested_len(&self) -> usize {
+        self.loaded.len()
+            + self.loading.len()
+            + self.pending.len()
+            + self.failed.len()
+            + self.evicted.len()
     }
 }
 
+/// Indices of the `frames` files after `current` (before it when playing backwards), wrapping
+/// around like playback does
+pub fn prefetch_window(
+    len: usize,
+    current: usize,
+    frames: usize,
+    direction: &FrameDirection,
+) -> Vec<usize> {
+    let frames = frames.min(len.saturating_sub(1));
+    (1..=frames)
+        .map(|offset| match direction {
+            FrameDirection::Forward => (current + offset) % len,
+            FrameDirection::Back => (current + len - offset % len) % len,
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
+    use crate::app::resources::{
+        frame_cache::FrameBudget, lod_iterator::LodStrategy,
+        paraview_collection::ParaViewCollection,
+    };
 
     #[test]
     fn test_three() {}
+
+    #[test]
+    fn test_prefetch_window_forward_wraps() {
+        let window = prefetch_window(10, 8, 3, &FrameDirection::Forward);
+        assert_eq!(window, vec![9, 0, 1]);
+    }
+
+    #[test]
+    fn test_prefetch_window_back_wraps() {
+        let window = prefetch_window(10, 1, 3, &FrameDirection::Back);
+        assert_eq!(window, vec![0, 9, 8]);
+    }
+
+    #[test]
+    fn test_prefetch_window_larger_than_files() {
+        let window = prefetch_window(3, 0, 10, &FrameDirection::Forward);
+        assert_eq!(window, vec![1, 2]);
+        assert!(prefetch_window(0, 0, 10, &FrameDirection::Forward).is_empty());
+    }
+
+    #[test]
+    fn test_loaded_in_frame_order() {
+        let in_order = ["fluid_t0.0125.ply", "fluid_t0.05.ply", "fluid_t0.1.ply"];
+        let lod_options = LodOptions::default();
+        let mut load_manager = LoadManager::new(lod_options.iterator(Vec::new(), 0), lod_options);
+        load_manager.add_new_assets(in_order.iter().map(|f| f.to_string()).collect());
+        assert_eq!(load_manager.load_iterator.all()[..], in_order);
+
+        let files: Vec<String> = ["fluid_t0.05.ply", "fluid_t0.1.ply", "fluid_t0.0125.ply"]
+            .iter()
+            .map(|f| f.to_string())
+            .collect();
+
+        for file in files {
+            load_manager.loaded.push((file, Handle::default()));
+        }
+        let loaded: Vec<String> = load_manager
+            .loaded_in_order()
+            .into_iter()
+            .map(|(f, _)| f)
+            .collect();
+        assert_eq!(loaded[..], in_order);
+        assert_eq!(load_manager.resident_files()[..], in_order);
+    }
+
+    #[test]
+    fn test_add_new_assets_keeps_the_order_given() {
+        // i.e. a manifest's list of files, or a collection's times
+        let files = ["fluid_2.ply", "fluid_10.ply", "fluid_1.ply"];
+        let lod_options = LodOptions::default();
+        let mut load_manager = LoadManager::new(lod_options.iterator(Vec::new(), 0), lod_options);
+        load_manager.add_new_assets(files.iter().map(|f| f.to_string()).collect());
+        assert_eq!(load_manager.load_iterator.all()[..], files);
+    }
+
+    #[test]
+    fn test_appended_frames_keep_an_explicit_order() {
+        let lod_options = LodOptions::default();
+        let mut load_manager = LoadManager::new(lod_options.iterator(Vec::new(), 0), lod_options);
+        load_manager.add_new_assets(vec![String::from("b.ply"), String::from("c.ply")]);
+        let appended = load_manager.add_appended(vec![String::from("a.ply")], false);
+        assert_eq!(appended, ["a.ply"]);
+        assert_eq!(
+            load_manager.load_iterator.all()[..],
+            ["b.ply", "c.ply", "a.ply"]
+        );
+
+        // a sequence's frames are slotted into it
+        let lod_options = LodOptions::default();
+        let mut load_manager = LoadManager::new(lod_options.iterator(Vec::new(), 0), lod_options);
+        load_manager.add_new_assets(vec![
+            String::from("fluid_2.ply"),
+            String::from("fluid_10.ply"),
+        ]);
+        let new_frames = vec![String::from("fluid_3.ply"), String::from("fluid_10.ply")];
+        assert_eq!(load_manager.add_appended(new_frames, true), ["fluid_3.ply"]);
+        assert_eq!(
+            load_manager.load_iterator.all()[..],
+            ["fluid_2.ply", "fluid_3.ply", "fluid_10.ply"]
+        );
+    }
+
+    #[test]
+    fn test_collection_keeps_its_time_order() {
+        // a run restarted from t = 1, numbering its frames from 0 again
+        let collection = ParaViewCollection::parse(
+            r#"<VTKFile type="Collection"><Collection>
+    <DataSet timestep="1.5" file="restart/fluid_0001.vtu"/>
+    <DataSet timestep="1.0" file="fluid_0010.vtu"/>
+    <DataSet timestep="1.25" file="restart/fluid_0000.vtu"/>
+</Collection></VTKFile>"#,
+            Path::new("run"),
+        )
+        .unwrap();
+        let files: Vec<String> = collection
+            .files()
+            .iter()
+            .map(|f| f.to_string_lossy().to_string())
+            .collect();
+        let lod_options = LodOptions::default();
+        let mut load_manager = LoadManager::new(lod_options.iterator(Vec::new(), 0), lod_options);
+        load_manager.add_new_assets(files);
+        assert_eq!(
+            load_manager.load_iterator.all()[..],
+            [
+                "run/fluid_0010.vtu",
+                "run/restart/fluid_0000.vtu",
+                "run/restart/fluid_0001.vtu"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prefetch_stays_within_the_budget() {
+        let files: Vec<String> = (0..10).map(|i| format!("fluid_{}.ply", i)).collect();
+        let lod_options = LodOptions::default();
+        let mut load_manager = LoadManager::new(lod_options.iterator(Vec::new(), 0), lod_options);
+        load_manager.add_new_assets(files);
+        let forward = FrameDirection::Forward;
+        assert_eq!(
+            load_manager.prefetch_files(Some("fluid_8.ply"), &forward, 3),
+            ["fluid_9.ply", "fluid_0.ply", "fluid_1.ply"]
+        );
+
+        // a radius of 2, frames wrapped around to are too far from the current one
+        load_manager.frame_cache = FrameCache::new(FrameBudget {
+            max_frames: Some(5),
+            ..Default::default()
+        });
+        assert_eq!(
+            load_manager.prefetch_files(Some("fluid_8.ply"), &forward, 3),
+            ["fluid_9.ply"]
+        );
+        assert_eq!(
+            load_manager.prefetch_files(Some("fluid_2.ply"), &forward, 3),
+            ["fluid_3.ply", "fluid_4.ply"]
+        );
+    }
+
+    #[test]
+    fn test_prefetch_and_reload_near_a_strided_lod() {
+        let files: Vec<String> = (0..40).map(|i| format!("fluid_{}.ply", i)).collect();
+        let lod_options = LodOptions {
+            strategy: LodStrategy::FixedStride,
+            stride: 10,
+            ..Default::default()
+        };
+        let mut load_manager = LoadManager::new(lod_options.iterator(Vec::new(), 100), lod_options);
+        load_manager.add_new_assets(files);
+        // a radius of 1, along the LOD's fluid_0, fluid_10, fluid_20 & fluid_30
+        load_manager.frame_cache = FrameCache::new(FrameBudget {
+            max_frames: Some(3),
+            ..Default::default()
+        });
+        assert_eq!(load_manager.near(10), 0..=20);
+        assert_eq!(load_manager.near(12), 10..=20);
+        assert_eq!(load_manager.near(30), 20..=39);
+        assert_eq!(
+            load_manager.prefetch_files(Some("fluid_10.ply"), &FrameDirection::Forward, 3),
+            ["fluid_11.ply", "fluid_12.ply", "fluid_13.ply"]
+        );
+        assert_eq!(
+            load_manager.prefetch_files(Some("fluid_30.ply"), &FrameDirection::Forward, 3),
+            ["fluid_31.ply", "fluid_32.ply", "fluid_33.ply"]
+        );
+        assert_eq!(
+            load_manager.prefetch_files(Some("fluid_20.ply"), &FrameDirection::Back, 3),
+            ["fluid_19.ply", "fluid_18.ply", "fluid_17.ply"]
+        );
+    }
 }
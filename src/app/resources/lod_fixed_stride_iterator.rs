@@ -0,0 +1,137 @@
+use super::lod_iterator::LodIterator;
+
+// Fixed Stride Iterator
+// Loads every `stride`th file, halving the stride at each LOD, until every file is loaded:
+// ```
+// let iterator = FixedStrideIterator::new(files_0_to_99, 8, 100);
+// assert_eq!(iterator.get_lods(), [13, 25, 50, 100]);
+// ```
+// The stride is doubled for the first LOD until it loads no more than `first_lod` files, so a
+// long sequence opens as quickly as with the other strategies
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixedStrideIterator {
+    inner: Vec<String>,
+    indices: Vec<usize>,
+    stride: usize,
+    first_stride: usize,
+    first_lod: usize,
+}
+
+impl FixedStrideIterator {
+    pub fn new(
+        inner: Vec<String>,
+        stride: usize,
+        first_lod: usize,
+    ) -> Self {
+        let mut me = Self {
+            inner,
+            indices: Vec::new(),
+            stride,
+            first_stride: stride,
+            first_lod,
+        };
+        me.initialize();
+        me
+    }
+
+    /// `first_stride`, doubled until there are no more than `first_lod` files at it
+    fn starting_stride(&self) -> usize {
+        let len = self.inner.len();
+        let mut stride = self.first_stride.max(1);
+        while stride < len && (len + stride - 1) / stride > self.first_lod.max(1) {
+            stride *= 2;
+        }
+        stride
+    }
+
+    fn indices_for_stride(&self) -> Vec<usize> {
+        (0..self.inner.len()).step_by(self.stride.max(1)).collect()
+    }
+}
+
+impl LodIterator for FixedStrideIterator {
+    fn all(&self) -> &Vec<String> {
+        &self.inner
+    }
+
+    fn all_mut(&mut self) -> &mut Vec<String> {
+        &mut self.inner
+    }
+
+    fn indices(&self) -> Vec<usize> {
+        self.indices.clone()
+    }
+
+    fn clear_indices(&mut self) {
+        self.indices.clear();
+    }
+
+    fn initialize(&mut self) {
+        self.stride = self.starting_stride();
+        self.indices = self.indices_for_stride();
+    }
+
+    fn first_lod(&self) -> usize {
+        self.first_lod
+    }
+
+    fn set_first_lod(
+        &mut self,
+        first_lod: usize,
+    ) {
+        self.first_lod = first_lod;
+    }
+
+    fn next_lod(&self) -> Option<Box<dyn LodIterator>> {
+        if self.stride <= 1 || self.is_saturated() {
+            return None;
+        }
+        let mut next = self.clone();
+        next.stride = self.stride / 2;
+        next.indices = next.indices_for_stride();
+        Some(Box::new(next))
+    }
+
+    fn box_clone(&self) -> Box<dyn LodIterator> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("{}.ply", i)).collect()
+    }
+
+    #[test]
+    fn test_one_hundred() {
+        let iterator = FixedStrideIterator::new(files(100), 8, 100);
+        assert_eq!(iterator.len(), 13);
+        assert_eq!(iterator.full_index_from_lod_index(1), 8);
+        assert_eq!(iterator.get_lods(), vec![13, 25, 50, 100]);
+        assert!(iterator.highest_lod().is_saturated());
+    }
+
+    #[test]
+    fn test_zero_length() {
+        let iterator = FixedStrideIterator::new(Vec::new(), 8, 100);
+        assert_eq!(iterator.get_lods().len(), 0);
+    }
+
+    #[test]
+    fn test_first_lod_doubles_the_stride() {
+        let iterator = FixedStrideIterator::new(files(100), 8, 10);
+        assert_eq!(iterator.len(), 7);
+        assert_eq!(iterator.full_index_from_lod_index(1), 16);
+        assert_eq!(iterator.get_lods(), vec![7, 13, 25, 50, 100]);
+
+        let mut iterator = FixedStrideIterator::new(files(100), 8, 1);
+        assert_eq!(iterator.len(), 1);
+        iterator.set_first_lod(100);
+        iterator.initialize();
+        assert_eq!(iterator.len(), 13);
+    }
+}
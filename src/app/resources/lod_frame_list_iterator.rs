@@ -0,0 +1,129 @@
+use std::path::Path;
+
+use super::lod_iterator::LodIterator;
+
+// Frame List Iterator
+// Loads exactly the frames listed in a file, one per line, either as an index into the sorted
+// files, or as a file name. Blank lines & lines starting with # are ignored:
+// ```
+// # the interesting bit
+// 0
+// 120
+// fluid_0200.ply
+// ```
+// There is only the one LOD
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameListIterator {
+    inner: Vec<String>,
+    entries: Vec<String>,
+    indices: Vec<usize>,
+    first_lod: usize,
+}
+
+impl FrameListIterator {
+    pub fn new(
+        inner: Vec<String>,
+        entries: Vec<String>,
+        first_lod: usize,
+    ) -> Self {
+        let mut me = Self {
+            inner,
+            entries,
+            indices: Vec::new(),
+            first_lod,
+        };
+        me.initialize();
+        me
+    }
+
+    pub fn parse(contents: &str) -> Vec<String> {
+        contents
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| l.to_string())
+            .collect()
+    }
+
+    fn resolve(
+        &self,
+        entry: &str,
+    ) -> Option<usize> {
+        if let Ok(index) = entry.parse::<usize>() {
+            return Some(index).filter(|&i| i < self.inner.len());
+        }
+        self.inner.iter().position(|f| {
+            f == entry
+                || Path::new(f)
+                    .file_name()
+                    .map_or(false, |name| name.to_string_lossy() == entry)
+        })
+    }
+}
+
+impl LodIterator for FrameListIterator {
+    fn all(&self) -> &Vec<String> {
+        &self.inner
+    }
+
+    fn all_mut(&mut self) -> &mut Vec<String> {
+        &mut self.inner
+    }
+
+    fn indices(&self) -> Vec<usize> {
+        self.indices.clone()
+    }
+
+    fn clear_indices(&mut self) {
+        self.indices.clear();
+    }
+
+    fn initialize(&mut self) {
+        let mut indices: Vec<usize> = self
+            .entries
+            .iter()
+            .filter_map(|e| self.resolve(e))
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        self.indices = indices;
+    }
+
+    fn first_lod(&self) -> usize {
+        self.first_lod
+    }
+
+    fn set_first_lod(
+        &mut self,
+        first_lod: usize,
+    ) {
+        self.first_lod = first_lod;
+    }
+
+    fn next_lod(&self) -> Option<Box<dyn LodIterator>> {
+        None
+    }
+
+    fn box_clone(&self) -> Box<dyn LodIterator> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indices_and_file_names() {
+        let files = vec![
+            String::from("run/fluid_0.ply"),
+            String::from("run/fluid_1.ply"),
+            String::from("run/fluid_2.ply"),
+        ];
+        let entries = FrameListIterator::parse("# comment\n2\n\nfluid_0.ply\n7\nmissing.ply\n");
+        let iterator = FrameListIterator::new(files, entries, 100);
+        assert_eq!(iterator.indices(), vec![0, 2]);
+        assert_eq!(iterator.get_lods(), vec![2]);
+    }
+}
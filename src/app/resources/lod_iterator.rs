@@ -0,0 +1,223 @@
+use std::{fmt, str::FromStr};
+
+use bevy::prelude::warn;
+use bevy_inspector_egui::Inspectable;
+use serde::*;
+
+use super::{
+    file_sequence::compare_frames, lod_fixed_stride_iterator::FixedStrideIterator,
+    lod_frame_list_iterator::FrameListIterator, lod_midpoint_iterator::MidpointIterator,
+    lod_window_iterator::WindowIterator,
+};
+
+/// Chooses which files of a dataset to load, at increasing levels of detail (LOD)
+pub trait LodIterator: Send + Sync {
+    /// Every file, regardless of LOD
+    fn all(&self) -> &Vec<String>;
+    fn all_mut(&mut self) -> &mut Vec<String>;
+    /// Indices into `all()` of the files at this LOD
+    fn indices(&self) -> Vec<usize>;
+    fn clear_indices(&mut self);
+    /// (Re)computes the indices of the first LOD
+    fn initialize(&mut self);
+    fn first_lod(&self) -> usize;
+    fn set_first_lod(
+        &mut self,
+        first_lod: usize,
+    );
+    fn next_lod(&self) -> Option<Box<dyn LodIterator>>;
+    fn box_clone(&self) -> Box<dyn LodIterator>;
+
+    fn get_lods(&self) -> Vec<usize> {
+        let mut lods = Vec::new();
+        if self.len() > 0 {
+            lods.push(self.len());
+            let mut next_lod = self.next_lod();
+            while let Some(lod) = next_lod {
+                lods.push(lod.len());
+                next_lod = lod.next_lod();
+            }
+        }
+        lods
+    }
+
+    fn highest_lod(&self) -> Box<dyn LodIterator> {
+        let mut iterator = self.box_clone();
+        while let Some(next_lod) = iterator.next_lod() {
+            iterator = next_lod;
+        }
+        iterator
+    }
+
+    /// This will return the index from within the highest LOD
+    /// i.e. we have loaded 10 files, but their indexes in the highest LOD are [0, 10, 20, 30...]
+    fn full_index_from_lod_index(
+        &self,
+        index: usize,
+    ) -> usize {
+        self.indices()[index]
+    }
+
+    /// The index of `file` within every file
+    fn full_index_of(
+        &self,
+        file: &str,
+    ) -> Option<usize> {
+        self.all().iter().position(|f| f == file)
+    }
+
+    fn is_saturated(&self) -> bool {
+        self.len() == self.all().len()
+    }
+
+    fn len(&self) -> usize {
+        self.indices().len()
+    }
+
+    /// The files at this LOD, in order
+    fn files(&self) -> Vec<String> {
+        let all = self.all();
+        self.indices()
+            .iter()
+            .filter_map(|&idx| all.get(idx))
+            .cloned()
+            .collect()
+    }
+
+    fn contains(
+        &self,
+        item: &str,
+    ) -> bool {
+        self.all().iter().any(|f| f == item)
+    }
+
+    fn push(
+        &mut self,
+        item: String,
+    ) {
+        self.all_mut().push(item)
+    }
+
+    /// In frame order, see `compare_frames`
+    fn sort(&mut self) {
+        self.all_mut().sort_by(|a, b| compare_frames(a, b));
+    }
+
+    fn clear(&mut self) {
+        self.clear_indices();
+        self.all_mut().clear();
+    }
+}
+
+impl Clone for Box<dyn LodIterator> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// Which frames each LOD loads. The variants aren't doc commented, Inspectable's derive rejects
+/// attributes on variants and doc comments are attributes
+#[derive(Inspectable, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LodStrategy {
+    // Spread evenly over the dataset, filling in the midpoints at each LOD
+    Midpoint,
+    // Every nth frame, halving n at each LOD
+    FixedStride,
+    // A contiguous run of frames from a start frame, doubling in length at each LOD
+    Window,
+    // The frames listed in a file
+    FrameList,
+}
+
+impl Default for LodStrategy {
+    fn default() -> Self {
+        LodStrategy::Midpoint
+    }
+}
+
+impl FromStr for LodStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "midpoint" => Ok(LodStrategy::Midpoint),
+            "stride" => Ok(LodStrategy::FixedStride),
+            "window" => Ok(LodStrategy::Window),
+            "list" => Ok(LodStrategy::FrameList),
+            _ => Err(format!(
+                "unknown lod strategy {}, expected one of midpoint, stride, window, list",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LodStrategy {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        let name = match self {
+            LodStrategy::Midpoint => "midpoint",
+            LodStrategy::FixedStride => "stride",
+            LodStrategy::Window => "window",
+            LodStrategy::FrameList => "list",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Inspectable, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LodOptions {
+    pub strategy: LodStrategy,
+    /// FixedStride: the stride of the first LOD, doubled while it has more frames than the
+    /// initial LOD
+    #[inspectable(min = 1)]
+    pub stride: usize,
+    /// Window: the frame the window starts at
+    pub window_start: usize,
+    /// FrameList: a file with a frame index, or file name, per line
+    pub frame_list: String,
+}
+
+impl Default for LodOptions {
+    fn default() -> Self {
+        Self {
+            strategy: LodStrategy::Midpoint,
+            stride: 10,
+            window_start: 0,
+            frame_list: String::new(),
+        }
+    }
+}
+
+impl LodOptions {
+    pub fn iterator(
+        &self,
+        files: Vec<String>,
+        first_lod: usize,
+    ) -> Box<dyn LodIterator> {
+        match self.strategy {
+            LodStrategy::Midpoint => Box::new(MidpointIterator::new(files, first_lod)),
+            LodStrategy::FixedStride => Box::new(FixedStrideIterator::new(
+                files,
+                self.stride.max(1),
+                first_lod,
+            )),
+            LodStrategy::Window => {
+                Box::new(WindowIterator::new(files, self.window_start, first_lod))
+            }
+            LodStrategy::FrameList => {
+                let entries = match std::fs::read_to_string(&self.frame_list) {
+                    Ok(contents) => FrameListIterator::parse(&contents),
+                    Err(e) => {
+                        warn!("Couldn't read frame list {}: {}", self.frame_list, e);
+                        Vec::new()
+                    }
+                };
+                Box::new(FrameListIterator::new(files, entries, first_lod))
+            }
+        }
+    }
+}
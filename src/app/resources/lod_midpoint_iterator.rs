@@ -9,6 +9,8 @@
 // let lod_2 = lod_1.next_lod();
 // assert_eq!(lod_1.len(), 19 + 18);
 
+use super::lod_iterator::LodIterator;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct MidpointIterator<T>
 where
@@ -189,6 +191,47 @@ where
     }
 }
 
+impl LodIterator for MidpointIterator<String> {
+    fn all(&self) -> &Vec<String> {
+        &self.inner
+    }
+
+    fn all_mut(&mut self) -> &mut Vec<String> {
+        &mut self.inner
+    }
+
+    fn indices(&self) -> Vec<usize> {
+        self.indices.clone()
+    }
+
+    fn clear_indices(&mut self) {
+        MidpointIterator::clear_indices(self)
+    }
+
+    fn initialize(&mut self) {
+        MidpointIterator::initialize(self)
+    }
+
+    fn first_lod(&self) -> usize {
+        self.first_lod
+    }
+
+    fn set_first_lod(
+        &mut self,
+        first_lod: usize,
+    ) {
+        self.first_lod = first_lod;
+    }
+
+    fn next_lod(&self) -> Option<Box<dyn LodIterator>> {
+        MidpointIterator::next_lod(self).map(|lod| Box::new(lod) as Box<dyn LodIterator>)
+    }
+
+    fn box_clone(&self) -> Box<dyn LodIterator> {
+        Box::new(self.clone())
+    }
+}
+
 impl<T> Iterator for MidpointIterator<T>
 where
     T: Clone,
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use super::{file_sequence::compare_frames, lod_iterator::LodIterator};
+
+// Tier Iterator
+// Loads the files of a dataset packaged in tiers (run-1.zip, run-2.zip...), a tier more at each
+// LOD. The first LOD has as many tiers as fit within `first_lod` files, at least one. Files that
+// aren't in any tier are always loaded:
+// ```
+// let iterator = TierIterator::new(files, tier_of, 3);
+// assert_eq!(iterator.get_lods(), [tier_1, tier_1 + tier_2, ...]);
+// ```
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TierIterator {
+    inner: Vec<String>,
+    /// the tier of each file, counting from 0
+    tier_of: HashMap<String, usize>,
+    tiers: usize,
+    level: usize,
+    first_lod: usize,
+}
+
+impl TierIterator {
+    pub fn new(
+        inner: Vec<String>,
+        tier_of: HashMap<String, usize>,
+        first_lod: usize,
+    ) -> Self {
+        let tiers = tier_of.values().max().map_or(0, |&max| max + 1);
+        let mut me = Self {
+            inner,
+            tier_of,
+            tiers,
+            level: 0,
+            first_lod,
+        };
+        me.initialize();
+        me
+    }
+
+    /// Files loaded with the tiers up to `level`
+    fn files_at(
+        &self,
+        level: usize,
+    ) -> usize {
+        self.inner
+            .iter()
+            .filter(|file| self.tier_of.get(*file).map_or(true, |&t| t <= level))
+            .count()
+    }
+}
+
+impl LodIterator for TierIterator {
+    fn all(&self) -> &Vec<String> {
+        &self.inner
+    }
+
+    fn all_mut(&mut self) -> &mut Vec<String> {
+        &mut self.inner
+    }
+
+    fn indices(&self) -> Vec<usize> {
+        self.inner
+            .iter()
+            .enumerate()
+            .filter(|(_, file)| self.tier_of.get(*file).map_or(true, |&t| t <= self.level))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    fn clear_indices(&mut self) {
+        self.level = 0;
+    }
+
+    fn initialize(&mut self) {
+        self.level = 0;
+        while self.level + 1 < self.tiers && self.files_at(self.level + 1) <= self.first_lod {
+            self.level += 1;
+        }
+    }
+
+    fn first_lod(&self) -> usize {
+        self.first_lod
+    }
+
+    fn set_first_lod(
+        &mut self,
+        first_lod: usize,
+    ) {
+        self.first_lod = first_lod;
+    }
+
+    fn next_lod(&self) -> Option<Box<dyn LodIterator>> {
+        if self.level + 1 >= self.tiers {
+            return None;
+        }
+        let mut next = self.clone();
+        next.level = self.level + 1;
+        Some(Box::new(next))
+    }
+
+    fn box_clone(&self) -> Box<dyn LodIterator> {
+        Box::new(self.clone())
+    }
+
+    /// By the name of each file within its archive, so frames of every tier interleave
+    fn sort(&mut self) {
+        self.inner
+            .sort_by(|a, b| compare_frames(entry_name(a), entry_name(b)));
+    }
+}
+
+/// i.e. dam/fluid_0001.ply for runs/dam-1.zip/dam/fluid_0001.ply
+fn entry_name(file: &str) -> &str {
+    let lowercase = file.to_ascii_lowercase();
+    match lowercase.find(".zip/") {
+        Some(end) => &file[end + ".zip/".len()..],
+        None => file,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_a_tier_per_lod() {
+        let mut files: Vec<String> = (0..8)
+            .map(|i| format!("run-{}.zip/{}.ply", i % 3 + 1, i))
+            .collect();
+        let tier_of: HashMap<String, usize> = files
+            .iter()
+            .map(|f| (f.clone(), f[4..5].parse::<usize>().unwrap() - 1))
+            .collect();
+        files.push(String::from("loose.ply"));
+        let mut iterator = TierIterator::new(files.clone(), tier_of.clone(), 4);
+        iterator.sort();
+
+        assert_eq!(
+            iterator.all()[..3],
+            ["run-1.zip/0.ply", "run-2.zip/1.ply", "run-3.zip/2.ply"]
+        );
+        assert_eq!(iterator.get_lods(), vec![4, 7, 9]);
+        assert_eq!(iterator.full_index_from_lod_index(1), 3);
+        assert!(iterator.highest_lod().is_saturated());
+
+        // as many tiers as fit within the first LOD
+        assert_eq!(
+            TierIterator::new(files.clone(), tier_of.clone(), 8).get_lods(),
+            vec![7, 9]
+        );
+        assert_eq!(
+            TierIterator::new(files, tier_of, 1).get_lods(),
+            vec![4, 7, 9]
+        );
+    }
+}
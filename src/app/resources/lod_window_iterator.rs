@@ -0,0 +1,112 @@
+use super::lod_iterator::LodIterator;
+
+// Window Iterator
+// Loads a contiguous run of `first_lod` files from `start`, doubling the length of the run at each
+// LOD, until it reaches the last file:
+// ```
+// let iterator = WindowIterator::new(files_0_to_99, 50, 10);
+// assert_eq!(iterator.get_lods(), [10, 20, 40, 50]);
+// ```
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowIterator {
+    inner: Vec<String>,
+    start: usize,
+    length: usize,
+    first_lod: usize,
+}
+
+impl WindowIterator {
+    pub fn new(
+        inner: Vec<String>,
+        start: usize,
+        first_lod: usize,
+    ) -> Self {
+        let mut me = Self {
+            inner,
+            start,
+            length: 0,
+            first_lod,
+        };
+        me.initialize();
+        me
+    }
+
+    fn end(&self) -> usize {
+        (self.start + self.length).min(self.inner.len())
+    }
+}
+
+impl LodIterator for WindowIterator {
+    fn all(&self) -> &Vec<String> {
+        &self.inner
+    }
+
+    fn all_mut(&mut self) -> &mut Vec<String> {
+        &mut self.inner
+    }
+
+    fn indices(&self) -> Vec<usize> {
+        (self.start.min(self.end())..self.end()).collect()
+    }
+
+    fn clear_indices(&mut self) {
+        self.length = 0;
+    }
+
+    fn initialize(&mut self) {
+        self.length = self.first_lod.max(1);
+    }
+
+    fn first_lod(&self) -> usize {
+        self.first_lod
+    }
+
+    fn set_first_lod(
+        &mut self,
+        first_lod: usize,
+    ) {
+        self.first_lod = first_lod;
+    }
+
+    fn next_lod(&self) -> Option<Box<dyn LodIterator>> {
+        if self.is_saturated() {
+            return None;
+        }
+        let mut next = self.clone();
+        next.length = self.length * 2;
+        Some(Box::new(next))
+    }
+
+    fn box_clone(&self) -> Box<dyn LodIterator> {
+        Box::new(self.clone())
+    }
+
+    /// Saturated once the window reaches the last file
+    fn is_saturated(&self) -> bool {
+        self.start + self.length >= self.inner.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("{}.ply", i)).collect()
+    }
+
+    #[test]
+    fn test_window_from_start_frame() {
+        let iterator = WindowIterator::new(files(100), 50, 10);
+        assert_eq!(iterator.full_index_from_lod_index(0), 50);
+        assert_eq!(iterator.get_lods(), vec![10, 20, 40, 50]);
+    }
+
+    #[test]
+    fn test_start_past_the_end() {
+        let iterator = WindowIterator::new(files(10), 20, 10);
+        assert_eq!(iterator.len(), 0);
+        assert!(iterator.next_lod().is_none());
+    }
+}
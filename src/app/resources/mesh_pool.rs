@@ -2,8 +2,13 @@ use std::time::Duration;
 
 use crate::app::loading::MeshAssets;
 use crate::app::resources::actions::FrameDirection;
+use crate::support::loader_fu::attributes::AttributeMapping;
+use crate::support::loader_fu::classes::ParticleClasses;
+use crate::support::loader_fu::colormap::Coloring;
 use crate::support::loader_fu::features::Features;
-use crate::support::loader_fu::render::{FeatureAwareRenderer, PointRenderOptions, RenderCache};
+use crate::support::loader_fu::render::{
+    FeatureAwareRenderer, PointRenderOptions, RenderCache, Surface,
+};
 use bevy::prelude::*;
 
 #[derive(Clone)]
@@ -15,8 +20,23 @@ pub struct MeshPool {
     pub current_mesh_index: usize,
     pub have_displayed: bool,
     pub sample_size: usize,
+    /// from the dataset's manifest, applied to every frame
+    pub dataset_transform: Transform,
+    /// draw meshes with their vertex colors, when they have them
+    pub vertex_colors: bool,
+    /// draw particles as a few batched meshes, rather than an entity each
+    pub batch_points: bool,
+    /// which of a frame's arrays are its positions, velocities...
+    pub attribute_mapping: AttributeMapping,
+    /// what frames are colored by, and the range held across the sequence
+    pub coloring: Option<Coloring>,
+    /// each particle class's color, and whether it is shown
+    pub classes: ParticleClasses,
     current_fluid_entities: Option<Vec<Entity>>,
     current_mesh_handle: Option<Handle<Mesh>>,
+    /// how the current entity draws its surface, which the next frame's surface can be swapped
+    /// onto when it is drawn the same way
+    current_surface: Option<Surface>,
     needs_update: bool,
     currently_advanced: Duration,
     previous_mesh_size: usize,
@@ -39,11 +59,18 @@ impl MeshPool {
             current_mesh_index: 0,
             current_fluid_entities: None,
             current_mesh_handle: None,
+            current_surface: None,
             needs_update: true,
             frame_direction: Default::default(),
             sampled_indices: Vec::new(),
             previous_mesh_size: 0,
             sample_size,
+            dataset_transform: Transform::identity(),
+            vertex_colors: false,
+            batch_points: true,
+            attribute_mapping: AttributeMapping::default(),
+            coloring: None,
+            classes: ParticleClasses::default(),
         }
     }
 
@@ -121,14 +148,17 @@ impl MeshPool {
                 renderer.despawn(commands, meshes, current_entities.to_vec());
             }
         }
+        self.current_fluid_entities = None;
+        self.current_surface = None;
     }
 
+    /// Shows the current frame, on the entity already showing a surface when it can be
     pub fn spawn_mesh(
         &mut self,
         fluids: &MeshAssets,
         water_material: Handle<StandardMaterial>,
         commands: &mut Commands,
-        meshes: &Assets<Mesh>,
+        meshes: &mut Assets<Mesh>,
         render_cache: &RenderCache,
         render_style: PointRenderOptions,
     ) {
@@ -151,18 +181,42 @@ impl MeshPool {
                 }
             }
 
-            let renderer = FeatureAwareRenderer::new(new_fluid.1.clone());
-            self.current_fluid_entities = Some(renderer.spawn(
-                commands,
-                meshes,
-                water_material,
-                render_style,
-                render_cache,
-                &self.sampled_indices,
-            ));
-            if self.current_fluid_entities.is_some() {
-                self.current_mesh_handle = Some(new_fluid.1.clone());
+            let renderer = FeatureAwareRenderer::new(new_fluid.1.clone())
+                .with_vertex_colors(self.vertex_colors)
+                .with_batched_points(self.batch_points)
+                .with_attribute_mapping(self.attribute_mapping.clone())
+                .with_coloring(self.coloring.clone())
+                .with_classes(self.classes.clone());
+            // a surface following a surface drawn the same way keeps its entity, only its mesh
+            // is swapped
+            let surface = renderer.surface(meshes, render_cache);
+            let swappable = match (self.current_fluid_entities.as_deref(), self.current_surface) {
+                (Some(&[entity]), Some(current)) if surface == Some(current) => Some(entity),
+                _ => None,
+            };
+            if let (Some(entity), Some(surface)) = (swappable, surface) {
+                renderer.swap(
+                    commands,
+                    meshes,
+                    entity,
+                    water_material,
+                    surface,
+                    self.dataset_transform,
+                );
+            } else {
+                self.despawn_mesh(commands, meshes);
+                self.current_fluid_entities = Some(renderer.spawn(
+                    commands,
+                    meshes,
+                    water_material,
+                    render_style,
+                    render_cache,
+                    &self.sampled_indices,
+                    self.dataset_transform,
+                ));
+                self.current_surface = surface;
             }
+            self.current_mesh_handle = Some(new_fluid.1.clone());
             if let Some(new_fluid) = self.current_mesh(fluids) {
                 if let Some(mesh) = meshes.get(new_fluid.1.clone()) {
                     self.update_previous_mesh_size(mesh);
@@ -177,18 +231,22 @@ impl MeshPool {
         meshes: &Assets<Mesh>,
     ) {
         self.despawn_mesh(commands, meshes);
+        self.current_fluid_entities = None;
         self.current_mesh_handle = None;
         self.current_mesh_index = 0;
         self.sampled_indices.clear();
+        // show the first frame of whatever is loaded next
+        self.have_displayed = false;
     }
 
+    /// Respawns the current frame, i.e. after how it is drawn has changed
     pub fn redraw(
         &mut self,
         commands: &mut Commands,
         fluids: &MeshAssets,
         water_material: Handle<StandardMaterial>,
         render_cache: &RenderCache,
-        meshes: &Assets<Mesh>,
+        meshes: &mut Assets<Mesh>,
         render_style: PointRenderOptions,
     ) {
         self.despawn_mesh(commands, meshes);
@@ -209,7 +267,7 @@ impl MeshPool {
         water_material: Handle<StandardMaterial>,
         delta: Duration,
         render_cache: &RenderCache,
-        meshes: &Assets<Mesh>,
+        meshes: &mut Assets<Mesh>,
         render_style: PointRenderOptions,
         sample_size: usize,
     ) {
@@ -221,7 +279,6 @@ impl MeshPool {
         self.currently_advanced = Duration::default();
 
         if fluids.loaded.len() > 0 {
-            self.despawn_mesh(commands, meshes);
             self.move_in_frame_direction();
 
             self.spawn_mesh(
@@ -0,0 +1,149 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::support::asset_io::asset_path;
+
+pub const COLLECTION_EXTENSION: &str = "pvd";
+
+// ParaView Collection
+// A .pvd file listing a run's .vtu/.vtp files, with the simulated time of each:
+// ```
+// <VTKFile type="Collection" version="0.1">
+//   <Collection>
+//     <DataSet timestep="0.0" part="0" file="fluid/fluid_0000.vtu"/>
+//     <DataSet timestep="0.0125" part="0" file="fluid/fluid_0001.vtu"/>
+//   </Collection>
+// </VTKFile>
+// ```
+// Files are relative to the .pvd. Only the first part of each timestep is shown
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectionFrame {
+    pub file: PathBuf,
+    pub time: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParaViewCollection {
+    /// In time order
+    pub frames: Vec<CollectionFrame>,
+}
+
+#[derive(Debug)]
+pub enum CollectionError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, String),
+}
+
+impl fmt::Display for CollectionError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            CollectionError::Io(path, e) => write!(f, "couldn't read {}: {}", path.display(), e),
+            CollectionError::Parse(path, e) => {
+                write!(f, "couldn't parse {}: {}", path.display(), e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CollectionError {}
+
+pub fn is_collection(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |ext| ext == COLLECTION_EXTENSION)
+}
+
+impl ParaViewCollection {
+    pub fn read(path: &Path) -> Result<Self, CollectionError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| CollectionError::Io(path.to_path_buf(), e))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::parse(&contents, dir).map_err(|e| CollectionError::Parse(path.to_path_buf(), e))
+    }
+
+    pub fn parse(
+        contents: &str,
+        dir: &Path,
+    ) -> Result<Self, String> {
+        let document = roxmltree::Document::parse(contents).map_err(|e| e.to_string())?;
+        let collection = document
+            .root_element()
+            .children()
+            .find(|n| n.has_tag_name("Collection"))
+            .ok_or_else(|| String::from("missing <Collection>"))?;
+
+        let mut data_sets = Vec::new();
+        for data_set in collection.children().filter(|n| n.has_tag_name("DataSet")) {
+            let file = data_set
+                .attribute("file")
+                .ok_or_else(|| String::from("<DataSet> without a file"))?;
+            let time = data_set.attribute("timestep").and_then(|t| t.parse().ok());
+            let part: u32 = data_set
+                .attribute("part")
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(0);
+            data_sets.push((part, dir.join(file), time));
+        }
+
+        let first_part = data_sets.iter().map(|(part, _, _)| *part).min();
+        let mut frames: Vec<CollectionFrame> = data_sets
+            .into_iter()
+            .filter(|(part, _, _)| Some(*part) == first_part)
+            .map(|(_, file, time)| CollectionFrame { file, time })
+            .collect();
+        // without every time, the listed order is all there is
+        if frames.iter().all(|f| f.time.is_some()) {
+            frames.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+        }
+        Ok(Self { frames })
+    }
+
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.frames.iter().map(|f| f.file.clone()).collect()
+    }
+
+    /// Times keyed by asset path, as the LoadManager knows frames
+    pub fn frame_times(&self) -> HashMap<String, f64> {
+        self.frames
+            .iter()
+            .filter_map(|f| Some((asset_path(&f.file).ok()?, f.time?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let collection = ParaViewCollection::parse(
+            r#"<?xml version="1.0"?>
+<VTKFile type="Collection" version="0.1">
+  <Collection>
+    <DataSet timestep="0.5" part="0" file="fluid_1.vtu"/>
+    <DataSet timestep="0.5" part="1" file="boundary_1.vtp"/>
+    <DataSet timestep="0" part="0" file="fluid_0.vtu"/>
+  </Collection>
+</VTKFile>"#,
+            Path::new("run"),
+        )
+        .unwrap();
+        assert_eq!(
+            collection.files(),
+            vec![
+                PathBuf::from("run/fluid_0.vtu"),
+                PathBuf::from("run/fluid_1.vtu")
+            ]
+        );
+        assert_eq!(collection.frames[1].time, Some(0.5));
+        assert!(is_collection(Path::new("run/fluid.pvd")));
+    }
+}
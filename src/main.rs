@@ -8,13 +8,19 @@ pub mod app;
 pub mod support;
 
 use app::{AppOptions, GamePlugin};
+use bevy::asset::AssetPlugin;
 use bevy::prelude::{App, ClearColor, Color, Msaa, WindowDescriptor};
 use bevy::DefaultPlugins;
 use structopt::StructOpt;
+use support::asset_io::DatasetAssetIoPlugin;
 
 fn main() {
     let options = AppOptions::from_args();
     let mut app = App::build();
+    // read by DatasetAssetIoPlugin
+    if let Some(http_cache) = options.http_cache() {
+        app.insert_resource(http_cache);
+    }
     app.insert_resource(options);
     app.insert_resource(Msaa { samples: 4 })
         .insert_resource(ClearColor(Color::rgb(0.4, 0.4, 0.4)))
@@ -24,7 +30,10 @@ fn main() {
             title: "Mesh Ripper".to_string(), // ToDo
             ..Default::default()
         })
-        .add_plugins(DefaultPlugins)
+        // load datasets from anywhere on disk, not just assets/
+        .add_plugins_with(DefaultPlugins, |group| {
+            group.add_before::<AssetPlugin, _>(DatasetAssetIoPlugin)
+        })
         .add_plugin(GamePlugin);
 
     #[cfg(target_arch = "wasm32")]
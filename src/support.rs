@@ -1 +1,7 @@
+pub mod archive;
+pub mod asset_io;
+pub mod compression;
+pub mod http_cache;
 pub mod loader_fu;
+pub mod loaders;
+pub mod mesh_cache;
@@ -0,0 +1,115 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+use zip::{result::ZipError, ZipArchive};
+
+// Archives
+// A .zip of frames is a dataset, its frames loaded straight from the archive. A frame's asset path
+// is the archive's path followed by the entry's name, i.e. runs/dam-1.zip/dam/fluid_0001.ply.
+// Several numbered archives (dam-1.zip, dam-2.zip...) are one dataset packaged in tiers, as
+// script/package-run writes them, each holding the frames one more LOD adds
+
+pub const ARCHIVE_EXTENSION: &str = "zip";
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(io::Error),
+    Zip(ZipError),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            ArchiveError::Io(e) => write!(f, "couldn't read archive: {}", e),
+            ArchiveError::Zip(e) => write!(f, "couldn't read archive: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+impl From<io::Error> for ArchiveError {
+    fn from(e: io::Error) -> Self {
+        ArchiveError::Io(e)
+    }
+}
+
+impl From<ZipError> for ArchiveError {
+    fn from(e: ZipError) -> Self {
+        ArchiveError::Zip(e)
+    }
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| {
+        ext.to_string_lossy()
+            .eq_ignore_ascii_case(ARCHIVE_EXTENSION)
+    })
+}
+
+/// The names of the files in an archive, sorted
+pub fn entries(archive: &Path) -> Result<Vec<String>, ArchiveError> {
+    let archive = ZipArchive::new(File::open(archive)?)?;
+    let mut entries: Vec<String> = archive
+        .file_names()
+        .filter(|name| !name.ends_with('/'))
+        .map(String::from)
+        .collect();
+    entries.sort();
+    Ok(entries)
+}
+
+/// The contents of one file of an archive, only reading that file
+pub fn read_entry(
+    archive: &Path,
+    entry: &str,
+) -> Result<Vec<u8>, ArchiveError> {
+    let mut archive = ZipArchive::new(File::open(archive)?)?;
+    let mut file = archive.by_name(entry)?;
+    let mut contents = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::{FileOptions, ZipWriter};
+
+    #[test]
+    fn test_entries() {
+        let path = std::env::temp_dir().join("mesh-ripper-test-archive.zip");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        zip.add_directory("dam/", FileOptions::default()).unwrap();
+        for (name, contents) in [
+            ("dam/fluid_0001.ply", "ply 1"),
+            ("dam/fluid_0002.ply", "ply 2"),
+        ]
+        .iter()
+        {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        assert_eq!(
+            entries(&path).unwrap(),
+            vec!["dam/fluid_0001.ply", "dam/fluid_0002.ply"]
+        );
+        assert_eq!(read_entry(&path, "dam/fluid_0002.ply").unwrap(), b"ply 2");
+        assert!(matches!(
+            read_entry(&path, "dam/mr-dataset.ron"),
+            Err(ArchiveError::Zip(ZipError::FileNotFound))
+        ));
+        assert!(is_archive(&path));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,498 @@
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+use super::{
+    archive::{is_archive, read_entry, ArchiveError},
+    compression::Compression,
+    http_cache::{HttpCache, HttpError},
+};
+use bevy::{
+    asset::{AssetIo, AssetIoError, AssetServer},
+    prelude::*,
+    tasks::{IoTaskPool, TaskPool, TaskPoolBuilder},
+    utils::BoxedFuture,
+};
+
+/// The directory bevy's default AssetIo is rooted at
+pub const ASSETS_DIR: &str = "assets";
+
+/// Paths under this are read from the path after `mr-reload/<generation>/(abs|rel)/`, so that the
+/// AssetServer loads a file that changed on disk again, instead of handing back what it has
+const RELOAD_DIR: &str = "mr-reload";
+
+/// Paths under this are read from `mr-decompress/<compression>/(abs|rel)/` followed by the path
+/// without its compression extension, plus that extension, and decompressed. The AssetServer picks
+/// a loader by the last extension, which is then the format of the frame
+const DECOMPRESS_DIR: &str = "mr-decompress";
+
+/// Threads frames of remote datasets are fetched on
+const FETCH_THREADS: usize = 4;
+
+#[derive(Debug)]
+pub enum DatasetPathError {
+    NotFound(PathBuf),
+    Io(PathBuf, std::io::Error),
+    BadGlob(String, String),
+}
+
+impl fmt::Display for DatasetPathError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            DatasetPathError::NotFound(path) => {
+                write!(f, "path does not exist: {}", path.to_string_lossy())
+            }
+            DatasetPathError::Io(path, e) => {
+                write!(f, "could not read {}: {}", path.to_string_lossy(), e)
+            }
+            DatasetPathError::BadGlob(glob, e) => write!(f, "invalid glob {}: {}", glob, e),
+        }
+    }
+}
+
+impl std::error::Error for DatasetPathError {}
+
+/// Maps a path given on the command line, or found while walking a dataset dir, to a path the
+/// AssetServer can load.
+/// Paths within `assets/` stay relative to it (as before), everything else is made absolute,
+/// and is read directly from the filesystem by `DatasetAssetIo`
+pub fn asset_path<P: AsRef<Path>>(path: P) -> Result<String, DatasetPathError> {
+    let path = path.as_ref();
+    if path.is_relative() {
+        if let Ok(stripped) = path.strip_prefix(ASSETS_DIR) {
+            return Ok(stripped.to_string_lossy().to_string());
+        }
+    }
+
+    match path.canonicalize() {
+        Ok(absolute) => Ok(absolute.to_string_lossy().to_string()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err(DatasetPathError::NotFound(path.to_path_buf()))
+        }
+        Err(e) => Err(DatasetPathError::Io(path.to_path_buf(), e)),
+    }
+}
+
+/// `<dir>/<key>/(abs|rel)/<asset_path>`, an asset path that `DatasetAssetIo` reads specially.
+/// An absolute path with a prefix, i.e. a drive on Windows, is `<dir>/<key>/drive/<prefix>/...`
+/// with the prefix percent encoded, so it is one component of the path
+fn virtual_path(
+    dir: &str,
+    key: &str,
+    asset_path: &str,
+) -> String {
+    let path = Path::new(asset_path);
+    if path.is_absolute() {
+        let mut prefix = None;
+        let mut relative = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::Prefix(p) => prefix = Some(p.as_os_str().to_string_lossy().to_string()),
+                Component::Normal(c) => relative.push(c),
+                _ => {}
+            }
+        }
+        match prefix {
+            Some(prefix) => format!(
+                "{}/{}/drive/{}/{}",
+                dir,
+                key,
+                percent_encode(&prefix),
+                relative.display()
+            ),
+            None => format!("{}/{}/abs/{}", dir, key, relative.display()),
+        }
+    } else {
+        format!("{}/{}/rel/{}", dir, key, asset_path)
+    }
+}
+
+/// The key & asset path a `virtual_path` under `dir` was made from
+fn from_virtual_path(
+    dir: &str,
+    path: &Path,
+) -> Option<(String, PathBuf)> {
+    let mut components = path.components();
+    if components.next()?.as_os_str() != dir {
+        return None;
+    }
+    let key = components.next()?.as_os_str().to_string_lossy().to_string();
+    match components.next()?.as_os_str().to_str()? {
+        "abs" => Some((key, Path::new("/").join(components.as_path()))),
+        "rel" => Some((key, components.as_path().to_path_buf())),
+        "drive" => {
+            let prefix = percent_decode(components.next()?.as_os_str().to_str()?)?;
+            let root = format!("{}{}", prefix, std::path::MAIN_SEPARATOR);
+            Some((key, Path::new(&root).join(components.as_path())))
+        }
+        _ => None,
+    }
+}
+
+/// Escapes everything but letters, digits, `-`, `_` and `.`, i.e. C: => C%3A
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut rest = text.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// A path the AssetServer hasn't loaded yet, that `DatasetAssetIo` reads `asset_path` from
+pub fn reload_path(
+    asset_path: &str,
+    generation: u64,
+) -> String {
+    virtual_path(RELOAD_DIR, &generation.to_string(), asset_path)
+}
+
+/// The asset path a `reload_path` was made from
+fn reloaded_path(path: &Path) -> Option<PathBuf> {
+    from_virtual_path(RELOAD_DIR, path).map(|(_, path)| path)
+}
+
+/// The path to load a frame by, which for a compressed file names the format underneath, i.e.
+/// mr-decompress/gz/rel/data/fluid_0001.ply for data/fluid_0001.ply.gz
+pub fn decompressed_path(asset_path: &str) -> String {
+    match Compression::of(Path::new(asset_path)) {
+        Some(compression) => {
+            let uncompressed = &asset_path[..asset_path.len() - compression.extension().len() - 1];
+            virtual_path(DECOMPRESS_DIR, compression.extension(), uncompressed)
+        }
+        None => asset_path.to_string(),
+    }
+}
+
+/// The compressed file & the path without compression a `decompressed_path` was made from
+fn compressed_path(path: &Path) -> Option<(PathBuf, PathBuf, Compression)> {
+    let (extension, uncompressed) = from_virtual_path(DECOMPRESS_DIR, path)?;
+    let compression = Compression::from_extension(&extension)?;
+    let mut compressed = uncompressed.clone().into_os_string();
+    compressed.push(".");
+    compressed.push(extension);
+    Some((PathBuf::from(compressed), uncompressed, compression))
+}
+
+/// The file on disk an asset path is read from, following reload and decompress paths as
+/// `DatasetAssetIo` does. None for an entry within an archive, which isn't a file of its own
+pub fn source_file(asset_path: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::from(asset_path);
+    if let Some(original) = reloaded_path(&path) {
+        path = original;
+    }
+    if let Some((compressed, _, _)) = compressed_path(&path) {
+        path = compressed;
+    }
+    if archive_entry(&path).is_some() {
+        return None;
+    }
+    if path.is_absolute() {
+        Some(path)
+    } else {
+        Some(Path::new(ASSETS_DIR).join(path))
+    }
+}
+
+/// Splits a path within an archive into the archive, where it is on disk, and the entry's name
+fn archive_entry(path: &Path) -> Option<(PathBuf, String)> {
+    // most paths aren't within an archive, which is cheap to tell without the filesystem
+    let within_archive = path
+        .parent()?
+        .components()
+        .any(|c| is_archive(Path::new(c.as_os_str())));
+    if !within_archive {
+        return None;
+    }
+    let mut archive = PathBuf::new();
+    let mut components = path.components();
+    while let Some(component) = components.next() {
+        archive.push(component);
+        if !is_archive(&archive) {
+            continue;
+        }
+        let on_disk = if archive.is_absolute() {
+            archive.clone()
+        } else {
+            Path::new(ASSETS_DIR).join(&archive)
+        };
+        if on_disk.is_file() {
+            let entry: Vec<String> = components
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect();
+            return Some((on_disk, entry.join("/")));
+        }
+    }
+    None
+}
+
+/// AssetIo that loads absolute paths straight from disk, frames from within archives, frames of
+/// remote datasets through the HttpCache, and hands everything else to the platform default
+/// AssetIo (rooted at `assets/`)
+pub struct DatasetAssetIo {
+    default_io: Box<dyn AssetIo>,
+    http_cache: Option<HttpCache>,
+    /// requests block until the file has been downloaded, so they are made here rather than on
+    /// the IoTaskPool, which every other load waits on
+    fetch_pool: Option<TaskPool>,
+}
+
+impl DatasetAssetIo {
+    pub fn new(
+        default_io: Box<dyn AssetIo>,
+        http_cache: Option<HttpCache>,
+    ) -> Self {
+        let fetch_pool = http_cache.as_ref().map(|_| {
+            TaskPoolBuilder::new()
+                .num_threads(FETCH_THREADS)
+                .thread_name(String::from("HTTP fetch"))
+                .build()
+        });
+        Self {
+            default_io,
+            http_cache,
+            fetch_pool,
+        }
+    }
+}
+
+impl AssetIo for DatasetAssetIo {
+    fn load_path<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> BoxedFuture<'a, Result<Vec<u8>, AssetIoError>> {
+        if let Some(original) = reloaded_path(path) {
+            return Box::pin(async move { self.load_path(&original).await });
+        }
+        if let Some((compressed, uncompressed, compression)) = compressed_path(path) {
+            return Box::pin(async move {
+                match self.load_path(&compressed).await {
+                    Ok(bytes) => compression.decompress(&bytes).map_err(AssetIoError::Io),
+                    // files loaders read beside the frame, i.e. the manifest, aren't compressed
+                    Err(AssetIoError::NotFound(_)) => self.load_path(&uncompressed).await,
+                    Err(e) => Err(e),
+                }
+            });
+        }
+        if let Some((archive, entry)) = archive_entry(path) {
+            return Box::pin(async move {
+                read_entry(&archive, &entry).map_err(|e| match e {
+                    // i.e. the manifest a loader looks for beside the frame
+                    ArchiveError::Zip(zip::result::ZipError::FileNotFound) => {
+                        AssetIoError::NotFound(path.to_path_buf())
+                    }
+                    ArchiveError::Io(e) => e.into(),
+                    e => AssetIoError::Io(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        e.to_string(),
+                    )),
+                })
+            });
+        }
+        if let Some((cache, url, fetch_pool)) = self
+            .http_cache
+            .as_ref()
+            .zip(self.fetch_pool.as_ref())
+            .and_then(|(cache, pool)| Some((cache.clone(), cache.url_of(path)?, pool)))
+        {
+            let fetch = fetch_pool.spawn(async move { cache.fetch(&url) });
+            return Box::pin(async move {
+                fetch.await.map_err(|e| match e {
+                    HttpError::NotFound(_) => AssetIoError::NotFound(path.to_path_buf()),
+                    HttpError::Io(e) => e.into(),
+                    e => AssetIoError::Io(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        e.to_string(),
+                    )),
+                })
+            });
+        }
+        if !path.is_absolute() {
+            return self.default_io.load_path(path);
+        }
+        Box::pin(async move {
+            std::fs::read(path).map_err(|e| {
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    AssetIoError::NotFound(path.to_path_buf())
+                } else {
+                    e.into()
+                }
+            })
+        })
+    }
+
+    fn read_directory(
+        &self,
+        path: &Path,
+    ) -> Result<Box<dyn Iterator<Item = PathBuf>>, AssetIoError> {
+        if !path.is_absolute() {
+            return self.default_io.read_directory(path);
+        }
+        let entries = std::fs::read_dir(path)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path());
+        Ok(Box::new(entries))
+    }
+
+    fn is_directory(
+        &self,
+        path: &Path,
+    ) -> bool {
+        if path.is_absolute() {
+            path.is_dir()
+        } else {
+            self.default_io.is_directory(path)
+        }
+    }
+
+    fn watch_path_for_changes(
+        &self,
+        path: &Path,
+    ) -> Result<(), AssetIoError> {
+        self.default_io.watch_path_for_changes(path)
+    }
+
+    fn watch_for_changes(&self) -> Result<(), AssetIoError> {
+        self.default_io.watch_for_changes()
+    }
+}
+
+/// Replaces the AssetServer with one backed by `DatasetAssetIo`, add before bevy's AssetPlugin
+pub struct DatasetAssetIoPlugin;
+
+impl Plugin for DatasetAssetIoPlugin {
+    fn build(
+        &self,
+        app: &mut AppBuilder,
+    ) {
+        let task_pool = app
+            .world()
+            .get_resource::<IoTaskPool>()
+            .expect("IoTaskPool resource not found")
+            .0
+            .clone();
+
+        let http_cache = app.world().get_resource::<HttpCache>().cloned();
+        let asset_io = DatasetAssetIo::new(
+            bevy::asset::create_platform_default_asset_io(app),
+            http_cache,
+        );
+
+        app.insert_resource(AssetServer::new(asset_io, task_pool));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assets_prefix_is_stripped() {
+        assert_eq!(
+            asset_path("assets/data/fluid_0001.ply").unwrap(),
+            "data/fluid_0001.ply"
+        );
+    }
+
+    #[test]
+    fn test_outside_assets_is_absolute() {
+        let path = asset_path("src").unwrap();
+        assert!(Path::new(&path).is_absolute());
+    }
+
+    #[test]
+    fn test_reload_paths() {
+        let path = reload_path("data/fluid_0001.ply", 3);
+        assert_eq!(path, "mr-reload/3/rel/data/fluid_0001.ply");
+        assert_eq!(
+            reloaded_path(Path::new(&path)),
+            Some(PathBuf::from("data/fluid_0001.ply"))
+        );
+        let path = reload_path("/runs/fluid_0001.ply", 4);
+        assert_eq!(
+            reloaded_path(Path::new(&path)),
+            Some(PathBuf::from("/runs/fluid_0001.ply"))
+        );
+        assert_eq!(reloaded_path(Path::new("data/fluid_0001.ply")), None);
+    }
+
+    #[test]
+    fn test_decompressed_paths() {
+        let path = decompressed_path("data/fluid_0001.ply.gz");
+        assert_eq!(path, "mr-decompress/gz/rel/data/fluid_0001.ply");
+        assert_eq!(
+            compressed_path(Path::new(&path)),
+            Some((
+                PathBuf::from("data/fluid_0001.ply.gz"),
+                PathBuf::from("data/fluid_0001.ply"),
+                Compression::Gzip
+            ))
+        );
+        // reloading a compressed frame goes through both
+        let path = reload_path(&decompressed_path("/runs/fluid_0001.vtk.xz"), 2);
+        let (compressed, _, _) =
+            compressed_path(&reloaded_path(Path::new(&path)).unwrap()).unwrap();
+        assert_eq!(compressed, PathBuf::from("/runs/fluid_0001.vtk.xz"));
+        assert_eq!(
+            decompressed_path("data/fluid_0001.ply"),
+            "data/fluid_0001.ply"
+        );
+    }
+
+    #[test]
+    fn test_source_file() {
+        let reloaded = reload_path(&decompressed_path("data/fluid_0001.ply.gz"), 3);
+        assert_eq!(
+            source_file(&reloaded),
+            Some(PathBuf::from("assets/data/fluid_0001.ply.gz"))
+        );
+        assert_eq!(
+            source_file("/sim/fluid_0001.ply"),
+            Some(PathBuf::from("/sim/fluid_0001.ply"))
+        );
+    }
+
+    #[test]
+    fn test_drive_prefix_is_kept() {
+        // what virtual_path makes of C:\runs\fluid_0001.ply on Windows
+        let path = Path::new("mr-reload/5/drive/C%3A/runs/fluid_0001.ply");
+        assert_eq!(
+            reloaded_path(path),
+            Some(PathBuf::from("C:/runs/fluid_0001.ply"))
+        );
+        assert_eq!(percent_encode(r"\\server\share"), "%5C%5Cserver%5Cshare");
+        assert_eq!(
+            percent_decode("%5C%5Cserver%5Cshare").as_deref(),
+            Some(r"\\server\share")
+        );
+        assert_eq!(percent_decode("C%3"), None);
+    }
+
+    #[test]
+    fn test_missing_path_errors() {
+        assert!(matches!(
+            asset_path("/does/not/exist/fluid.ply"),
+            Err(DatasetPathError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    io::{self, Read},
+    path::Path,
+};
+
+// Compression
+// Frames may be stored compressed, i.e. fluid_0001.ply.gz or fluid_0001.obj.zst. They are known
+// by the extension under the compression suffix, and decompressed by `DatasetAssetIo` as they are
+// read, so every loader sees the file as it was written
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    pub const ALL: [Compression; 3] = [Compression::Gzip, Compression::Zstd, Compression::Xz];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+            Compression::Xz => "xz",
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|c| extension.eq_ignore_ascii_case(c.extension()))
+    }
+
+    /// The compression of a file, by its extension
+    pub fn of(path: &Path) -> Option<Self> {
+        Self::from_extension(path.extension()?.to_str()?)
+    }
+
+    pub fn decompress(
+        &self,
+        bytes: &[u8],
+    ) -> io::Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        let mut input = bytes;
+        match self {
+            Compression::Gzip => {
+                flate2::read::MultiGzDecoder::new(input).read_to_end(&mut decompressed)?;
+            }
+            Compression::Zstd => {
+                ruzstd::StreamingDecoder::new(&mut input)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                    .read_to_end(&mut decompressed)?;
+            }
+            Compression::Xz => lzma_rs::xz_decompress(&mut input, &mut decompressed)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?,
+        }
+        Ok(decompressed)
+    }
+}
+
+/// The file name without its compression suffix, i.e. fluid_0001.ply for fluid_0001.ply.gz
+pub fn strip_compression(file_name: &str) -> &str {
+    match Compression::of(Path::new(file_name)) {
+        Some(compression) => &file_name[..file_name.len() - compression.extension().len() - 1],
+        None => file_name,
+    }
+}
+
+/// The extension of the format under any compression, i.e. ply for fluid_0001.ply.gz
+pub fn frame_extension(path: &Path) -> Option<String> {
+    let file_name = path.file_name()?.to_str()?;
+    Path::new(strip_compression(file_name))
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_suffixes() {
+        assert_eq!(strip_compression("fluid_0001.ply.gz"), "fluid_0001.ply");
+        assert_eq!(strip_compression("fluid_0001.obj.ZST"), "fluid_0001.obj");
+        assert_eq!(strip_compression("fluid_0001.ply"), "fluid_0001.ply");
+        assert_eq!(
+            frame_extension(Path::new("run/fluid_0001.vtk.xz")),
+            Some(String::from("vtk"))
+        );
+        assert_eq!(frame_extension(Path::new("run/fluid.gz")), None);
+    }
+
+    #[test]
+    fn test_gzip_round_trip() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"ply\nformat ascii 1.0\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(
+            Compression::Gzip.decompress(&compressed).unwrap(),
+            b"ply\nformat ascii 1.0\n"
+        );
+        assert!(Compression::Gzip.decompress(b"ply").is_err());
+    }
+}
@@ -0,0 +1,517 @@
+use std::{
+    fmt, fs,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bevy::prelude::warn;
+use serde_json::Value;
+use walkdir::WalkDir;
+
+use crate::app::resources::file_sequence::compare_frames;
+
+// HTTP Cache
+// Datasets served over HTTP, i.e. by a static file server. A dataset is the URL of an index of its
+// frames, either a JSON list of file names (or {"files": [...]}) or the server's directory
+// listing. Frames are fetched as the LoadManager loads them, so in the order of its LOD iterator,
+// and kept on disk beneath the cache dir:
+// ```
+// http://host:8000/runs/dam/fluid_0001.ply => <cache dir>/http/host_8000/runs/dam/fluid_0001.ply
+// ```
+// The index is kept as well, so a dataset that has been opened before opens offline. Once the
+// cache grows past its size, the least recently used files are removed
+
+/// Where the listing of a directory URL (one ending in /) is kept
+pub const INDEX_FILE: &str = "mr-index";
+/// Trimming goes a little under the size, so it isn't needed again for the very next frame
+const TRIM_TO: f64 = 0.9;
+
+#[derive(Debug)]
+pub enum HttpError {
+    BadUrl(String),
+    NotFound(String),
+    Status(String, u16),
+    Transport(String, String),
+    Io(io::Error),
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            HttpError::BadUrl(url) => write!(f, "{} isn't an http(s) url of a file", url),
+            HttpError::NotFound(url) => write!(f, "{} not found", url),
+            HttpError::Status(url, status) => write!(f, "{} responded {}", url, status),
+            HttpError::Transport(url, e) => write!(f, "couldn't fetch {}: {}", url, e),
+            HttpError::Io(e) => write!(f, "couldn't cache: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl From<io::Error> for HttpError {
+    fn from(e: io::Error) -> Self {
+        HttpError::Io(e)
+    }
+}
+
+pub fn is_url(dataset: &str) -> bool {
+    dataset.starts_with("http://") || dataset.starts_with("https://")
+}
+
+#[derive(Clone)]
+pub struct HttpCache {
+    root: PathBuf,
+    /// 0 for unlimited
+    max_bytes: u64,
+    agent: ureq::Agent,
+    /// bytes in the cache, once it has been measured
+    used: Arc<Mutex<Option<u64>>>,
+}
+
+impl HttpCache {
+    pub fn new(
+        root: &Path,
+        max_bytes: u64,
+    ) -> Self {
+        // frames are loaded by their absolute path within the cache
+        let _ = fs::create_dir_all(root);
+        let root = root
+            .canonicalize()
+            .or_else(|_| std::env::current_dir().map(|dir| dir.join(root)))
+            .unwrap_or_else(|_| root.to_path_buf());
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(10))
+            .timeout_read(Duration::from_secs(60))
+            .build();
+        Self {
+            root,
+            max_bytes,
+            agent,
+            used: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where a URL is kept, its query ignored
+    pub fn path_of(
+        &self,
+        url: &str,
+    ) -> Result<PathBuf, HttpError> {
+        let bad_url = || HttpError::BadUrl(url.to_string());
+        let (scheme, rest) = url.split_once("://").ok_or_else(bad_url)?;
+        if scheme != "http" && scheme != "https" {
+            return Err(bad_url());
+        }
+        let rest = rest
+            .split(|c| c == '?' || c == '#')
+            .next()
+            .unwrap_or_default();
+        let mut segments = rest.split('/');
+        let host = segments
+            .next()
+            .filter(|h| !h.is_empty())
+            .ok_or_else(bad_url)?;
+
+        let mut path = self.root.join(scheme).join(host.replace(':', "_"));
+        for segment in segments.filter(|s| !s.is_empty()) {
+            if segment == "." || segment == ".." || segment.contains('\\') {
+                return Err(bad_url());
+            }
+            path.push(segment);
+        }
+        if rest.ends_with('/') || !rest.contains('/') {
+            path.push(INDEX_FILE);
+        }
+        Ok(path)
+    }
+
+    /// Where the index at `url` is kept, under a name starting with INDEX_FILE whatever the
+    /// server calls it, i.e. mr-index-index.json, so trimming the cache keeps it
+    fn index_path(
+        &self,
+        url: &str,
+    ) -> Result<PathBuf, HttpError> {
+        let path = self.path_of(url)?;
+        match path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+        {
+            Some(name) if name != INDEX_FILE => {
+                Ok(path.with_file_name(format!("{}-{}", INDEX_FILE, name)))
+            }
+            _ => Ok(path),
+        }
+    }
+
+    /// The URL a path within the cache is kept for
+    pub fn url_of(
+        &self,
+        path: &Path,
+    ) -> Option<String> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let mut segments = Vec::new();
+        for component in relative.components() {
+            match component {
+                Component::Normal(segment) => segments.push(segment.to_str()?),
+                _ => return None,
+            }
+        }
+        if segments.len() < 3 {
+            return None;
+        }
+        let scheme = segments[0];
+        let host = match segments[1].rsplit_once('_') {
+            Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => {
+                format!("{}:{}", host, port)
+            }
+            _ => segments[1].to_string(),
+        };
+        let mut url = format!("{}://{}/{}", scheme, host, segments[2..].join("/"));
+        if url.ends_with(INDEX_FILE) {
+            url.truncate(url.len() - INDEX_FILE.len());
+        }
+        Some(url)
+    }
+
+    /// The file at `url`, from the cache when it's there
+    pub fn fetch(
+        &self,
+        url: &str,
+    ) -> Result<Vec<u8>, HttpError> {
+        let path = self.path_of(url)?;
+        if let Ok(bytes) = fs::read(&path) {
+            // most recently used
+            let _ = filetime::set_file_mtime(&path, filetime::FileTime::now());
+            return Ok(bytes);
+        }
+        let bytes = self.get(url)?;
+        self.store(&path, &bytes)?;
+        Ok(bytes)
+    }
+
+    /// The URL of each file listed by the index at `url`, fetched again if the server can be
+    /// reached, or as it was last fetched if it can't
+    pub fn index(
+        &self,
+        url: &str,
+    ) -> Result<Vec<String>, HttpError> {
+        let path = self.index_path(url)?;
+        let bytes = match self.get(url) {
+            Ok(bytes) => {
+                self.store(&path, &bytes)?;
+                bytes
+            }
+            Err(HttpError::Transport(_, e)) if path.is_file() => {
+                warn!("Using the cached index of {}, {}", url, e);
+                fs::read(&path)?
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(parse_index(url, &String::from_utf8_lossy(&bytes)))
+    }
+
+    fn get(
+        &self,
+        url: &str,
+    ) -> Result<Vec<u8>, HttpError> {
+        let response = match self.agent.get(url).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Err(HttpError::NotFound(url.to_string())),
+            Err(ureq::Error::Status(status, _)) => {
+                return Err(HttpError::Status(url.to_string(), status))
+            }
+            Err(e) => return Err(HttpError::Transport(url.to_string(), e.to_string())),
+        };
+        let mut bytes = Vec::new();
+        response
+            .into_reader()
+            .read_to_end(&mut bytes)
+            .map_err(|e| HttpError::Transport(url.to_string(), e.to_string()))?;
+        Ok(bytes)
+    }
+
+    fn store(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+    ) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // so a frame being written is never read as a whole one
+        let part = path.with_file_name(format!(
+            "{}.part",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        fs::write(&part, bytes)?;
+        fs::rename(&part, path)?;
+
+        if self.max_bytes == 0 {
+            return Ok(());
+        }
+        let mut used = self.used.lock().unwrap();
+        let total = match *used {
+            Some(total) => total + bytes.len() as u64,
+            None => self.measure(),
+        };
+        *used = Some(if total > self.max_bytes {
+            self.trim(path)
+        } else {
+            total
+        });
+        Ok(())
+    }
+
+    /// (last used, bytes, path) of every cached file
+    fn cached_files(&self) -> Vec<(filetime::FileTime, u64, PathBuf)> {
+        WalkDir::new(&self.root)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let metadata = e.metadata().ok().filter(|m| m.is_file())?;
+                let modified = filetime::FileTime::from_last_modification_time(&metadata);
+                Some((modified, metadata.len(), e.into_path()))
+            })
+            .collect()
+    }
+
+    fn measure(&self) -> u64 {
+        self.cached_files().iter().map(|(_, bytes, _)| bytes).sum()
+    }
+
+    /// Removes the least recently used frames, besides `keep`, until the cache is under its size.
+    /// Returns the bytes left in the cache
+    fn trim(
+        &self,
+        keep: &Path,
+    ) -> u64 {
+        let mut files = self.cached_files();
+        files.sort();
+        let mut total: u64 = files.iter().map(|(_, bytes, _)| bytes).sum();
+        let target = (self.max_bytes as f64 * TRIM_TO) as u64;
+        for (_, bytes, path) in files {
+            if total <= target {
+                break;
+            }
+            // indexes, and the configs saved beside them
+            let ours = path
+                .file_name()
+                .map_or(false, |name| name.to_string_lossy().starts_with("mr-"));
+            if path == keep || ours {
+                continue;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total -= bytes;
+            }
+        }
+        total
+    }
+}
+
+/// The URLs of the files an index lists, resolved against the index's URL. Either JSON, a list of
+/// names or {"files": [...]}, or the links of an HTML directory listing
+pub fn parse_index(
+    index_url: &str,
+    index: &str,
+) -> Vec<String> {
+    let base = match index_url.rfind('/') {
+        Some(slash) if slash > index_url.find("://").map_or(0, |s| s + 2) => {
+            index_url[..slash + 1].to_string()
+        }
+        // just the host
+        _ => format!("{}/", index_url),
+    };
+    let names: Vec<String> = match serde_json::from_str::<Value>(index) {
+        Ok(Value::Array(names)) => json_strings(&names),
+        Ok(Value::Object(object)) => match object.get("files") {
+            Some(Value::Array(names)) => json_strings(names),
+            _ => Vec::new(),
+        },
+        // a listing is in whichever order the server sorts it
+        _ => {
+            let mut links = html_links(index);
+            links.sort_by(|a, b| compare_frames(a, b));
+            links
+        }
+    };
+    names
+        .iter()
+        // parent directories, sort orders, sub directories
+        .filter(|name| !name.starts_with("..") && !name.starts_with('?') && !name.ends_with('/'))
+        .map(|name| {
+            if is_url(name) {
+                name.clone()
+            } else if let Some(absolute) = name.strip_prefix('/') {
+                let host_end = base.find("://").map_or(0, |s| s + 3);
+                let host_end = base[host_end..]
+                    .find('/')
+                    .map_or(base.len(), |e| e + host_end);
+                format!("{}/{}", &base[..host_end], absolute)
+            } else {
+                format!("{}{}", base, name.trim_start_matches("./"))
+            }
+        })
+        .collect()
+}
+
+fn json_strings(values: &[Value]) -> Vec<String> {
+    values
+        .iter()
+        .filter_map(|v| v.as_str())
+        .map(String::from)
+        .collect()
+}
+
+/// The href of each link, as written by directory listings (python's http.server, nginx, apache)
+fn html_links(html: &str) -> Vec<String> {
+    html.split("href=")
+        .skip(1)
+        .filter_map(|rest| {
+            let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            let end = rest[1..].find(quote)?;
+            Some(rest[1..end + 1].to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::Write,
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    /// Serves `files` until it has answered `requests` requests
+    fn serve(
+        files: Vec<(&'static str, &'static str)>,
+        requests: usize,
+    ) -> (String, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                respond(stream.unwrap(), &files);
+            }
+        });
+        (url, server)
+    }
+
+    fn respond(
+        mut stream: TcpStream,
+        files: &[(&str, &str)],
+    ) {
+        let mut request = [0u8; 1024];
+        let read = stream.read(&mut request).unwrap();
+        let request = String::from_utf8_lossy(&request[..read]);
+        let path = request.split_whitespace().nth(1).unwrap_or_default();
+        let response = match files.iter().find(|(name, _)| *name == path) {
+            Some((_, body)) => format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            ),
+            None => String::from("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"),
+        };
+        stream.write_all(response.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_parse_index() {
+        let listing = r#"<ul><li><a href="../">..</a></li><li><a href="fluid_0001.ply">
+            fluid_0001.ply</a></li><li><a href='sub/'>sub/</a></li></ul>"#;
+        assert_eq!(
+            parse_index("http://host:8000/runs/dam/", listing),
+            vec!["http://host:8000/runs/dam/fluid_0001.ply"]
+        );
+        assert_eq!(
+            parse_index(
+                "http://host/runs/dam/index.json",
+                r#"{"files": ["fluid_0001.ply", "/other/fluid_0002.ply"]}"#
+            ),
+            vec![
+                "http://host/runs/dam/fluid_0001.ply",
+                "http://host/other/fluid_0002.ply"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cached_paths() {
+        let cache = HttpCache::new(&std::env::temp_dir().join("mesh-ripper-test-paths"), 0);
+        for url in [
+            "http://host:8000/runs/dam/fluid_0001.ply",
+            "https://host/runs/dam/",
+        ]
+        .iter()
+        {
+            let path = cache.path_of(url).unwrap();
+            assert!(path.starts_with(cache.root()));
+            assert_eq!(cache.url_of(&path).unwrap(), *url);
+        }
+        assert!(cache.path_of("http://host/runs/../secrets").is_err());
+        assert!(cache.path_of("ftp://host/runs/dam/").is_err());
+    }
+
+    #[test]
+    fn test_fetch_then_offline() {
+        let root = std::env::temp_dir().join("mesh-ripper-test-http-cache");
+        let _ = fs::remove_dir_all(&root);
+        let cache = HttpCache::new(&root, 0);
+        let files = vec![
+            ("/index.json", r#"["fluid_0001.ply", "fluid_0002.ply"]"#),
+            ("/fluid_0001.ply", "ply 1"),
+        ];
+        // the index, a frame, and a frame that isn't there
+        let (url, server) = serve(files, 3);
+
+        let index = cache.index(&format!("{}index.json", url)).unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(cache.fetch(&index[0]).unwrap(), b"ply 1");
+        assert!(matches!(
+            cache.fetch(&index[1]),
+            Err(HttpError::NotFound(_))
+        ));
+        server.join().unwrap();
+
+        // the server is gone
+        assert_eq!(cache.index(&format!("{}index.json", url)).unwrap(), index);
+        assert_eq!(cache.fetch(&index[0]).unwrap(), b"ply 1");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_least_recently_used_are_removed() {
+        let root = std::env::temp_dir().join("mesh-ripper-test-http-trim");
+        let _ = fs::remove_dir_all(&root);
+        let cache = HttpCache::new(&root, 10);
+        let old = cache.path_of("http://host/a.ply").unwrap();
+        let used = cache.path_of("http://host/b.ply").unwrap();
+        let new = cache.path_of("http://host/c.ply").unwrap();
+        let index = cache.index_path("http://host/index.json").unwrap();
+        assert!(index.ends_with("mr-index-index.json"));
+        cache.store(&index, b"1").unwrap();
+        filetime::set_file_mtime(&index, filetime::FileTime::from_unix_time(0, 0)).unwrap();
+        cache.store(&old, b"1234").unwrap();
+        cache.store(&used, b"1234").unwrap();
+        filetime::set_file_mtime(&old, filetime::FileTime::from_unix_time(1, 0)).unwrap();
+        filetime::set_file_mtime(&used, filetime::FileTime::from_unix_time(2, 0)).unwrap();
+        cache.store(&new, b"1234").unwrap();
+
+        assert!(!old.exists());
+        assert!(used.exists() && new.exists());
+        // the index is kept, however long since it was used
+        assert!(index.exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,2 +1,5 @@
+pub mod attributes;
+pub mod classes;
+pub mod colormap;
 pub mod features;
 pub mod render;
@@ -0,0 +1,269 @@
+use bevy::{prelude::Mesh, render::mesh::VertexAttributeValues};
+use bevy_inspector_egui::Inspectable;
+use serde::*;
+
+use super::features::{attribute_names, POINT_DATA_PREFIX};
+
+// Attributes
+// Loaders keep every per point array a file has by its own name, i.e. a PLY's vx, vy & vz
+// properties become the array "v". What each array is for, its role, is guessed from its name,
+// or set by the dataset's `AttributeMapping`, edited in the inspector and saved in mr-config.ron:
+// ```
+// attribute_mapping: (velocity: "vel", density: "rho"),
+// ```
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttributeRole {
+    Position,
+    Normal,
+    Velocity,
+    Id,
+    Density,
+    Pressure,
+    /// An integer phase or object id, that particles are colored and hidden by
+    Class,
+}
+
+impl AttributeRole {
+    pub const ALL: [AttributeRole; 7] = [
+        AttributeRole::Position,
+        AttributeRole::Normal,
+        AttributeRole::Velocity,
+        AttributeRole::Id,
+        AttributeRole::Density,
+        AttributeRole::Pressure,
+        AttributeRole::Class,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AttributeRole::Position => "position",
+            AttributeRole::Normal => "normal",
+            AttributeRole::Velocity => "velocity",
+            AttributeRole::Id => "id",
+            AttributeRole::Density => "density",
+            AttributeRole::Pressure => "pressure",
+            AttributeRole::Class => "class",
+        }
+    }
+
+    pub fn components(&self) -> usize {
+        match self {
+            AttributeRole::Position | AttributeRole::Normal | AttributeRole::Velocity => 3,
+            AttributeRole::Id
+            | AttributeRole::Density
+            | AttributeRole::Pressure
+            | AttributeRole::Class => 1,
+        }
+    }
+
+    /// Array names (lowercased) taken to play the role, when the mapping doesn't say. Positions
+    /// are the mesh's own unless mapped
+    pub fn guesses(&self) -> &'static [&'static str] {
+        match self {
+            AttributeRole::Position => &[],
+            AttributeRole::Normal => &["normal", "normals", "n"],
+            AttributeRole::Velocity => &["velocity", "velocities", "vel", "v", "u"],
+            AttributeRole::Id => &["id", "ids", "particle_id", "tag"],
+            AttributeRole::Density => &["density", "rho", "d"],
+            AttributeRole::Pressure => &["pressure", "p"],
+            AttributeRole::Class => &["phase", "class", "object_id", "object", "body", "type"],
+        }
+    }
+}
+
+/// The array each role is read from, by name. Empty names are guessed
+#[derive(Inspectable, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AttributeMapping {
+    pub position: String,
+    pub normal: String,
+    pub velocity: String,
+    pub id: String,
+    pub density: String,
+    pub pressure: String,
+    pub class: String,
+    /// Arrays offered as scalars, every one component array without a role when empty
+    pub scalars: Vec<String>,
+}
+
+impl AttributeMapping {
+    /// The array mapped to a role, if the mapping names one
+    pub fn mapped(
+        &self,
+        role: AttributeRole,
+    ) -> Option<&str> {
+        let name = match role {
+            AttributeRole::Position => &self.position,
+            AttributeRole::Normal => &self.normal,
+            AttributeRole::Velocity => &self.velocity,
+            AttributeRole::Id => &self.id,
+            AttributeRole::Density => &self.density,
+            AttributeRole::Pressure => &self.pressure,
+            AttributeRole::Class => &self.class,
+        };
+        Some(name.trim()).filter(|name| !name.is_empty())
+    }
+
+    /// The array that plays a role, of a frame's arrays, by the mapping or else by its name
+    pub fn resolve<'a>(
+        &self,
+        role: AttributeRole,
+        arrays: &'a [PointArrayInfo],
+    ) -> Option<&'a PointArrayInfo> {
+        let components = |array: &&PointArrayInfo| array.components == role.components();
+        match self.mapped(role) {
+            Some(name) => arrays.iter().filter(components).find(|a| a.name == name),
+            None => role.guesses().iter().find_map(|guess| {
+                arrays
+                    .iter()
+                    .filter(components)
+                    .find(|a| a.name.eq_ignore_ascii_case(guess))
+            }),
+        }
+    }
+
+    /// The arrays offered as scalars, of a frame's arrays
+    pub fn scalars<'a>(
+        &self,
+        arrays: &'a [PointArrayInfo],
+    ) -> Vec<&'a PointArrayInfo> {
+        if !self.scalars.is_empty() {
+            return arrays
+                .iter()
+                .filter(|a| a.components == 1 && self.scalars.contains(&a.name))
+                .collect();
+        }
+        let roles: Vec<&PointArrayInfo> = AttributeRole::ALL
+            .iter()
+            .filter_map(|role| self.resolve(*role, arrays))
+            .collect();
+        arrays
+            .iter()
+            .filter(|a| a.components == 1 && !roles.iter().any(|r| r.name == a.name))
+            .collect()
+    }
+
+    /// Each array as "name (components): role", for browsing in the inspector
+    pub fn describe(
+        &self,
+        arrays: &[PointArrayInfo],
+    ) -> Vec<String> {
+        let scalars = self.scalars(arrays);
+        arrays
+            .iter()
+            .map(|array| {
+                let role = AttributeRole::ALL
+                    .iter()
+                    .find(|role| self.resolve(**role, arrays) == Some(array))
+                    .map(|role| role.name())
+                    .or_else(|| Some("scalar").filter(|_| scalars.contains(&array)))
+                    .unwrap_or("-");
+                format!("{} ({}): {}", array.name, array.components, role)
+            })
+            .collect()
+    }
+}
+
+/// A per point array of a frame, by the name the file gave it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointArrayInfo {
+    pub name: String,
+    pub components: usize,
+}
+
+/// The per point arrays a loaded frame has, sorted by name
+pub fn point_arrays(mesh: &Mesh) -> Vec<PointArrayInfo> {
+    let mut arrays: Vec<PointArrayInfo> = attribute_names(mesh)
+        .into_iter()
+        .filter_map(|name| {
+            let components = match mesh.attribute(name.clone())? {
+                VertexAttributeValues::Float(_) => 1,
+                VertexAttributeValues::Float2(_) => 2,
+                VertexAttributeValues::Float3(_) => 3,
+                VertexAttributeValues::Float4(_) => 4,
+                _ => return None,
+            };
+            Some(PointArrayInfo {
+                name: name.strip_prefix(POINT_DATA_PREFIX)?.to_string(),
+                components,
+            })
+        })
+        .collect();
+    arrays.sort_by(|a, b| a.name.cmp(&b.name));
+    arrays
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::support::loader_fu::features::set_point_data;
+    use bevy::render::pipeline::PrimitiveTopology;
+
+    fn array(
+        name: &str,
+        components: usize,
+    ) -> PointArrayInfo {
+        PointArrayInfo {
+            name: String::from(name),
+            components,
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let arrays = vec![
+            array("n", 3),
+            array("p", 1),
+            array("rho", 1),
+            array("temperature", 1),
+            array("v", 3),
+            array("vel", 3),
+        ];
+        let mut mapping = AttributeMapping::default();
+        let resolve = |mapping: &AttributeMapping, role| {
+            mapping.resolve(role, &arrays).map(|a| a.name.as_str())
+        };
+        assert_eq!(resolve(&mapping, AttributeRole::Velocity), Some("vel"));
+        assert_eq!(resolve(&mapping, AttributeRole::Normal), Some("n"));
+        assert_eq!(resolve(&mapping, AttributeRole::Position), None);
+        assert_eq!(resolve(&mapping, AttributeRole::Id), None);
+        assert_eq!(
+            mapping
+                .scalars(&arrays)
+                .iter()
+                .map(|a| a.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["temperature"]
+        );
+
+        mapping.velocity = String::from("v");
+        mapping.pressure = String::from("temperature");
+        mapping.id = String::from("n");
+        assert_eq!(resolve(&mapping, AttributeRole::Velocity), Some("v"));
+        assert_eq!(
+            resolve(&mapping, AttributeRole::Pressure),
+            Some("temperature")
+        );
+        // a mapped array with the wrong number of components plays no role
+        assert_eq!(resolve(&mapping, AttributeRole::Id), None);
+        assert_eq!(
+            mapping
+                .scalars(&arrays)
+                .iter()
+                .map(|a| a.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["p"]
+        );
+    }
+    #[test]
+    fn test_point_arrays() {
+        let mut mesh = Mesh::new(PrimitiveTopology::PointList);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0f32; 3]; 2]);
+        set_point_data(&mut mesh, "vel", vec![[1f32; 3]; 2]);
+        set_point_data(&mut mesh, "rho", vec![1f32, 2.]);
+        assert_eq!(point_arrays(&mesh), vec![array("rho", 1), array("vel", 3)]);
+        // arrays stored on other meshes aren't listed
+        assert!(point_arrays(&Mesh::new(PrimitiveTopology::PointList)).is_empty());
+    }
+}
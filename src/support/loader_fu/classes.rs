@@ -0,0 +1,153 @@
+use bevy::prelude::Color;
+use bevy_inspector_egui::Inspectable;
+use serde::*;
+
+// Particle Classes
+// Multiphase and multi-body simulations tag each particle with an integer phase or object id, the
+// array playing the `class` attribute role. Each class found in the loaded frames is listed in
+// the inspector with a color, from a categorical palette, and whether it is shown, i.e. to hide
+// the boundary particles. Both are saved in the dataset's mr-config.ron:
+// ```
+// particle_classes: (classes: [(id: 0, name: "water", color: ..., visible: true),
+//                              (id: 1, name: "boundary", color: ..., visible: false)]),
+// ```
+
+/// Tableau 10, distinct colors for up to ten classes before they repeat
+const CLASS_PALETTE: [[u8; 3]; 10] = [
+    [78, 121, 167],
+    [242, 142, 43],
+    [225, 87, 89],
+    [118, 183, 178],
+    [89, 161, 79],
+    [237, 201, 72],
+    [176, 122, 161],
+    [255, 157, 167],
+    [156, 117, 95],
+    [186, 176, 172],
+];
+
+/// Classes listed at most, so a continuous array mapped to the role by mistake doesn't list
+/// every one of its values
+const MAX_CLASSES: usize = 64;
+
+/// The class of a value of the class array, which loaders keep as floats
+pub fn class_of(value: f32) -> i32 {
+    value.round() as i32
+}
+
+/// The palette's color for a class, new classes start out with it
+pub fn palette_color(id: i32) -> Color {
+    let [r, g, b] = CLASS_PALETTE[id.rem_euclid(CLASS_PALETTE.len() as i32) as usize];
+    Color::rgb_u8(r, g, b)
+}
+
+#[derive(Inspectable, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParticleClass {
+    pub id: i32,
+    pub name: String,
+    pub color: Color,
+    pub visible: bool,
+}
+
+impl Default for ParticleClass {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            name: String::new(),
+            color: palette_color(0),
+            visible: true,
+        }
+    }
+}
+
+/// Every class seen in the dataset's frames, sorted by id
+#[derive(Inspectable, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParticleClasses {
+    pub classes: Vec<ParticleClass>,
+}
+
+impl ParticleClasses {
+    pub fn get(
+        &self,
+        id: i32,
+    ) -> Option<&ParticleClass> {
+        self.classes.iter().find(|class| class.id == id)
+    }
+
+    /// Lists the classes of a frame's class array that aren't yet, true if there were any. Up to
+    /// MAX_CLASSES
+    pub fn discover(
+        &mut self,
+        values: &[f32],
+    ) -> bool {
+        let mut found = false;
+        for &value in values.iter().filter(|v| v.is_finite()) {
+            let id = class_of(value);
+            if self.classes.len() >= MAX_CLASSES {
+                break;
+            }
+            if self.get(id).is_none() {
+                self.classes.push(ParticleClass {
+                    id,
+                    name: format!("class {}", id),
+                    color: palette_color(id),
+                    visible: true,
+                });
+                found = true;
+            }
+        }
+        if found {
+            self.classes.sort_by_key(|class| class.id);
+        }
+        found
+    }
+
+    /// Whether particles of a class are drawn, classes that aren't listed are
+    pub fn visible(
+        &self,
+        id: i32,
+    ) -> bool {
+        self.get(id).map_or(true, |class| class.visible)
+    }
+
+    pub fn any_hidden(&self) -> bool {
+        self.classes.iter().any(|class| !class.visible)
+    }
+
+    /// The color of a class, the palette's when it isn't listed
+    pub fn color(
+        &self,
+        id: i32,
+    ) -> Color {
+        self.get(id).map_or(palette_color(id), |class| class.color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discover() {
+        let mut classes = ParticleClasses::default();
+        assert!(classes.discover(&[2., 0., 2., f32::NAN, 0.]));
+        assert_eq!(
+            classes.classes.iter().map(|c| c.id).collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+        assert!(!classes.discover(&[0., 2.]));
+
+        classes.classes[1].visible = false;
+        classes.classes[1].color = Color::WHITE;
+        assert!(classes.discover(&[1.]));
+        // settings survive classes found later
+        assert!(!classes.visible(2));
+        assert_eq!(classes.color(2), Color::WHITE);
+        assert!(classes.visible(1) && classes.visible(7));
+        assert_eq!(classes.color(7), palette_color(7));
+        assert_eq!(palette_color(-1), palette_color(9));
+        assert!(classes.any_hidden());
+    }
+}
@@ -0,0 +1,352 @@
+use bevy::prelude::Transform;
+use bevy_inspector_egui::Inspectable;
+use serde::*;
+
+use super::{
+    attributes::AttributeRole,
+    classes::{class_of, ParticleClasses},
+    features::Features,
+};
+
+// Colormaps
+// Frames colored by a value per vertex (speed, height, pressure...) rather than one color. Each
+// value's color is sampled from the colormap at its place in the range, a range that is held for
+// the whole sequence so that a value is the same color in every frame. Viridis, magma, inferno,
+// plasma and cividis are perceptually uniform and readable with color blindness (cividis was made
+// for it), coolwarm diverges from the middle of the range
+
+#[derive(Inspectable, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Colormap {
+    Viridis,
+    Magma,
+    Inferno,
+    Plasma,
+    Cividis,
+    Coolwarm,
+    Grayscale,
+}
+
+impl Default for Colormap {
+    fn default() -> Self {
+        Colormap::Viridis
+    }
+}
+
+impl Colormap {
+    /// sRGB colors spaced evenly from the bottom of the range to the top
+    fn stops(&self) -> &'static [[u8; 3]] {
+        match self {
+            Colormap::Viridis => &[
+                [68, 1, 84],
+                [71, 45, 123],
+                [59, 82, 139],
+                [44, 114, 142],
+                [33, 144, 140],
+                [39, 173, 129],
+                [93, 200, 99],
+                [170, 220, 50],
+                [253, 231, 37],
+            ],
+            Colormap::Magma => &[
+                [0, 0, 4],
+                [29, 17, 71],
+                [81, 18, 124],
+                [130, 38, 129],
+                [182, 54, 121],
+                [230, 81, 100],
+                [251, 136, 97],
+                [254, 194, 135],
+                [252, 253, 191],
+            ],
+            Colormap::Inferno => &[
+                [0, 0, 4],
+                [87, 16, 110],
+                [188, 55, 84],
+                [249, 142, 9],
+                [252, 255, 164],
+            ],
+            Colormap::Plasma => &[
+                [13, 8, 135],
+                [126, 3, 168],
+                [204, 71, 120],
+                [248, 149, 64],
+                [240, 249, 33],
+            ],
+            Colormap::Cividis => &[
+                [0, 34, 78],
+                [18, 53, 112],
+                [59, 73, 108],
+                [87, 93, 109],
+                [112, 113, 115],
+                [138, 134, 120],
+                [165, 156, 116],
+                [195, 179, 105],
+                [225, 204, 85],
+                [254, 232, 56],
+            ],
+            Colormap::Coolwarm => &[
+                [59, 76, 192],
+                [98, 130, 234],
+                [141, 176, 254],
+                [184, 208, 249],
+                [221, 220, 220],
+                [245, 196, 173],
+                [244, 154, 123],
+                [222, 96, 77],
+                [180, 4, 38],
+            ],
+            Colormap::Grayscale => &[[0, 0, 0], [255, 255, 255]],
+        }
+    }
+
+    /// The sRGB color at `t`, from 0 at the bottom of the range to 1 at the top
+    pub fn sample(
+        &self,
+        t: f32,
+    ) -> [f32; 3] {
+        let stops = self.stops();
+        let t = if t.is_nan() { 0. } else { t.max(0.).min(1.) };
+        let position = t * (stops.len() - 1) as f32;
+        let below = (position.floor() as usize).min(stops.len() - 2);
+        let fraction = position - below as f32;
+        let (a, b) = (stops[below], stops[below + 1]);
+        let mut color = [0f32; 3];
+        for channel in 0..3 {
+            let a = a[channel] as f32 / 255.;
+            let b = b[channel] as f32 / 255.;
+            color[channel] = a + (b - a) * fraction;
+        }
+        color
+    }
+
+    /// The color at `t` as a vertex color, which unlike a material's color is linear
+    pub fn sample_linear(
+        &self,
+        t: f32,
+    ) -> [f32; 4] {
+        let [r, g, b] = self.sample(t);
+        [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), 1.]
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// What frames are colored by, fluid_color when Uniform
+#[derive(Inspectable, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ColorBy {
+    Uniform,
+    // The length of each velocity
+    Speed,
+    // Up, in the dataset's transform
+    Height,
+    Density,
+    Pressure,
+    // The array named by `color_scalar`
+    Scalar,
+    // Each particle's class, in its color from `particle_classes`
+    Class,
+}
+
+impl Default for ColorBy {
+    fn default() -> Self {
+        ColorBy::Uniform
+    }
+}
+
+#[derive(Inspectable, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RangeMode {
+    // The smallest to the largest value of every loaded frame
+    Auto,
+    // Between two percentiles of the loaded frames' values, so outliers don't wash it out
+    Percentile,
+    // From `min` to `max`
+    Fixed,
+}
+
+#[derive(Inspectable, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorRange {
+    pub mode: RangeMode,
+    pub min: f32,
+    pub max: f32,
+    #[inspectable(min = 0.0, max = 100.0)]
+    pub low_percentile: f32,
+    #[inspectable(min = 0.0, max = 100.0)]
+    pub high_percentile: f32,
+}
+
+impl Default for ColorRange {
+    fn default() -> Self {
+        Self {
+            mode: RangeMode::Auto,
+            min: 0.,
+            max: 1.,
+            low_percentile: 2.,
+            high_percentile: 98.,
+        }
+    }
+}
+
+/// A frame colored by a value per vertex, through a colormap spanning min to max
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coloring {
+    pub by: ColorBy,
+    /// The array colored by, for ColorBy::Scalar
+    pub scalar: String,
+    pub colormap: Colormap,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Coloring {
+    /// The value each vertex is colored by, None if the frame hasn't got it
+    pub fn values(
+        by: ColorBy,
+        scalar: &str,
+        features: &Features,
+        transform: Transform,
+    ) -> Option<Vec<f32>> {
+        match by {
+            ColorBy::Uniform => None,
+            ColorBy::Speed => Some(
+                features
+                    .velocities()?
+                    .iter()
+                    .map(|v| (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt())
+                    .collect(),
+            ),
+            ColorBy::Height => Some(
+                features
+                    .vertices()?
+                    .iter()
+                    .map(|v| transform.mul_vec3((*v).into()).y)
+                    .collect(),
+            ),
+            ColorBy::Density => features.role_scalars(AttributeRole::Density).cloned(),
+            ColorBy::Pressure => features.role_scalars(AttributeRole::Pressure).cloned(),
+            ColorBy::Scalar => features.scalars(scalar).cloned(),
+            ColorBy::Class => features.role_scalars(AttributeRole::Class).cloned(),
+        }
+    }
+
+    /// Whether the frame has the value colored by, without reading it
+    pub fn available(
+        &self,
+        features: &Features,
+    ) -> bool {
+        match self.by {
+            ColorBy::Uniform => false,
+            ColorBy::Speed => features.has_velocities(),
+            ColorBy::Height => features.has_vertices(),
+            ColorBy::Density => features.role_scalars(AttributeRole::Density).is_some(),
+            ColorBy::Pressure => features.role_scalars(AttributeRole::Pressure).is_some(),
+            ColorBy::Scalar => features.scalars(&self.scalar).is_some(),
+            ColorBy::Class => features.role_scalars(AttributeRole::Class).is_some(),
+        }
+    }
+
+    /// Where each vertex's value falls in the range, 0 at min and 1 at max. None for classes,
+    /// which aren't a range
+    pub fn shades(
+        &self,
+        features: &Features,
+        transform: Transform,
+    ) -> Option<Vec<f32>> {
+        if self.by == ColorBy::Class {
+            return None;
+        }
+        let values = Self::values(self.by, &self.scalar, features, transform)?;
+        Some(values.into_iter().map(|value| self.shade(value)).collect())
+    }
+
+    pub fn shade(
+        &self,
+        value: f32,
+    ) -> f32 {
+        if self.max > self.min {
+            ((value - self.min) / (self.max - self.min)).max(0.).min(1.)
+        } else {
+            0.5
+        }
+    }
+
+    /// Each vertex's color, as a vertex color
+    pub fn colors(
+        &self,
+        features: &Features,
+        transform: Transform,
+        classes: &ParticleClasses,
+    ) -> Option<Vec<[f32; 4]>> {
+        if self.by == ColorBy::Class {
+            let values = Self::values(self.by, &self.scalar, features, transform)?;
+            return Some(
+                values
+                    .into_iter()
+                    .map(|value| classes.color(class_of(value)).as_linear_rgba_f32())
+                    .collect(),
+            );
+        }
+        let shades = self.shades(features, transform)?;
+        Some(
+            shades
+                .into_iter()
+                .map(|shade| self.colormap.sample_linear(shade))
+                .collect(),
+        )
+    }
+
+    /// What is colored by, for the legend
+    pub fn label(&self) -> String {
+        match self.by {
+            ColorBy::Scalar => self.scalar.clone(),
+            by => format!("{:?}", by),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        assert_eq!(Colormap::Grayscale.sample(0.25), [0.25, 0.25, 0.25]);
+        assert_eq!(Colormap::Grayscale.sample(-1.), [0., 0., 0.]);
+        assert_eq!(Colormap::Grayscale.sample(f32::NAN), [0., 0., 0.]);
+        assert_eq!(Colormap::Grayscale.sample_linear(1.), [1., 1., 1., 1.]);
+        let top = Colormap::Viridis.sample(1.);
+        let expected = [253. / 255., 231. / 255., 37. / 255.];
+        assert!(top
+            .iter()
+            .zip(expected.iter())
+            .all(|(a, b)| (a - b).abs() < 1e-6));
+        // halfway between two stops
+        let [r, _, _] = Colormap::Coolwarm.sample(1. / 16.);
+        assert!((r - (59. + 98.) / 2. / 255.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_shade() {
+        let coloring = Coloring {
+            by: ColorBy::Speed,
+            scalar: String::new(),
+            colormap: Colormap::Viridis,
+            min: 10.,
+            max: 20.,
+        };
+        assert_eq!(coloring.shade(15.), 0.5);
+        assert_eq!(coloring.shade(25.), 1.);
+        assert_eq!(coloring.shade(0.), 0.);
+        let flat = Coloring {
+            max: 10.,
+            ..coloring
+        };
+        assert_eq!(flat.shade(10.), 0.5);
+    }
+}
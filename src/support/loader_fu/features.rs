@@ -1,12 +1,96 @@
-use bevy::{prelude::Mesh, render::mesh::Indices};
+use std::{collections::BTreeSet, sync::Mutex};
+
+use bevy::{
+    prelude::Mesh,
+    render::mesh::{Indices, VertexAttributeValues},
+};
+
+use super::attributes::{point_arrays, AttributeMapping, AttributeRole};
+
+/// Per point velocities, set by loaders from a point data array that looks like one
+pub const ATTRIBUTE_VELOCITY: &str = "Vertex_Velocity";
+
+/// Point data arrays are stored under their name, after this
+pub const POINT_DATA_PREFIX: &str = "Vertex_Data_";
+
+/// The mesh attribute a named point data array (i.e. "pressure" in a .vtk file) is stored as
+pub fn point_data_attribute(name: &str) -> String {
+    format!("{}{}", POINT_DATA_PREFIX, name)
+}
+
+/// The attributes a mesh may have other than point data arrays
+const STANDARD_ATTRIBUTES: [&str; 6] = [
+    Mesh::ATTRIBUTE_POSITION,
+    Mesh::ATTRIBUTE_NORMAL,
+    Mesh::ATTRIBUTE_TANGENT,
+    Mesh::ATTRIBUTE_UV_0,
+    Mesh::ATTRIBUTE_COLOR,
+    ATTRIBUTE_VELOCITY,
+];
+
+/// bevy 0.5's Mesh can only be asked for an attribute by name, so the names point data arrays
+/// have been stored under are kept here, for `attribute_names` to look for
+static POINT_DATA_ATTRIBUTES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Notes an attribute that was stored on a mesh, if it is a point data array
+pub fn record_attribute(name: &str) {
+    if name.starts_with(POINT_DATA_PREFIX) {
+        let mut recorded = POINT_DATA_ATTRIBUTES.lock().unwrap();
+        if !recorded.contains(name) {
+            recorded.insert(name.to_string());
+        }
+    }
+}
+
+/// Stores a named point data array on a mesh, where `attribute_names` will find it
+pub fn set_point_data(
+    mesh: &mut Mesh,
+    name: &str,
+    values: impl Into<VertexAttributeValues>,
+) {
+    let attribute = point_data_attribute(name);
+    record_attribute(&attribute);
+    mesh.set_attribute(attribute, values);
+}
+
+/// The names of every attribute a mesh has, the standard ones then its point data arrays
+pub fn attribute_names(mesh: &Mesh) -> Vec<String> {
+    let recorded = POINT_DATA_ATTRIBUTES.lock().unwrap();
+    STANDARD_ATTRIBUTES
+        .iter()
+        .map(|name| name.to_string())
+        .chain(recorded.iter().cloned())
+        .filter(|name| mesh.attribute(name.clone()).is_some())
+        .collect()
+}
 
 pub struct Features<'a> {
     mesh: &'a Mesh,
+    mapping: Option<&'a AttributeMapping>,
 }
 
 impl<'a> Features<'a> {
     pub fn new(mesh: &'a Mesh) -> Self {
-        Self { mesh }
+        Self {
+            mesh,
+            mapping: None,
+        }
+    }
+
+    /// Reads positions, normals and velocities from the arrays the mapping names, when it does
+    pub fn with_mapping(
+        mut self,
+        mapping: &'a AttributeMapping,
+    ) -> Self {
+        self.mapping = Some(mapping);
+        self
+    }
+
+    fn mapped(
+        &self,
+        role: AttributeRole,
+    ) -> Option<&'a str> {
+        self.mapping.and_then(|mapping| mapping.mapped(role))
     }
 
     pub fn has_vertices(&self) -> bool {
@@ -25,7 +109,14 @@ impl<'a> Features<'a> {
         self.velocities().is_some()
     }
 
+    pub fn has_colors(&self) -> bool {
+        self.colors().is_some()
+    }
+
     pub fn vertices(&self) -> Option<&Vec<[f32; 3]>> {
+        if let Some(name) = self.mapped(AttributeRole::Position) {
+            return self.vectors(name);
+        }
         let vertices = self.mesh.attribute(Mesh::ATTRIBUTE_POSITION);
         let vertices = if let Some(vertices) = vertices {
             match vertices {
@@ -40,6 +131,9 @@ impl<'a> Features<'a> {
     }
 
     pub fn normals(&self) -> Option<&Vec<[f32; 3]>> {
+        if let Some(name) = self.mapped(AttributeRole::Normal) {
+            return self.vectors(name);
+        }
         let normals = self.mesh.attribute(Mesh::ATTRIBUTE_NORMAL);
         let normals = if let Some(normals) = normals {
             match normals {
@@ -57,7 +151,56 @@ impl<'a> Features<'a> {
         self.mesh.indices()
     }
 
+    /// Velocities from the mapped array, else the one the loader took to be velocities. Never
+    /// normals, which only point somewhere
     pub fn velocities(&self) -> Option<&Vec<[f32; 3]>> {
-        self.normals()
+        if let Some(name) = self.mapped(AttributeRole::Velocity) {
+            return self.vectors(name);
+        }
+        match self.mesh.attribute(ATTRIBUTE_VELOCITY) {
+            Some(VertexAttributeValues::Float3(velocities)) => Some(velocities),
+            _ => None,
+        }
+    }
+
+    pub fn colors(&self) -> Option<&Vec<[f32; 4]>> {
+        match self.mesh.attribute(Mesh::ATTRIBUTE_COLOR) {
+            Some(VertexAttributeValues::Float4(colors)) => Some(colors),
+            _ => None,
+        }
+    }
+
+    /// A point data array with one component per point
+    pub fn scalars(
+        &self,
+        name: &str,
+    ) -> Option<&Vec<f32>> {
+        match self.mesh.attribute(point_data_attribute(name)) {
+            Some(VertexAttributeValues::Float(scalars)) => Some(scalars),
+            _ => None,
+        }
+    }
+
+    /// The one component array playing a role, i.e. density, by the mapping or else its name
+    pub fn role_scalars(
+        &self,
+        role: AttributeRole,
+    ) -> Option<&Vec<f32>> {
+        let default = AttributeMapping::default();
+        let mapping = self.mapping.unwrap_or(&default);
+        let arrays = point_arrays(self.mesh);
+        let array = mapping.resolve(role, &arrays)?;
+        self.scalars(&array.name)
+    }
+
+    /// A point data array with three components per point
+    pub fn vectors(
+        &self,
+        name: &str,
+    ) -> Option<&Vec<[f32; 3]>> {
+        match self.mesh.attribute(point_data_attribute(name)) {
+            Some(VertexAttributeValues::Float3(vectors)) => Some(vectors),
+            _ => None,
+        }
     }
 }
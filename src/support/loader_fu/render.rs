@@ -5,7 +5,8 @@ use bevy::{
     prelude::{shape::Icosphere, *},
     render::{
         mesh::{Indices, VertexAttributeValues},
-        pipeline::PrimitiveTopology,
+        pipeline::{CullMode, PipelineDescriptor, PrimitiveTopology, RenderPipeline},
+        shader::{ShaderStage, ShaderStages},
     },
 };
 use bevy_inspector_egui::Inspectable;
@@ -14,7 +15,12 @@ use parry3d::math::Real;
 use rand::{prelude::IteratorRandom, thread_rng};
 use rapier3d::prelude::Cone;
 
-use super::features::Features;
+use super::{
+    attributes::{AttributeMapping, AttributeRole},
+    classes::{class_of, ParticleClasses},
+    colormap::{ColorBy, Coloring, Colormap},
+    features::Features,
+};
 
 use serde::*;
 
@@ -24,9 +30,57 @@ pub enum PointRenderOptions {
     Directional,
 }
 
+// Vertex colors
+// bevy's StandardMaterial ignores them, so meshes with colors (when they're asked for) are drawn
+// with this instead, lit from above on both faces
+const VERTEX_COLOR_VERTEX_SHADER: &str = r#"
+#version 450
+layout(location = 0) in vec3 Vertex_Position;
+layout(location = 1) in vec3 Vertex_Normal;
+layout(location = 2) in vec4 Vertex_Color;
+layout(location = 0) out vec3 v_Normal;
+layout(location = 1) out vec4 v_Color;
+layout(set = 0, binding = 0) uniform CameraViewProj {
+    mat4 ViewProj;
+};
+layout(set = 1, binding = 0) uniform Transform {
+    mat4 Model;
+};
+void main() {
+    v_Normal = mat3(Model) * Vertex_Normal;
+    v_Color = Vertex_Color;
+    gl_Position = ViewProj * Model * vec4(Vertex_Position, 1.0);
+}
+"#;
+
+const VERTEX_COLOR_FRAGMENT_SHADER: &str = r#"
+#version 450
+layout(location = 0) in vec3 v_Normal;
+layout(location = 1) in vec4 v_Color;
+layout(location = 0) out vec4 o_Target;
+void main() {
+    float light = 0.35 + 0.65 * abs(dot(normalize(v_Normal), normalize(vec3(0.3, 1.0, 0.5))));
+    o_Target = vec4(v_Color.rgb * light, v_Color.a);
+}
+"#;
+
+/// Particles merged into each mesh, when they are batched
+const BATCH_PARTICLES: usize = 16384;
+
+/// Materials a colormap is cut into, for particles drawn an entity each
+const PALETTE_SHADES: usize = 32;
+
 pub struct RenderCache {
     pub radius: f32,
     pub cache: HashMap<PointRenderOptions, Handle<Mesh>>,
+    /// coarser glyphs, copied once per particle when particles are batched
+    pub glyphs: HashMap<PointRenderOptions, Mesh>,
+    /// for meshes drawn with their vertex colors
+    pub vertex_color_pipeline: Option<Handle<PipelineDescriptor>>,
+    /// the colormap's shades from bottom to top, for particles colored by a value
+    pub palette: Vec<Handle<StandardMaterial>>,
+    /// each class's color, for particles colored by class
+    pub class_materials: HashMap<i32, Handle<StandardMaterial>>,
 }
 
 impl RenderCache {
@@ -34,6 +88,10 @@ impl RenderCache {
         Self {
             radius,
             cache: HashMap::new(),
+            glyphs: HashMap::new(),
+            vertex_color_pipeline: None,
+            palette: Vec::new(),
+            class_materials: HashMap::new(),
         }
     }
 
@@ -47,6 +105,7 @@ impl RenderCache {
 
     pub fn clear(&mut self) {
         self.cache.clear();
+        self.glyphs.clear();
     }
 
     pub fn get(
@@ -57,17 +116,83 @@ impl RenderCache {
     }
 }
 
+/// How a surface mesh is drawn, on a single entity that can show the next frame's surface drawn
+/// the same way by swapping its mesh
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Surface {
+    Material,
+    VertexColors,
+}
+
 pub struct FeatureAwareRenderer {
     handle: Handle<Mesh>,
+    vertex_colors: bool,
+    batch_points: bool,
+    attribute_mapping: AttributeMapping,
+    coloring: Option<Coloring>,
+    classes: ParticleClasses,
 }
 
 /// This handles rendering 'meshes', that are either:
 /// Meshes, that have vertices, and indices, and normals hopefully, these are rendered as-is
 /// Particles, that have vertices, and possibly normals, but not indices (i.e. no triangles)
-/// Particles are rendered either via spheres, or directional arrows
+/// Particles are rendered either via spheres, or directional arrows, an entity each or batched
+/// into a few meshes
 impl FeatureAwareRenderer {
     pub fn new(mesh: Handle<Mesh>) -> Self {
-        Self { handle: mesh }
+        Self {
+            handle: mesh,
+            vertex_colors: false,
+            batch_points: false,
+            attribute_mapping: AttributeMapping::default(),
+            coloring: None,
+            classes: ParticleClasses::default(),
+        }
+    }
+
+    /// Draws meshes that have vertex colors with them, instead of the material
+    pub fn with_vertex_colors(
+        mut self,
+        vertex_colors: bool,
+    ) -> Self {
+        self.vertex_colors = vertex_colors;
+        self
+    }
+
+    /// Merges the glyphs of every particle into a few meshes, instead of an entity each
+    pub fn with_batched_points(
+        mut self,
+        batch_points: bool,
+    ) -> Self {
+        self.batch_points = batch_points;
+        self
+    }
+
+    /// Places and points particles by the arrays the dataset maps to positions and velocities
+    pub fn with_attribute_mapping(
+        mut self,
+        attribute_mapping: AttributeMapping,
+    ) -> Self {
+        self.attribute_mapping = attribute_mapping;
+        self
+    }
+
+    /// Colors particles and surfaces by a value per vertex, instead of the material
+    pub fn with_coloring(
+        mut self,
+        coloring: Option<Coloring>,
+    ) -> Self {
+        self.coloring = coloring;
+        self
+    }
+
+    /// Colors particles colored by class, and leaves out the particles of hidden classes
+    pub fn with_classes(
+        mut self,
+        classes: ParticleClasses,
+    ) -> Self {
+        self.classes = classes;
+        self
     }
 
     pub fn cache_meshes(
@@ -82,35 +207,257 @@ impl FeatureAwareRenderer {
 
         let pr = PointsRenderer;
         pr.cache_meshes(render_cache, meshes, radius);
+
+        let br = BatchedPointsRenderer;
+        br.cache_meshes(render_cache, meshes, radius);
+    }
+
+    pub fn cache_pipelines(
+        pipelines: &mut Assets<PipelineDescriptor>,
+        shaders: &mut Assets<Shader>,
+        render_cache: &mut RenderCache,
+    ) {
+        let mut pipeline = PipelineDescriptor::default_config(ShaderStages {
+            vertex: shaders.add(Shader::from_glsl(
+                ShaderStage::Vertex,
+                VERTEX_COLOR_VERTEX_SHADER,
+            )),
+            fragment: Some(shaders.add(Shader::from_glsl(
+                ShaderStage::Fragment,
+                VERTEX_COLOR_FRAGMENT_SHADER,
+            ))),
+        });
+        pipeline.primitive.cull_mode = CullMode::None;
+        render_cache.vertex_color_pipeline = Some(pipelines.add(pipeline));
+    }
+
+    /// Materials for each shade of the colormap, lit like the fluid's material. Updated in place
+    /// once made, so particles already drawn with them change too
+    pub fn cache_palette(
+        materials: &mut Assets<StandardMaterial>,
+        colormap: Colormap,
+        opacity: f32,
+        roughness: f32,
+        render_cache: &mut RenderCache,
+    ) {
+        if render_cache.palette.len() != PALETTE_SHADES {
+            render_cache.palette = (0..PALETTE_SHADES)
+                .map(|_| materials.add(StandardMaterial::default()))
+                .collect();
+        }
+        for (shade, handle) in render_cache.palette.iter().enumerate() {
+            if let Some(material) = materials.get_mut(handle) {
+                let [r, g, b] = colormap.sample(shade as f32 / (PALETTE_SHADES - 1) as f32);
+                material.base_color = Color::rgba(r, g, b, opacity);
+                material.roughness = roughness;
+                material.double_sided = true;
+            }
+        }
+    }
+
+    /// A material for each class's color, updated in place once made
+    pub fn cache_class_materials(
+        materials: &mut Assets<StandardMaterial>,
+        classes: &ParticleClasses,
+        opacity: f32,
+        roughness: f32,
+        render_cache: &mut RenderCache,
+    ) {
+        for class in classes.classes.iter() {
+            let handle = render_cache
+                .class_materials
+                .entry(class.id)
+                .or_insert_with(|| materials.add(StandardMaterial::default()));
+            if let Some(material) = materials.get_mut(&*handle) {
+                material.base_color = class.color;
+                material.base_color.set_a(opacity);
+                material.roughness = roughness;
+                material.double_sided = true;
+            }
+        }
     }
 
     pub fn spawn(
         &self,
         commands: &mut Commands,
-        meshes: &Assets<Mesh>,
+        meshes: &mut Assets<Mesh>,
         material: Handle<StandardMaterial>,
         render_options: PointRenderOptions,
         cache: &RenderCache,
         sampled_indices: &Vec<usize>,
+        transform: Transform,
     ) -> Vec<Entity> {
+        let batches = match meshes.get(self.handle.clone()) {
+            Some(mesh) if self.batch_points && !Features::new(mesh).has_indices() => {
+                let features = Features::new(mesh).with_mapping(&self.attribute_mapping);
+                let colors = self.point_colors(&features, transform);
+                let visible = self.visible_indices(&features, sampled_indices);
+                BatchedPointsRenderer::batches(
+                    features,
+                    cache.glyphs.get(&render_options),
+                    visible.as_ref().unwrap_or(sampled_indices),
+                    transform,
+                    colors.as_deref(),
+                )
+            }
+            _ => None,
+        };
+        if let Some(batches) = batches {
+            return BatchedPointsRenderer.spawn_batches(commands, meshes, material, cache, batches);
+        }
+
+        let handle = self.surface_handle(meshes, transform);
         let mesh = meshes.get(self.handle.clone());
         if let Some(mesh) = mesh {
-            let renderer = Self::renderer(mesh);
-            let features = Features::new(mesh);
+            let renderer = self.renderer(mesh);
+            let features = Features::new(mesh).with_mapping(&self.attribute_mapping);
+            let visible = if features.has_indices() {
+                None
+            } else {
+                self.visible_indices(&features, sampled_indices)
+            };
             return renderer.spawn(
                 commands,
-                self.handle.clone(),
+                handle,
                 material,
                 features,
                 render_options,
                 cache,
-                sampled_indices,
+                visible.as_ref().unwrap_or(sampled_indices),
+                transform,
+                self.coloring.as_ref(),
             );
         }
 
         Vec::new()
     }
 
+    /// The sampled particles whose class is shown, None when none are hidden
+    fn visible_indices(
+        &self,
+        features: &Features,
+        sampled_indices: &[usize],
+    ) -> Option<Vec<usize>> {
+        if !self.classes.any_hidden() {
+            return None;
+        }
+        let classes = features.role_scalars(AttributeRole::Class)?;
+        Some(
+            sampled_indices
+                .iter()
+                .copied()
+                .filter(|&idx| {
+                    classes
+                        .get(idx)
+                        .map_or(true, |class| self.classes.visible(class_of(*class)))
+                })
+                .collect(),
+        )
+    }
+
+    /// How the mesh is drawn, if it is a loaded surface rather than particles
+    pub fn surface(
+        &self,
+        meshes: &Assets<Mesh>,
+        cache: &RenderCache,
+    ) -> Option<Surface> {
+        let mesh = meshes.get(self.handle.clone())?;
+        self.surface_of(
+            Features::new(mesh).with_mapping(&self.attribute_mapping),
+            cache,
+        )
+    }
+
+    fn surface_of(
+        &self,
+        features: Features,
+        cache: &RenderCache,
+    ) -> Option<Surface> {
+        if !features.has_indices() {
+            None
+        } else if self.surface_colored(&features) && cache.vertex_color_pipeline.is_some() {
+            Some(Surface::VertexColors)
+        } else {
+            Some(Surface::Material)
+        }
+    }
+
+    /// Whether a surface is drawn with vertex colors, the coloring's or else the file's own
+    fn surface_colored(
+        &self,
+        features: &Features,
+    ) -> bool {
+        let colored = self
+            .coloring
+            .as_ref()
+            .map_or(false, |coloring| coloring.available(features))
+            || (self.vertex_colors && features.has_colors());
+        colored && features.has_normals()
+    }
+
+    /// The mesh a surface is drawn with, a copy with the coloring's vertex colors when it is
+    /// colored by a value
+    fn surface_handle(
+        &self,
+        meshes: &mut Assets<Mesh>,
+        transform: Transform,
+    ) -> Handle<Mesh> {
+        let colored = match (&self.coloring, meshes.get(self.handle.clone())) {
+            (Some(coloring), Some(mesh)) if Features::new(mesh).has_indices() => {
+                let features = Features::new(mesh).with_mapping(&self.attribute_mapping);
+                coloring
+                    .colors(&features, transform, &self.classes)
+                    .map(|colors| {
+                        let mut mesh = mesh.clone();
+                        mesh.set_attribute(
+                            Mesh::ATTRIBUTE_COLOR,
+                            VertexAttributeValues::from(colors),
+                        );
+                        mesh
+                    })
+            }
+            _ => None,
+        };
+        match colored {
+            Some(mesh) => meshes.add(mesh),
+            None => self.handle.clone(),
+        }
+    }
+
+    /// The colors particles are drawn with, the coloring's, or else the file's own when they are
+    /// asked for
+    fn point_colors(
+        &self,
+        features: &Features,
+        transform: Transform,
+    ) -> Option<Vec<[f32; 4]>> {
+        if let Some(coloring) = &self.coloring {
+            if let Some(colors) = coloring.colors(features, transform, &self.classes) {
+                return Some(colors);
+            }
+        }
+        features.colors().filter(|_| self.vertex_colors).cloned()
+    }
+
+    /// Shows this mesh on an entity spawned for another surface drawn the same way, replacing
+    /// its mesh, transform and material in place rather than spawning a new entity
+    pub fn swap(
+        &self,
+        commands: &mut Commands,
+        meshes: &mut Assets<Mesh>,
+        entity: Entity,
+        material: Handle<StandardMaterial>,
+        surface: Surface,
+        transform: Transform,
+    ) {
+        let handle = self.surface_handle(meshes, transform);
+        let mut entity = commands.entity(entity);
+        entity.insert(handle).insert(transform);
+        if surface == Surface::Material {
+            entity.insert(material);
+        }
+    }
+
     pub fn sample_indices(
         mesh: &Mesh,
         sample_size: usize,
@@ -136,18 +483,25 @@ impl FeatureAwareRenderer {
         meshes: &Assets<Mesh>,
         entities: Vec<Entity>,
     ) {
-        let mesh = meshes.get(self.handle.clone());
-        if let Some(mesh) = mesh {
-            let renderer = Self::renderer(mesh);
-            renderer.despawn(commands, entities);
+        // a mesh that has since been unloaded was drawn on entities all the same
+        match meshes.get(self.handle.clone()) {
+            Some(mesh) => self.renderer(mesh).despawn(commands, entities),
+            None => MeshRenderer.despawn(commands, entities),
         }
     }
 
-    fn renderer(mesh: &Mesh) -> &dyn Renderer {
-        let features = Features::new(mesh);
+    fn renderer(
+        &self,
+        mesh: &Mesh,
+    ) -> &dyn Renderer {
+        let features = Features::new(mesh).with_mapping(&self.attribute_mapping);
         // This is a mesh
         let renderer: &dyn Renderer = if features.has_indices() {
-            &MeshRenderer as &dyn Renderer
+            if self.surface_colored(&features) {
+                &VertexColorRenderer as &dyn Renderer
+            } else {
+                &MeshRenderer as &dyn Renderer
+            }
         } else {
             &PointsRenderer as &dyn Renderer
         };
@@ -157,6 +511,8 @@ impl FeatureAwareRenderer {
 }
 
 trait Renderer {
+    /// Spawns the entities drawing the mesh, placed by `transform`, the dataset's transform from
+    /// its manifest
     fn spawn(
         &self,
         commands: &mut Commands,
@@ -166,6 +522,8 @@ trait Renderer {
         render_options: PointRenderOptions,
         cache: &RenderCache,
         sampled_indices: &Vec<usize>,
+        transform: Transform,
+        coloring: Option<&Coloring>,
     ) -> Vec<Entity>;
 
     fn despawn(
@@ -199,13 +557,61 @@ impl Renderer for MeshRenderer {
         _features: Features,
         _render_options: PointRenderOptions,
         _cache: &RenderCache,
-        sampled_indices: &Vec<usize>,
+        _sampled_indices: &Vec<usize>,
+        transform: Transform,
+        _coloring: Option<&Coloring>,
     ) -> Vec<Entity> {
         let entity = commands
             .spawn()
             .insert_bundle(PbrBundle {
                 mesh: mesh.clone(),
                 material: material.clone(),
+                transform,
+                ..Default::default()
+            })
+            .id();
+        vec![entity]
+    }
+}
+
+/// Meshes with their own colors, which their material is ignored for
+pub struct VertexColorRenderer;
+
+impl Renderer for VertexColorRenderer {
+    fn spawn(
+        &self,
+        commands: &mut Commands,
+        mesh: Handle<Mesh>,
+        material: Handle<StandardMaterial>,
+        features: Features,
+        render_options: PointRenderOptions,
+        cache: &RenderCache,
+        sampled_indices: &Vec<usize>,
+        transform: Transform,
+        coloring: Option<&Coloring>,
+    ) -> Vec<Entity> {
+        let pipeline = if let Some(pipeline) = cache.vertex_color_pipeline.clone() {
+            pipeline
+        } else {
+            return MeshRenderer.spawn(
+                commands,
+                mesh,
+                material,
+                features,
+                render_options,
+                cache,
+                sampled_indices,
+                transform,
+                coloring,
+            );
+        };
+        let entity = commands
+            .spawn_bundle(MeshBundle {
+                mesh,
+                render_pipelines: RenderPipelines::from_pipelines(vec![RenderPipeline::new(
+                    pipeline,
+                )]),
+                transform,
                 ..Default::default()
             })
             .id();
@@ -380,18 +786,28 @@ impl Renderer for PointsRenderer {
         render_options: PointRenderOptions,
         cache: &RenderCache,
         sampled_indices: &Vec<usize>,
+        transform: Transform,
+        coloring: Option<&Coloring>,
     ) -> Vec<Entity> {
         let mut entities = Vec::new();
         if let Some(vertices) = features.vertices() {
-            let normals = features.normals();
+            let normals = features.velocities();
+            let shades = coloring
+                .filter(|_| !cache.palette.is_empty())
+                .and_then(|coloring| coloring.shades(&features, transform));
+            let classes = coloring
+                .filter(|coloring| coloring.by == ColorBy::Class)
+                .and_then(|_| features.role_scalars(AttributeRole::Class));
 
             for &idx in sampled_indices {
                 let vertex = vertices[idx];
-                let origin = Vec3::new(vertex[0], vertex[1], vertex[2]);
+                // particles keep their size, only their positions are transformed
+                let origin = transform.mul_vec3(Vec3::new(vertex[0], vertex[1], vertex[2]));
 
                 let normal = if let Some(normals) = normals {
                     if let Some(normal) = normals.get(idx) {
-                        let normal = Vec3::new(normal[0], normal[1], normal[2]);
+                        let normal =
+                            transform.rotation * Vec3::new(normal[0], normal[1], normal[2]);
                         Some(normal)
                     } else {
                         None
@@ -400,12 +816,24 @@ impl Renderer for PointsRenderer {
                     None
                 };
 
+                let class_material = classes
+                    .and_then(|classes| classes.get(idx))
+                    .and_then(|class| cache.class_materials.get(&class_of(*class)));
+                let shade = shades.as_ref().and_then(|shades| shades.get(idx));
+                let material = match (class_material, shade) {
+                    (Some(class_material), _) => class_material.clone(),
+                    (None, Some(shade)) => {
+                        let last = (cache.palette.len() - 1) as f32;
+                        cache.palette[(shade * last).round() as usize].clone()
+                    }
+                    (None, None) => material.clone(),
+                };
                 let entity = match render_options {
                     PointRenderOptions::Sphere => {
-                        self.spawn_sphere(commands, &cache, material.clone(), origin, normal)
+                        self.spawn_sphere(commands, &cache, material, origin, normal)
                     }
                     PointRenderOptions::Directional => {
-                        self.spawn_directional(commands, &cache, material.clone(), origin, normal)
+                        self.spawn_directional(commands, &cache, material, origin, normal)
                     }
                 };
 
@@ -417,3 +845,244 @@ impl Renderer for PointsRenderer {
         entities
     }
 }
+
+/// Particles as a few meshes, each the glyph of thousands of particles merged together, rebuilt
+/// whenever the frame changes. Much cheaper to draw than an entity per particle
+pub struct BatchedPointsRenderer;
+
+impl BatchedPointsRenderer {
+    pub fn cache_meshes(
+        &self,
+        cache: &mut RenderCache,
+        _meshes: &mut Assets<Mesh>,
+        radius: f32,
+    ) {
+        let sphere = Icosphere {
+            radius,
+            subdivisions: 1,
+        };
+        cache
+            .glyphs
+            .insert(PointRenderOptions::Sphere, Mesh::from(sphere));
+        let arrow = PointsRenderer::bevy_mesh(Cone::new(radius / 2., radius / 4.).to_trimesh(6));
+        cache.glyphs.insert(PointRenderOptions::Directional, arrow);
+    }
+
+    /// The glyph of each sampled particle, placed (and pointed along its velocity) as
+    /// PointsRenderer places them, merged into meshes of BATCH_PARTICLES particles, each colored
+    /// its particle's color when there are colors. None if there is no glyph, or the particles
+    /// have no positions
+    pub fn batches(
+        features: Features,
+        glyph: Option<&Mesh>,
+        sampled_indices: &[usize],
+        transform: Transform,
+        colors: Option<&[[f32; 4]]>,
+    ) -> Option<Vec<Mesh>> {
+        let glyph = glyph?;
+        let glyph_features = Features::new(glyph);
+        let glyph_vertices = glyph_features.vertices()?;
+        let glyph_normals = glyph_features.normals()?;
+        let glyph_indices: Vec<u32> = match glyph_features.indices() {
+            Some(Indices::U16(indices)) => indices.iter().map(|&i| i as u32).collect(),
+            Some(Indices::U32(indices)) => indices.clone(),
+            None => (0..glyph_vertices.len() as u32).collect(),
+        };
+        let vertices = features.vertices()?;
+        let velocities = features.velocities();
+
+        let batches = sampled_indices
+            .chunks(BATCH_PARTICLES)
+            .map(|chunk| {
+                let len = chunk.len() * glyph_vertices.len();
+                let mut positions: Vec<[f32; 3]> = Vec::with_capacity(len);
+                let mut normals: Vec<[f32; 3]> = Vec::with_capacity(len);
+                let mut batch_colors: Vec<[f32; 4]> = Vec::new();
+                let mut indices: Vec<u32> = Vec::with_capacity(chunk.len() * glyph_indices.len());
+                for &idx in chunk {
+                    let vertex = match vertices.get(idx) {
+                        Some(vertex) => vertex,
+                        None => continue,
+                    };
+                    let origin = transform.mul_vec3(Vec3::from(*vertex));
+                    let direction = velocities
+                        .and_then(|velocities| velocities.get(idx))
+                        .map(|v| transform.rotation * Vec3::from(*v));
+                    let placement = PointsRenderer::transform(origin, direction);
+
+                    let offset = positions.len() as u32;
+                    indices.extend(glyph_indices.iter().map(|i| i + offset));
+                    for (position, normal) in glyph_vertices.iter().zip(glyph_normals.iter()) {
+                        positions.push(placement.mul_vec3(Vec3::from(*position)).into());
+                        normals.push((placement.rotation * Vec3::from(*normal)).into());
+                    }
+                    if let Some(colors) = colors {
+                        let color = colors.get(idx).copied().unwrap_or([1., 1., 1., 1.]);
+                        batch_colors.extend(std::iter::repeat(color).take(glyph_vertices.len()));
+                    }
+                }
+
+                let uvs = vec![[0f32; 2]; positions.len()];
+                let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+                mesh.set_attribute(
+                    Mesh::ATTRIBUTE_POSITION,
+                    VertexAttributeValues::from(positions),
+                );
+                mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, VertexAttributeValues::from(normals));
+                mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, VertexAttributeValues::from(uvs));
+                if colors.is_some() {
+                    mesh.set_attribute(
+                        Mesh::ATTRIBUTE_COLOR,
+                        VertexAttributeValues::from(batch_colors),
+                    );
+                }
+                mesh.set_indices(Some(Indices::U32(indices)));
+                mesh
+            })
+            .collect();
+        Some(batches)
+    }
+
+    /// An entity per batch, owning its mesh, so despawning it frees the mesh too
+    pub fn spawn_batches(
+        &self,
+        commands: &mut Commands,
+        meshes: &mut Assets<Mesh>,
+        material: Handle<StandardMaterial>,
+        cache: &RenderCache,
+        batches: Vec<Mesh>,
+    ) -> Vec<Entity> {
+        batches
+            .into_iter()
+            .map(|batch| {
+                let colored = Features::new(&batch).has_colors();
+                let mesh = meshes.add(batch);
+                match (colored, cache.vertex_color_pipeline.clone()) {
+                    (true, Some(pipeline)) => commands
+                        .spawn_bundle(MeshBundle {
+                            mesh,
+                            render_pipelines: RenderPipelines::from_pipelines(vec![
+                                RenderPipeline::new(pipeline),
+                            ]),
+                            ..Default::default()
+                        })
+                        .id(),
+                    _ => commands
+                        .spawn_bundle(PbrBundle {
+                            mesh,
+                            material: material.clone(),
+                            ..Default::default()
+                        })
+                        .id(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::support::loader_fu::features::set_point_data;
+
+    #[test]
+    fn test_surfaces() {
+        let mut cache = RenderCache::new(0.1);
+        let mut particles = Mesh::new(PrimitiveTopology::PointList);
+        particles.set_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0f32; 3]; 3]);
+        let mut surface = particles.clone();
+        surface.set_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0f32, 1., 0.]; 3]);
+        surface.set_indices(Some(Indices::U32(vec![0, 1, 2])));
+        let uncolored = surface.clone();
+        surface.set_attribute(Mesh::ATTRIBUTE_COLOR, vec![[1f32; 4]; 3]);
+        let surface_of = |mesh: &Mesh, vertex_colors: bool, cache: &RenderCache| {
+            FeatureAwareRenderer::new(Handle::default())
+                .with_vertex_colors(vertex_colors)
+                .surface_of(Features::new(mesh), cache)
+        };
+
+        assert_eq!(surface_of(&particles, true, &cache), None);
+        assert_eq!(surface_of(&surface, true, &cache), Some(Surface::Material));
+        cache.vertex_color_pipeline = Some(Handle::default());
+        assert_eq!(
+            surface_of(&surface, true, &cache),
+            Some(Surface::VertexColors)
+        );
+        assert_eq!(surface_of(&surface, false, &cache), Some(Surface::Material));
+
+        // colored by height, without colors of its own
+        let coloring = Coloring {
+            by: ColorBy::Height,
+            scalar: String::new(),
+            colormap: Colormap::Viridis,
+            min: 0.,
+            max: 1.,
+        };
+        let colored = FeatureAwareRenderer::new(Handle::default()).with_coloring(Some(coloring));
+        assert_eq!(
+            colored.surface_of(Features::new(&uncolored), &cache),
+            Some(Surface::VertexColors)
+        );
+        assert_eq!(
+            surface_of(&uncolored, true, &cache),
+            Some(Surface::Material)
+        );
+    }
+
+    #[test]
+    fn test_hidden_classes() {
+        let mut particles = Mesh::new(PrimitiveTopology::PointList);
+        particles.set_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0f32; 3]; 4]);
+        set_point_data(&mut particles, "phase", vec![0f32, 1., 1., 2.]);
+        let mut classes = ParticleClasses::default();
+        classes.discover(&[0., 1., 2.]);
+        let renderer = FeatureAwareRenderer::new(Handle::default()).with_classes(classes.clone());
+        let sampled = vec![0, 1, 2, 3];
+        assert_eq!(
+            renderer.visible_indices(&Features::new(&particles), &sampled),
+            None
+        );
+
+        classes.classes[1].visible = false;
+        let renderer = renderer.with_classes(classes);
+        assert_eq!(
+            renderer.visible_indices(&Features::new(&particles), &sampled),
+            Some(vec![0, 3])
+        );
+    }
+
+    #[test]
+    fn test_particles_are_batched() {
+        let glyph = Mesh::from(Icosphere {
+            radius: 0.1,
+            subdivisions: 1,
+        });
+        let glyph_vertices = glyph.count_vertices();
+
+        let mut particles = Mesh::new(PrimitiveTopology::PointList);
+        let positions: Vec<[f32; 3]> = (0..BATCH_PARTICLES + 1)
+            .map(|i| [i as f32, 0., 0.])
+            .collect();
+        particles.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        let sampled: Vec<usize> = (0..BATCH_PARTICLES + 1).collect();
+
+        let batches = BatchedPointsRenderer::batches(
+            Features::new(&particles),
+            Some(&glyph),
+            &sampled,
+            Transform::from_xyz(0., 1., 0.),
+            None,
+        )
+        .unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[1].count_vertices(), glyph_vertices);
+        assert!(!Features::new(&batches[1]).has_colors());
+        let vertices = Features::new(&batches[1]).vertices().unwrap().clone();
+        let (min, max) = vertices.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), v| (min.min(Vec3::from(*v)), max.max(Vec3::from(*v))),
+        );
+        let centre = (min + max) / 2.;
+        assert!((centre - Vec3::new(BATCH_PARTICLES as f32, 1., 0.)).length() < 1e-2);
+    }
+}
@@ -0,0 +1,8 @@
+pub mod bgeo;
+pub mod gltf;
+pub mod mesh_data;
+pub mod numpy;
+pub mod ply;
+pub mod point_cloud;
+pub mod vtk;
+pub mod vtk_xml;
@@ -0,0 +1,299 @@
+use std::{
+    fmt,
+    io::{self, Read},
+};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::BoxedFuture,
+};
+
+use super::mesh_data::{MeshData, PointArray};
+
+// BGEO
+// Houdini's classic binary geometry, as partio reads and writes it, plain or gzipped. Only the
+// points are read, with each of their attributes (v, id, pscale, density...) kept by name.
+// Houdini's newer json based .bgeo isn't supported
+
+const MAGIC: &[u8; 4] = b"Bgeo";
+/// Binary json geometry, written by default since Houdini 12
+const JSON_MAGIC: &[u8; 4] = b"\x7fNSJ";
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+const FLOAT: i32 = 0;
+const INT: i32 = 1;
+const INDEX: i32 = 4;
+const VECTOR: i32 = 5;
+
+#[derive(Debug)]
+pub enum BgeoError {
+    Io(io::Error),
+    Parse(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for BgeoError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            BgeoError::Io(e) => write!(f, "couldn't read: {}", e),
+            BgeoError::Parse(e) => write!(f, "couldn't parse: {}", e),
+            BgeoError::Unsupported(e) => write!(f, "unsupported: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BgeoError {}
+
+impl From<io::Error> for BgeoError {
+    fn from(e: io::Error) -> Self {
+        BgeoError::Io(e)
+    }
+}
+
+#[derive(Default)]
+pub struct BgeoLoader;
+
+impl AssetLoader for BgeoLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let mesh = parse(bytes)?.into_mesh();
+            load_context.set_default_asset(LoadedAsset::new(mesh));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bgeo"]
+    }
+}
+
+pub struct BgeoPlugin;
+
+impl Plugin for BgeoPlugin {
+    fn build(
+        &self,
+        app: &mut AppBuilder,
+    ) {
+        app.init_asset_loader::<BgeoLoader>();
+    }
+}
+
+struct Attribute {
+    kind: i32,
+    array: PointArray,
+}
+
+pub fn parse(bytes: &[u8]) -> Result<MeshData, BgeoError> {
+    let mut decompressed = Vec::new();
+    let mut input = if bytes.starts_with(&GZIP_MAGIC) {
+        flate2::read::MultiGzDecoder::new(bytes).read_to_end(&mut decompressed)?;
+        &decompressed[..]
+    } else {
+        bytes
+    };
+
+    let mut magic = [0u8; 4];
+    input.read_exact(&mut magic)?;
+    if &magic == JSON_MAGIC {
+        return Err(BgeoError::Unsupported(String::from(
+            "json bgeo, save it as a classic bgeo",
+        )));
+    }
+    if &magic != MAGIC || read_u8(&mut input)? != b'V' {
+        return Err(BgeoError::Parse(String::from("not a bgeo file")));
+    }
+    let _version = read_i32(&mut input)?;
+    let point_count = read_count(&mut input)?;
+    // primitives, point groups, primitive groups
+    for _ in 0..3 {
+        read_i32(&mut input)?;
+    }
+    let attribute_count = read_count(&mut input)?;
+    // vertex, primitive and detail attribute counts follow
+    for _ in 0..3 {
+        read_i32(&mut input)?;
+    }
+
+    // the counts are unchecked until the points' size is known
+    let mut attributes = Vec::new();
+    for _ in 0..attribute_count {
+        let name = read_string(&mut input)?;
+        let components = read_u16(&mut input)? as usize;
+        let kind = read_i32(&mut input)?;
+        match kind {
+            // defaults
+            FLOAT | INT | VECTOR => {
+                for _ in 0..components {
+                    read_i32(&mut input)?;
+                }
+            }
+            // the strings values index into
+            INDEX => {
+                for _ in 0..read_count(&mut input)? {
+                    read_string(&mut input)?;
+                }
+            }
+            other => {
+                return Err(BgeoError::Unsupported(format!(
+                    "attribute {} of type {}",
+                    name, other
+                )))
+            }
+        }
+        attributes.push(Attribute {
+            kind,
+            array: PointArray {
+                name,
+                components,
+                values: Vec::new(),
+            },
+        });
+    }
+
+    // a homogeneous position then each attribute's components, 4 bytes each
+    let point_size = attributes
+        .iter()
+        .map(|a| a.array.components)
+        .sum::<usize>()
+        .checked_add(4)
+        .and_then(|components| components.checked_mul(4));
+    match point_size.and_then(|size| size.checked_mul(point_count)) {
+        Some(size) if size <= input.len() => {}
+        _ => {
+            return Err(BgeoError::Parse(format!(
+                "truncated, {} points don't fit in {} bytes",
+                point_count,
+                input.len()
+            )))
+        }
+    }
+    for attribute in attributes.iter_mut() {
+        attribute.array.values = Vec::with_capacity(point_count * attribute.array.components);
+    }
+
+    let mut positions = Vec::with_capacity(point_count);
+    for _ in 0..point_count {
+        // homogeneous
+        let [x, y, z, _w] = [
+            read_f32(&mut input)?,
+            read_f32(&mut input)?,
+            read_f32(&mut input)?,
+            read_f32(&mut input)?,
+        ];
+        positions.push([x, y, z]);
+        for attribute in attributes.iter_mut() {
+            for _ in 0..attribute.array.components {
+                let value = match attribute.kind {
+                    INT | INDEX => read_i32(&mut input)? as f32,
+                    _ => read_f32(&mut input)?,
+                };
+                attribute.array.values.push(value);
+            }
+        }
+    }
+
+    Ok(MeshData {
+        positions,
+        triangles: Vec::new(),
+        point_data: attributes.into_iter().map(|a| a.array).collect(),
+    })
+}
+
+fn read_u8(input: &mut &[u8]) -> io::Result<u8> {
+    let mut bytes = [0u8; 1];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u16(input: &mut &[u8]) -> io::Result<u16> {
+    let mut bytes = [0u8; 2];
+    input.read_exact(&mut bytes)?;
+    Ok(u16::from_be_bytes(bytes))
+}
+
+fn read_i32(input: &mut &[u8]) -> io::Result<i32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(i32::from_be_bytes(bytes))
+}
+
+fn read_f32(input: &mut &[u8]) -> io::Result<f32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(f32::from_be_bytes(bytes))
+}
+
+fn read_count(input: &mut &[u8]) -> Result<usize, BgeoError> {
+    let count = read_i32(input)?;
+    if count < 0 {
+        return Err(BgeoError::Parse(format!("negative count {}", count)));
+    }
+    Ok(count as usize)
+}
+
+fn read_string(input: &mut &[u8]) -> io::Result<String> {
+    let mut bytes = vec![0u8; read_u16(input)? as usize];
+    input.read_exact(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(
+        bytes: &mut Vec<u8>,
+        s: &str,
+    ) {
+        bytes.extend_from_slice(&(s.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(s.as_bytes());
+    }
+
+    #[test]
+    fn test_points_and_attributes() {
+        let mut bytes = b"BgeoV".to_vec();
+        // version, points, prims, point groups, prim groups, point/vertex/prim/detail attributes
+        for count in [5i32, 2, 0, 0, 0, 2, 0, 0, 0].iter() {
+            bytes.extend_from_slice(&count.to_be_bytes());
+        }
+        string(&mut bytes, "v");
+        bytes.extend_from_slice(&3u16.to_be_bytes());
+        bytes.extend_from_slice(&VECTOR.to_be_bytes());
+        bytes.extend_from_slice(&[0u8; 12]);
+        string(&mut bytes, "id");
+        bytes.extend_from_slice(&1u16.to_be_bytes());
+        bytes.extend_from_slice(&INT.to_be_bytes());
+        bytes.extend_from_slice(&[0u8; 4]);
+        for point in 0..2i32 {
+            let p = point as f32;
+            for v in [p, p + 1., p + 2., 1., 0.5, 0., 0.].iter() {
+                bytes.extend_from_slice(&v.to_be_bytes());
+            }
+            bytes.extend_from_slice(&(10 + point).to_be_bytes());
+        }
+
+        let data = parse(&bytes).unwrap();
+        assert_eq!(data.positions, vec![[0., 1., 2.], [1., 2., 3.]]);
+        assert_eq!(
+            data.point_array("v").unwrap().values,
+            vec![0.5, 0., 0., 0.5, 0., 0.]
+        );
+        assert_eq!(data.point_array("id").unwrap().values, vec![10., 11.]);
+        assert!(matches!(
+            parse(b"\x7fNSJ{}"),
+            Err(BgeoError::Unsupported(_))
+        ));
+
+        // a header claiming more points than there are bytes
+        bytes[9..13].copy_from_slice(&i32::MAX.to_be_bytes());
+        assert!(matches!(parse(&bytes), Err(BgeoError::Parse(_))));
+    }
+}
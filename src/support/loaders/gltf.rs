@@ -0,0 +1,343 @@
+use std::{fmt, path::Path};
+
+use ::gltf::{buffer::Source, mesh::Mode, Document};
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    math::{Mat4, Vec3},
+    prelude::*,
+    utils::BoxedFuture,
+};
+use serde::*;
+
+use super::mesh_data::{
+    CellTriangulator, MeshData, PointArray, VTK_POLYGON, VTK_TRIANGLE, VTK_TRIANGLE_STRIP,
+};
+use crate::app::resources::dataset_manifest::DatasetManifest;
+
+// glTF
+// Frames as .glb, or .gltf with embedded or neighbouring buffers. Which meshes of the scene make
+// up a frame is set in the dataset's manifest:
+// ```
+// gltf_meshes: All,
+// ```
+// Node transforms are applied, and each vertex's COLOR_0 times its material's base color is kept
+// as its color. Textures, morph targets and skins are ignored
+
+/// Which meshes of a glTF scene are a frame
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GltfMeshes {
+    /// The first mesh in the scene
+    Primary,
+    /// Every mesh in the scene, merged into one
+    All,
+}
+
+impl Default for GltfMeshes {
+    fn default() -> Self {
+        GltfMeshes::Primary
+    }
+}
+
+#[derive(Debug)]
+pub enum GltfError {
+    Gltf(::gltf::Error),
+    Buffer(String, String),
+    Parse(String),
+    NoMeshes,
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            GltfError::Gltf(e) => write!(f, "couldn't parse: {}", e),
+            GltfError::Buffer(uri, e) => write!(f, "couldn't read buffer {}: {}", uri, e),
+            GltfError::Parse(e) => write!(f, "couldn't parse: {}", e),
+            GltfError::NoMeshes => write!(f, "no meshes in the scene"),
+        }
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+#[derive(Default)]
+pub struct GltfFrameLoader;
+
+impl AssetLoader for GltfFrameLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let meshes = DatasetManifest::beside(load_context).await.gltf_meshes;
+            let gltf = ::gltf::Gltf::from_slice(bytes).map_err(GltfError::Gltf)?;
+            let buffers = load_buffers(&gltf, load_context).await?;
+            let mesh = mesh_data(&gltf.document, &buffers, meshes)?.into_mesh();
+            load_context.set_default_asset(LoadedAsset::new(mesh));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["gltf", "glb"]
+    }
+}
+
+/// Frames are a Mesh like every other format, so this takes over bevy_gltf's extensions, it has
+/// to be added after it
+pub struct GltfFramePlugin;
+
+impl Plugin for GltfFramePlugin {
+    fn build(
+        &self,
+        app: &mut AppBuilder,
+    ) {
+        app.init_asset_loader::<GltfFrameLoader>();
+    }
+}
+
+/// The contents of every buffer, from the .glb, a data uri, or a file beside the .gltf
+async fn load_buffers(
+    gltf: &::gltf::Gltf,
+    load_context: &LoadContext<'_>,
+) -> Result<Vec<Vec<u8>>, GltfError> {
+    let dir = load_context
+        .path()
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    let mut buffers = Vec::new();
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            Source::Bin => gltf.blob.clone().ok_or_else(|| {
+                GltfError::Buffer(String::from("bin"), String::from("missing from the glb"))
+            })?,
+            Source::Uri(uri) => match data_uri(uri) {
+                Some(data) => data?,
+                None => load_context
+                    .read_asset_bytes(dir.join(uri))
+                    .await
+                    .map_err(|e| GltfError::Buffer(uri.to_string(), e.to_string()))?,
+            },
+        };
+        buffers.push(data);
+    }
+    Ok(buffers)
+}
+
+/// The bytes of a base64 `data:` uri, None for any other uri
+pub fn data_uri(uri: &str) -> Option<Result<Vec<u8>, GltfError>> {
+    let data = uri.strip_prefix("data:")?;
+    let encoded = match data.find(";base64,") {
+        Some(start) => &data[start + ";base64,".len()..],
+        None => {
+            return Some(Err(GltfError::Buffer(
+                String::from("data uri"),
+                String::from("not base64"),
+            )))
+        }
+    };
+    Some(
+        base64::decode(encoded)
+            .map_err(|e| GltfError::Buffer(String::from("data uri"), e.to_string())),
+    )
+}
+
+pub fn mesh_data(
+    document: &Document,
+    buffers: &[Vec<u8>],
+    meshes: GltfMeshes,
+) -> Result<MeshData, GltfError> {
+    let mut instances = mesh_instances(document);
+    if meshes == GltfMeshes::Primary {
+        instances.truncate(1);
+    }
+    if instances.is_empty() {
+        return Err(GltfError::NoMeshes);
+    }
+
+    let mut data = MeshData::default();
+    let mut triangulator = CellTriangulator::default();
+    let mut normals = Vec::new();
+    let mut every_normal = true;
+    let mut colors = Vec::new();
+    let mut any_color = false;
+    for (mesh, transform) in instances {
+        let normal_transform = transform.inverse().transpose();
+        for primitive in mesh.primitives() {
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+            let positions = match reader.read_positions() {
+                Some(positions) => positions,
+                None => continue,
+            };
+            let base = data.positions.len() as u32;
+            data.positions.extend(
+                positions.map(|p| -> [f32; 3] { transform.transform_point3(Vec3::from(p)).into() }),
+            );
+            let count = data.positions.len() as u32 - base;
+
+            match reader.read_normals() {
+                Some(primitive_normals) => normals.extend(primitive_normals.map(|n| {
+                    let n = normal_transform.transform_vector3(Vec3::from(n));
+                    let length = n.length();
+                    let n = if length > 0. { n / length } else { n };
+                    <[f32; 3]>::from(n)
+                })),
+                None => every_normal = false,
+            }
+
+            let material = primitive.material();
+            let factor = material.pbr_metallic_roughness().base_color_factor();
+            any_color |= material.index().is_some();
+            match reader.read_colors(0) {
+                Some(primitive_colors) => {
+                    any_color = true;
+                    colors.extend(primitive_colors.into_rgba_f32().map(|c| {
+                        [
+                            c[0] * factor[0],
+                            c[1] * factor[1],
+                            c[2] * factor[2],
+                            c[3] * factor[3],
+                        ]
+                    }));
+                }
+                None => colors.extend(std::iter::repeat(factor).take(count as usize)),
+            }
+
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().map(|i| base + i).collect(),
+                None => (base..base + count).collect(),
+            };
+            if indices.iter().any(|&i| i >= base + count) {
+                return Err(GltfError::Parse(format!(
+                    "mesh {} indexes past its {} vertices",
+                    mesh.index(),
+                    count
+                )));
+            }
+            match primitive.mode() {
+                Mode::Triangles => indices
+                    .chunks_exact(3)
+                    .for_each(|triangle| triangulator.add(VTK_TRIANGLE, triangle)),
+                Mode::TriangleStrip => triangulator.add(VTK_TRIANGLE_STRIP, &indices),
+                Mode::TriangleFan => triangulator.add(VTK_POLYGON, &indices),
+                Mode::Points => {}
+                mode => warn!("Skipping the {:?} of mesh {}", mode, mesh.index()),
+            }
+        }
+    }
+
+    data.triangles = triangulator.into_triangles();
+    if every_normal && normals.len() == data.positions.len() {
+        data.point_data.push(PointArray {
+            name: String::from("normals"),
+            components: 3,
+            values: normals.into_iter().flatten().collect(),
+        });
+    }
+    // without materials or vertex colors, the file has no colors to honor
+    if any_color {
+        data.point_data.push(PointArray {
+            name: String::from("color"),
+            components: 4,
+            values: colors.into_iter().flatten().collect(),
+        });
+    }
+    Ok(data)
+}
+
+/// Every mesh of the default (or first) scene, with its node's transform, depth first
+fn mesh_instances(document: &Document) -> Vec<(::gltf::Mesh, Mat4)> {
+    let mut instances = Vec::new();
+    match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => {
+            for node in scene.nodes() {
+                add_node(node, Mat4::IDENTITY, &mut instances);
+            }
+        }
+        // a library of meshes, without a scene
+        None => instances.extend(document.meshes().map(|mesh| (mesh, Mat4::IDENTITY))),
+    }
+    instances
+}
+
+fn add_node<'a>(
+    node: ::gltf::Node<'a>,
+    parent: Mat4,
+    instances: &mut Vec<(::gltf::Mesh<'a>, Mat4)>,
+) {
+    let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        instances.push((mesh, transform));
+    }
+    for child in node.children() {
+        add_node(child, transform, instances);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a red triangle, placed by two nodes
+    const TRIANGLES: &str = r#"{
+        "asset": {"version": "2.0"},
+        "scene": 0,
+        "scenes": [{"nodes": [0, 1]}],
+        "nodes": [
+            {"mesh": 0, "translation": [0.0, 0.0, 2.0]},
+            {"mesh": 0, "translation": [5.0, 0.0, 0.0]}
+        ],
+        "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1, "material": 0}]}],
+        "materials": [{"pbrMetallicRoughness": {"baseColorFactor": [1.0, 0.0, 0.0, 1.0]}}],
+        "buffers": [{
+            "byteLength": 44,
+            "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAABAAIAAAA="
+        }],
+        "bufferViews": [
+            {"buffer": 0, "byteOffset": 0, "byteLength": 36},
+            {"buffer": 0, "byteOffset": 36, "byteLength": 6}
+        ],
+        "accessors": [
+            {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+             "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0]},
+            {"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}
+        ]
+    }"#;
+
+    fn parse(meshes: GltfMeshes) -> MeshData {
+        let gltf = ::gltf::Gltf::from_slice(TRIANGLES.as_bytes()).unwrap();
+        let buffers: Vec<Vec<u8>> = gltf
+            .buffers()
+            .map(|buffer| match buffer.source() {
+                Source::Uri(uri) => data_uri(uri).unwrap().unwrap(),
+                Source::Bin => unreachable!(),
+            })
+            .collect();
+        mesh_data(&gltf.document, &buffers, meshes).unwrap()
+    }
+
+    #[test]
+    fn test_primary_and_all_meshes() {
+        let primary = parse(GltfMeshes::Primary);
+        assert_eq!(
+            primary.positions,
+            vec![[0., 0., 2.], [1., 0., 2.], [0., 1., 2.]]
+        );
+        assert_eq!(primary.triangles, vec![[0, 1, 2]]);
+        assert_eq!(
+            primary.point_array("color").unwrap().values[..4],
+            [1., 0., 0., 1.]
+        );
+
+        let all = parse(GltfMeshes::All);
+        assert_eq!(all.positions.len(), 6);
+        assert_eq!(all.positions[3], [5., 0., 0.]);
+        assert_eq!(all.triangles, vec![[0, 1, 2], [3, 4, 5]]);
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+
+use bevy::{
+    prelude::Mesh,
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        pipeline::PrimitiveTopology,
+    },
+};
+
+use crate::support::loader_fu::{
+    attributes::AttributeRole,
+    features::{set_point_data, ATTRIBUTE_VELOCITY},
+};
+
+// Mesh Data
+// Points, triangles and per point arrays read from a simulation's output, before they become a
+// bevy Mesh. Cells use VTK's cell types and point ordering, as most of the formats we read do
+
+pub const VTK_VERTEX: u8 = 1;
+pub const VTK_POLY_VERTEX: u8 = 2;
+pub const VTK_LINE: u8 = 3;
+pub const VTK_POLY_LINE: u8 = 4;
+pub const VTK_TRIANGLE: u8 = 5;
+pub const VTK_TRIANGLE_STRIP: u8 = 6;
+pub const VTK_POLYGON: u8 = 7;
+pub const VTK_PIXEL: u8 = 8;
+pub const VTK_QUAD: u8 = 9;
+pub const VTK_TETRA: u8 = 10;
+pub const VTK_VOXEL: u8 = 11;
+pub const VTK_HEXAHEDRON: u8 = 12;
+pub const VTK_WEDGE: u8 = 13;
+pub const VTK_PYRAMID: u8 = 14;
+
+/// Array names (lowercased) that are taken to be colors, rgb or rgba
+const COLOR_NAMES: [&str; 6] = ["color", "colors", "colour", "cd", "rgb", "rgba"];
+
+/// A named array with `components` values per point
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointArray {
+    pub name: String,
+    pub components: usize,
+    pub values: Vec<f32>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MeshData {
+    pub positions: Vec<[f32; 3]>,
+    /// Empty for particles
+    pub triangles: Vec<[u32; 3]>,
+    pub point_data: Vec<PointArray>,
+}
+
+impl MeshData {
+    pub fn point_array(
+        &self,
+        name: &str,
+    ) -> Option<&PointArray> {
+        self.point_data.iter().find(|a| a.name == name)
+    }
+
+    /// Normals from a point array named like them, else averaged from the triangles
+    fn normals(&self) -> Option<Vec<[f32; 3]>> {
+        let given = self
+            .point_data
+            .iter()
+            .find(|a| a.components == 3 && is_named_for(&a.name, AttributeRole::Normal));
+        if let Some(given) = given {
+            return Some(as_float3(&given.values));
+        }
+        if self.triangles.is_empty() {
+            return None;
+        }
+
+        let mut normals = vec![[0f32; 3]; self.positions.len()];
+        for triangle in self.triangles.iter() {
+            let a = self.positions[triangle[0] as usize];
+            let b = self.positions[triangle[1] as usize];
+            let c = self.positions[triangle[2] as usize];
+            let ab = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+            let ac = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+            // area weighted
+            let normal = [
+                ab[1] * ac[2] - ab[2] * ac[1],
+                ab[2] * ac[0] - ab[0] * ac[2],
+                ab[0] * ac[1] - ab[1] * ac[0],
+            ];
+            for &i in triangle.iter() {
+                for axis in 0..3 {
+                    normals[i as usize][axis] += normal[axis];
+                }
+            }
+        }
+        for normal in normals.iter_mut() {
+            let length =
+                (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+            if length > 0. {
+                normal.iter_mut().for_each(|n| *n /= length);
+            }
+        }
+        Some(normals)
+    }
+
+    /// A surface mesh when there are triangles, else a point list that renders as particles.
+    /// Each point array is stored with `set_point_data`, velocity like ones `ATTRIBUTE_VELOCITY`
+    /// too, and color like ones `Mesh::ATTRIBUTE_COLOR`
+    pub fn into_mesh(self) -> Mesh {
+        let vertex_count = self.positions.len();
+        let normals = self.normals();
+        let topology = if self.triangles.is_empty() {
+            PrimitiveTopology::PointList
+        } else {
+            PrimitiveTopology::TriangleList
+        };
+
+        let mut mesh = Mesh::new(topology);
+        if let Some(normals) = normals {
+            mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, VertexAttributeValues::from(normals));
+        }
+        if !self.triangles.is_empty() {
+            let uvs = vec![[0f32; 2]; vertex_count];
+            mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, VertexAttributeValues::from(uvs));
+            let indices = self.triangles.iter().flatten().copied().collect();
+            mesh.set_indices(Some(Indices::U32(indices)));
+        }
+
+        for array in self.point_data.into_iter() {
+            if array.values.len() != vertex_count * array.components {
+                // bevy requires every attribute to have a value per vertex
+                continue;
+            }
+            if (array.components == 3 || array.components == 4)
+                && COLOR_NAMES.contains(&array.name.to_lowercase().as_str())
+                && mesh.attribute(Mesh::ATTRIBUTE_COLOR).is_none()
+            {
+                let colors: Vec<[f32; 4]> = array
+                    .values
+                    .chunks_exact(array.components)
+                    .map(|c| [c[0], c[1], c[2], c.get(3).copied().unwrap_or(1.)])
+                    .collect();
+                mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, VertexAttributeValues::from(colors));
+            }
+            let values = match array.components {
+                1 => VertexAttributeValues::from(array.values),
+                2 => VertexAttributeValues::from(
+                    array
+                        .values
+                        .chunks_exact(2)
+                        .map(|c| [c[0], c[1]])
+                        .collect::<Vec<_>>(),
+                ),
+                3 => VertexAttributeValues::from(as_float3(&array.values)),
+                4 => VertexAttributeValues::from(
+                    array
+                        .values
+                        .chunks_exact(4)
+                        .map(|c| [c[0], c[1], c[2], c[3]])
+                        .collect::<Vec<_>>(),
+                ),
+                _ => continue,
+            };
+            if array.components == 3
+                && is_named_for(&array.name, AttributeRole::Velocity)
+                && mesh.attribute(ATTRIBUTE_VELOCITY).is_none()
+            {
+                mesh.set_attribute(ATTRIBUTE_VELOCITY, values.clone());
+            }
+            set_point_data(&mut mesh, &array.name, values);
+        }
+
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            VertexAttributeValues::from(self.positions),
+        );
+        mesh
+    }
+}
+
+fn is_named_for(
+    name: &str,
+    role: AttributeRole,
+) -> bool {
+    role.guesses()
+        .iter()
+        .any(|guess| name.eq_ignore_ascii_case(guess))
+}
+
+fn as_float3(values: &[f32]) -> Vec<[f32; 3]> {
+    values.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect()
+}
+
+/// Turns cells into triangles. Surface cells are kept as they are, volume cells contribute the
+/// faces that aren't shared with another cell, i.e. the boundary of the volume
+#[derive(Debug, Default)]
+pub struct CellTriangulator {
+    triangles: Vec<[u32; 3]>,
+    /// Volume cell faces keyed by their sorted points, with how often they were seen
+    faces: HashMap<Vec<u32>, (Vec<u32>, usize)>,
+    /// Cell types that aren't triangulated, i.e. quadratic cells, with their count
+    unsupported: HashMap<u8, usize>,
+}
+
+impl CellTriangulator {
+    pub fn add(
+        &mut self,
+        cell_type: u8,
+        points: &[u32],
+    ) {
+        let faces: &[&[usize]] = match cell_type {
+            VTK_VERTEX | VTK_POLY_VERTEX | VTK_LINE | VTK_POLY_LINE => return,
+            VTK_TRIANGLE | VTK_POLYGON | VTK_QUAD => {
+                self.add_polygon(points);
+                return;
+            }
+            VTK_TRIANGLE_STRIP => {
+                self.add_strip(points);
+                return;
+            }
+            VTK_PIXEL if points.len() == 4 => {
+                self.add_polygon(&[points[0], points[1], points[3], points[2]]);
+                return;
+            }
+            VTK_TETRA if points.len() == 4 => &[&[0, 2, 1], &[0, 1, 3], &[1, 2, 3], &[2, 0, 3]],
+            VTK_VOXEL if points.len() == 8 => {
+                let hexahedron = [
+                    points[0], points[1], points[3], points[2], points[4], points[5], points[7],
+                    points[6],
+                ];
+                self.add(VTK_HEXAHEDRON, &hexahedron);
+                return;
+            }
+            VTK_HEXAHEDRON if points.len() == 8 => &[
+                &[0, 3, 2, 1],
+                &[4, 5, 6, 7],
+                &[0, 1, 5, 4],
+                &[1, 2, 6, 5],
+                &[2, 3, 7, 6],
+                &[3, 0, 4, 7],
+            ],
+            VTK_WEDGE if points.len() == 6 => &[
+                &[0, 1, 2],
+                &[3, 5, 4],
+                &[1, 0, 3, 4],
+                &[2, 1, 4, 5],
+                &[0, 2, 5, 3],
+            ],
+            VTK_PYRAMID if points.len() == 5 => &[
+                &[0, 3, 2, 1],
+                &[0, 1, 4],
+                &[1, 2, 4],
+                &[2, 3, 4],
+                &[3, 0, 4],
+            ],
+            _ => {
+                *self.unsupported.entry(cell_type).or_default() += 1;
+                return;
+            }
+        };
+
+        for face in faces.iter() {
+            let face: Vec<u32> = face.iter().map(|&i| points[i]).collect();
+            let mut key = face.clone();
+            key.sort_unstable();
+            self.faces.entry(key).or_insert((face, 0)).1 += 1;
+        }
+    }
+
+    /// Cell types that were skipped, and how many of each
+    pub fn unsupported(&self) -> &HashMap<u8, usize> {
+        &self.unsupported
+    }
+
+    pub fn into_triangles(mut self) -> Vec<[u32; 3]> {
+        let mut boundary: Vec<Vec<u32>> = self
+            .faces
+            .drain()
+            .filter(|(_, (_, count))| *count == 1)
+            .map(|(_, (face, _))| face)
+            .collect();
+        // the same triangles each load, whatever order the map is in
+        boundary.sort_unstable();
+        for face in boundary.iter() {
+            self.add_polygon(face);
+        }
+        self.triangles
+    }
+
+    /// Fans a convex polygon
+    fn add_polygon(
+        &mut self,
+        points: &[u32],
+    ) {
+        for i in 1..points.len().saturating_sub(1) {
+            self.triangles.push([points[0], points[i], points[i + 1]]);
+        }
+    }
+
+    fn add_strip(
+        &mut self,
+        points: &[u32],
+    ) {
+        for i in 0..points.len().saturating_sub(2) {
+            // every other triangle is flipped, to keep the winding
+            if i % 2 == 0 {
+                self.triangles
+                    .push([points[i], points[i + 1], points[i + 2]]);
+            } else {
+                self.triangles
+                    .push([points[i + 1], points[i], points[i + 2]]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_faces_are_not_boundary() {
+        // two tetrahedra sharing the face 1, 2, 3
+        let mut triangulator = CellTriangulator::default();
+        triangulator.add(VTK_TETRA, &[0, 1, 2, 3]);
+        triangulator.add(VTK_TETRA, &[4, 1, 3, 2]);
+        let triangles = triangulator.into_triangles();
+        assert_eq!(triangles.len(), 6);
+        assert!(triangles.iter().all(|t| {
+            let mut t = *t;
+            t.sort_unstable();
+            t != [1, 2, 3]
+        }));
+    }
+
+    #[test]
+    fn test_surface_cells() {
+        let mut triangulator = CellTriangulator::default();
+        triangulator.add(VTK_QUAD, &[0, 1, 2, 3]);
+        triangulator.add(VTK_TRIANGLE_STRIP, &[4, 5, 6, 7]);
+        triangulator.add(VTK_VERTEX, &[8]);
+        triangulator.add(25, &[0; 20]);
+        assert_eq!(triangulator.unsupported().get(&25), Some(&1));
+        assert_eq!(
+            triangulator.into_triangles(),
+            vec![[0, 1, 2], [0, 2, 3], [4, 5, 6], [6, 5, 7]]
+        );
+    }
+}
@@ -0,0 +1,432 @@
+use std::{
+    fmt,
+    io::{self, Cursor, Read},
+    path::Path,
+};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::BoxedFuture,
+};
+
+use super::mesh_data::{MeshData, PointArray};
+
+// NumPy
+// A .npy array of shape (N, 3) is a frame's positions, any further columns are kept as scalars.
+// A .npz holds named arrays, one of them (N, 3) positions (named like `POSITION_NAMES`, or else
+// the first), the others with N rows kept as attributes by name, i.e. v, id, pscale, density
+
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+/// Names of the positions in a .npz, tried in order
+const POSITION_NAMES: [&str; 6] = ["positions", "position", "pos", "points", "p", "x"];
+
+#[derive(Debug)]
+pub enum NumpyError {
+    Io(io::Error),
+    Zip(zip::result::ZipError),
+    Parse(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for NumpyError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            NumpyError::Io(e) => write!(f, "couldn't read: {}", e),
+            NumpyError::Zip(e) => write!(f, "couldn't read npz: {}", e),
+            NumpyError::Parse(e) => write!(f, "couldn't parse: {}", e),
+            NumpyError::Unsupported(e) => write!(f, "unsupported: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for NumpyError {}
+
+impl From<io::Error> for NumpyError {
+    fn from(e: io::Error) -> Self {
+        NumpyError::Io(e)
+    }
+}
+
+impl From<zip::result::ZipError> for NumpyError {
+    fn from(e: zip::result::ZipError) -> Self {
+        NumpyError::Zip(e)
+    }
+}
+
+#[derive(Default)]
+pub struct NumpyLoader;
+
+impl AssetLoader for NumpyLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let data = if load_context
+                .path()
+                .extension()
+                .map_or(false, |e| e == "npz")
+            {
+                parse_npz(bytes)?
+            } else {
+                parse_npy(bytes)?
+            };
+            load_context.set_default_asset(LoadedAsset::new(data.into_mesh()));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["npy", "npz"]
+    }
+}
+
+pub struct NumpyPlugin;
+
+impl Plugin for NumpyPlugin {
+    fn build(
+        &self,
+        app: &mut AppBuilder,
+    ) {
+        app.init_asset_loader::<NumpyLoader>();
+    }
+}
+
+/// An array's values, in row major order
+#[derive(Debug, Clone, PartialEq)]
+pub struct NpyArray {
+    pub shape: Vec<usize>,
+    pub values: Vec<f64>,
+}
+
+impl NpyArray {
+    pub fn rows(&self) -> usize {
+        self.shape.first().copied().unwrap_or(1)
+    }
+
+    /// Values per row
+    pub fn columns(&self) -> usize {
+        self.shape.iter().skip(1).product()
+    }
+
+    fn column(
+        &self,
+        column: usize,
+    ) -> impl Iterator<Item = f32> + '_ {
+        let columns = self.columns();
+        self.values
+            .iter()
+            .skip(column)
+            .step_by(columns)
+            .map(|&v| v as f32)
+    }
+
+    fn positions(&self) -> Vec<[f32; 3]> {
+        let columns = self.columns();
+        self.values
+            .chunks_exact(columns)
+            .map(|row| [row[0] as f32, row[1] as f32, row[2] as f32])
+            .collect()
+    }
+}
+
+pub fn parse_npy(bytes: &[u8]) -> Result<MeshData, NumpyError> {
+    let array = read_array(bytes)?;
+    if array.shape.len() != 2 || array.columns() < 3 {
+        return Err(NumpyError::Unsupported(format!(
+            "an array of shape {:?}, positions are (N, 3)",
+            array.shape
+        )));
+    }
+    let point_data = (3..array.columns())
+        .map(|column| PointArray {
+            name: format!("column_{}", column),
+            components: 1,
+            values: array.column(column).collect(),
+        })
+        .collect();
+    Ok(MeshData {
+        positions: array.positions(),
+        triangles: Vec::new(),
+        point_data,
+    })
+}
+
+pub fn parse_npz(bytes: &[u8]) -> Result<MeshData, NumpyError> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let mut arrays = Vec::new();
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        let name = Path::new(file.name())
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        match read_array(&contents) {
+            Ok(array) => arrays.push((name, array)),
+            // i.e. an array of strings, beside the numbers
+            Err(NumpyError::Unsupported(e)) => warn!("Skipping {}, {}", name, e),
+            Err(e) => return Err(e),
+        }
+    }
+
+    let is_positions = |array: &NpyArray| array.shape.len() == 2 && array.columns() == 3;
+    let positions = POSITION_NAMES
+        .iter()
+        .find_map(|name| {
+            arrays
+                .iter()
+                .position(|(n, a)| n.eq_ignore_ascii_case(name) && is_positions(a))
+        })
+        .or_else(|| arrays.iter().position(|(_, a)| is_positions(a)))
+        .ok_or_else(|| NumpyError::Parse(String::from("no (N, 3) array of positions")))?;
+    let (_, positions) = arrays.remove(positions);
+
+    let point_count = positions.rows();
+    let point_data = arrays
+        .into_iter()
+        .filter(|(name, array)| {
+            let per_point = array.rows() == point_count && !array.shape.is_empty();
+            if !per_point {
+                warn!(
+                    "Skipping {} of shape {:?}, it isn't per point",
+                    name, array.shape
+                );
+            }
+            per_point
+        })
+        .map(|(name, array)| PointArray {
+            name,
+            components: array.columns(),
+            values: array.values.into_iter().map(|v| v as f32).collect(),
+        })
+        .collect();
+
+    Ok(MeshData {
+        positions: positions.positions(),
+        triangles: Vec::new(),
+        point_data,
+    })
+}
+
+/// The magic, a version, the header's length, then a python dict describing the array:
+/// `{'descr': '<f4', 'fortran_order': False, 'shape': (100, 3), }`
+pub fn read_array(bytes: &[u8]) -> Result<NpyArray, NumpyError> {
+    if !bytes.starts_with(MAGIC) || bytes.len() < 10 {
+        return Err(NumpyError::Parse(String::from("not an npy array")));
+    }
+    let (header_len, header_start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        _ => {
+            let len = bytes
+                .get(8..12)
+                .ok_or_else(|| NumpyError::Parse(String::from("truncated header")))?;
+            (
+                u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize,
+                12,
+            )
+        }
+    };
+    let header = bytes
+        .get(header_start..header_start + header_len)
+        .ok_or_else(|| NumpyError::Parse(String::from("truncated header")))?;
+    let header = String::from_utf8_lossy(header);
+
+    let descr = header_value(&header, "descr")
+        .map(|d| d.trim_matches(|c| c == '\'' || c == '"').to_string())
+        .ok_or_else(|| NumpyError::Parse(String::from("no descr")))?;
+    let fortran_order = header_value(&header, "fortran_order") == Some("True");
+    let shape: Vec<usize> = header_value(&header, "shape")
+        .ok_or_else(|| NumpyError::Parse(String::from("no shape")))?
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse()
+                .map_err(|_| NumpyError::Parse(format!("bad shape {}", s)))
+        })
+        .collect::<Result<_, _>>()?;
+
+    let data = &bytes[header_start + header_len..];
+    let count = shape
+        .iter()
+        .try_fold(1usize, |count, &n| count.checked_mul(n))
+        .ok_or_else(|| NumpyError::Parse(format!("shape {:?} is too large", shape)))?;
+    let mut values = decode(data, &descr, count)?;
+
+    if fortran_order && shape.len() == 2 {
+        let (rows, columns) = (shape[0], shape[1]);
+        values = (0..rows * columns)
+            .map(|i| values[(i % columns) * rows + i / columns])
+            .collect();
+    } else if fortran_order && shape.len() > 2 {
+        return Err(NumpyError::Unsupported(String::from(
+            "fortran ordered arrays of more than 2 dimensions",
+        )));
+    }
+    Ok(NpyArray { shape, values })
+}
+
+/// The text of a key's value in the header's dict
+fn header_value<'a>(
+    header: &'a str,
+    key: &str,
+) -> Option<&'a str> {
+    let start = header.find(&format!("'{}'", key))? + key.len() + 2;
+    let rest = header[start..].trim_start().strip_prefix(':')?.trim_start();
+    let end = if rest.starts_with('(') {
+        rest.find(')')? + 1
+    } else {
+        rest.find(',').unwrap_or_else(|| rest.len())
+    };
+    Some(rest[..end].trim())
+}
+
+fn decode(
+    data: &[u8],
+    descr: &str,
+    count: usize,
+) -> Result<Vec<f64>, NumpyError> {
+    let mut chars = descr.chars();
+    let big_endian = match chars.next() {
+        Some('>') => true,
+        Some('<') | Some('|') | Some('=') => false,
+        _ => return Err(NumpyError::Unsupported(format!("dtype {}", descr))),
+    };
+    let kind = chars.next();
+    let size: usize = chars
+        .as_str()
+        .parse()
+        .map_err(|_| NumpyError::Unsupported(format!("dtype {}", descr)))?;
+    // i.e. strings (<U32) or complex numbers (<c16)
+    if ![1, 2, 4, 8].contains(&size) {
+        return Err(NumpyError::Unsupported(format!("dtype {}", descr)));
+    }
+
+    let len = count
+        .checked_mul(size)
+        .ok_or_else(|| NumpyError::Parse(String::from("data too large")))?;
+    let data = data
+        .get(..len)
+        .ok_or_else(|| NumpyError::Parse(String::from("truncated data")))?;
+    let values = data.chunks_exact(size).map(|b| {
+        let mut word = [0u8; 8];
+        word[..size].copy_from_slice(b);
+        if big_endian {
+            word[..size].reverse();
+        }
+        let w = word;
+        match (kind, size) {
+            (Some('f'), 4) => Some(f32::from_le_bytes([w[0], w[1], w[2], w[3]]) as f64),
+            (Some('f'), 8) => Some(f64::from_le_bytes(w)),
+            (Some('i'), 1) => Some(w[0] as i8 as f64),
+            (Some('i'), 2) => Some(i16::from_le_bytes([w[0], w[1]]) as f64),
+            (Some('i'), 4) => Some(i32::from_le_bytes([w[0], w[1], w[2], w[3]]) as f64),
+            (Some('i'), 8) => Some(i64::from_le_bytes(w) as f64),
+            (Some('u'), _) | (Some('b'), 1) => Some(u64::from_le_bytes(w) as f64),
+            _ => None,
+        }
+    });
+    values
+        .collect::<Option<Vec<f64>>>()
+        .ok_or_else(|| NumpyError::Unsupported(format!("dtype {}", descr)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn npy(
+        descr: &str,
+        shape: &str,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let header = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}\n",
+            descr, shape
+        );
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn f32s(values: &[f32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_npy_positions() {
+        let bytes = npy("<f4", "(2, 4)", &f32s(&[0., 1., 2., 9., 3., 4., 5., 8.]));
+        let data = parse_npy(&bytes).unwrap();
+        assert_eq!(data.positions, vec![[0., 1., 2.], [3., 4., 5.]]);
+        assert_eq!(data.point_array("column_3").unwrap().values, vec![9., 8.]);
+
+        let bytes = npy("<f4", "(6,)", &f32s(&[0.; 6]));
+        assert!(parse_npy(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_dtypes() {
+        for descr in ["<U32", "<c16", "<f0", "|V3"].iter() {
+            let bytes = npy(descr, "(2, 3)", &[0; 192]);
+            assert!(matches!(
+                read_array(&bytes),
+                Err(NumpyError::Unsupported(_))
+            ));
+        }
+        let bytes = npy("<f8", "(4611686018427387904, 3)", &[0; 8]);
+        assert!(matches!(read_array(&bytes), Err(NumpyError::Parse(_))));
+    }
+
+    #[test]
+    fn test_npz_channels() {
+        use std::io::Write;
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+        let arrays = [
+            (
+                "density.npy",
+                npy(
+                    "<f8",
+                    "(2,)",
+                    &[1000f64.to_le_bytes(), 998f64.to_le_bytes()].concat(),
+                ),
+            ),
+            (
+                "pos.npy",
+                npy("<f4", "(2, 3)", &f32s(&[0., 1., 2., 3., 4., 5.])),
+            ),
+            (
+                "v.npy",
+                npy("<f4", "(2, 3)", &f32s(&[1., 0., 0., 0., 1., 0.])),
+            ),
+        ];
+        for (name, bytes) in arrays.iter() {
+            archive.start_file(*name, options).unwrap();
+            archive.write_all(bytes).unwrap();
+        }
+        let bytes = archive.finish().unwrap().into_inner();
+
+        let data = parse_npz(&bytes).unwrap();
+        assert_eq!(data.positions[1], [3., 4., 5.]);
+        assert_eq!(
+            data.point_array("density").unwrap().values,
+            vec![1000., 998.]
+        );
+        assert_eq!(data.point_array("v").unwrap().components, 3);
+    }
+}
@@ -0,0 +1,542 @@
+use std::{collections::HashMap, fmt};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::BoxedFuture,
+};
+
+use super::mesh_data::{MeshData, PointArray};
+
+// PLY
+// Stanford's polygon format, ascii or binary. Every vertex property is kept by name, properties
+// named like the components of a vector (vx, vy & vz, or velocity_x...) as one array named for
+// the vector, i.e. "v" or "velocity". red, green & blue (& alpha) are the vertex colors. Faces are
+// fanned into triangles, any other element is skipped
+
+#[derive(Debug)]
+pub enum PlyError {
+    Parse(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            PlyError::Parse(e) => write!(f, "couldn't parse: {}", e),
+            PlyError::Unsupported(e) => write!(f, "unsupported: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PlyError {}
+
+#[derive(Default)]
+pub struct PlyLoader;
+
+impl AssetLoader for PlyLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let mesh = parse(bytes)?.into_mesh();
+            load_context.set_default_asset(LoadedAsset::new(mesh));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ply"]
+    }
+}
+
+pub struct PlyPlugin;
+
+impl Plugin for PlyPlugin {
+    fn build(
+        &self,
+        app: &mut AppBuilder,
+    ) {
+        app.init_asset_loader::<PlyLoader>();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<Self, PlyError> {
+        Ok(match name {
+            "char" | "int8" => ScalarType::I8,
+            "uchar" | "uint8" => ScalarType::U8,
+            "short" | "int16" => ScalarType::I16,
+            "ushort" | "uint16" => ScalarType::U16,
+            "int" | "int32" => ScalarType::I32,
+            "uint" | "uint32" => ScalarType::U32,
+            "float" | "float32" => ScalarType::F32,
+            "double" | "float64" => ScalarType::F64,
+            _ => return Err(PlyError::Parse(format!("unknown type {}", name))),
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+
+    fn is_integer(&self) -> bool {
+        !matches!(self, ScalarType::F32 | ScalarType::F64)
+    }
+}
+
+#[derive(Debug)]
+enum Property {
+    Scalar(String, ScalarType),
+    /// name, the type of the count, the type of the items
+    List(String, ScalarType, ScalarType),
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// A scalar vertex property's values
+struct Column {
+    name: String,
+    kind: ScalarType,
+    values: Vec<f32>,
+}
+
+/// The values after the header, read one at a time
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary(&'a [u8], Format),
+}
+
+impl<'a> Body<'a> {
+    fn read(
+        &mut self,
+        kind: ScalarType,
+    ) -> Result<f64, PlyError> {
+        match self {
+            Body::Ascii(values) => {
+                let value = values
+                    .next()
+                    .ok_or_else(|| PlyError::Parse(String::from("too few values")))?;
+                value
+                    .parse::<f64>()
+                    .map_err(|_| PlyError::Parse(format!("bad value {}", value)))
+            }
+            Body::Binary(input, format) => {
+                let size = kind.size();
+                if input.len() < size {
+                    return Err(PlyError::Parse(String::from("file ends early")));
+                }
+                // little endian, whatever the file is
+                let mut b = [0u8; 8];
+                b[..size].copy_from_slice(&input[..size]);
+                if *format == Format::BinaryBigEndian {
+                    b[..size].reverse();
+                }
+                let rest: &'a [u8] = input;
+                *input = &rest[size..];
+                Ok(match kind {
+                    ScalarType::I8 => b[0] as i8 as f64,
+                    ScalarType::U8 => b[0] as f64,
+                    ScalarType::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    ScalarType::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    ScalarType::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    ScalarType::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    ScalarType::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    ScalarType::F64 => f64::from_le_bytes(b),
+                })
+            }
+        }
+    }
+
+    fn read_list(
+        &mut self,
+        count_kind: ScalarType,
+        item_kind: ScalarType,
+    ) -> Result<Vec<f64>, PlyError> {
+        let count = self.read(count_kind)? as usize;
+        (0..count).map(|_| self.read(item_kind)).collect()
+    }
+}
+
+pub fn parse(bytes: &[u8]) -> Result<MeshData, PlyError> {
+    if !bytes.starts_with(b"ply") {
+        return Err(PlyError::Parse(String::from("not a ply file")));
+    }
+    let end =
+        find(bytes, b"end_header").ok_or_else(|| PlyError::Parse(String::from("no end_header")))?;
+    let body_start = bytes[end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(bytes.len(), |newline| end + newline + 1);
+    let body_len = bytes.len() - body_start;
+    let (format, elements) = parse_header(&String::from_utf8_lossy(&bytes[..end]))?;
+
+    let text;
+    let mut body = match format {
+        Format::Ascii => {
+            text = String::from_utf8_lossy(&bytes[body_start..]);
+            Body::Ascii(text.split_ascii_whitespace())
+        }
+        _ => Body::Binary(&bytes[body_start..], format),
+    };
+
+    let mut columns: Vec<Column> = Vec::new();
+    let mut triangles = Vec::new();
+    let mut vertex_count = 0;
+    for element in elements.iter() {
+        match element.name.as_str() {
+            "vertex" => {
+                vertex_count = element.count;
+                columns = element
+                    .properties
+                    .iter()
+                    .filter_map(|property| match property {
+                        Property::Scalar(name, kind) => Some(Column {
+                            name: name.clone(),
+                            kind: *kind,
+                            // the count is unchecked, each value takes a byte at least
+                            values: Vec::with_capacity(element.count.min(body_len)),
+                        }),
+                        Property::List(..) => None,
+                    })
+                    .collect();
+                for _ in 0..element.count {
+                    let mut column = 0;
+                    for property in element.properties.iter() {
+                        match property {
+                            Property::Scalar(_, kind) => {
+                                let value = body.read(*kind)?;
+                                columns[column].values.push(value as f32);
+                                column += 1;
+                            }
+                            Property::List(_, count_kind, item_kind) => {
+                                body.read_list(*count_kind, *item_kind)?;
+                            }
+                        }
+                    }
+                }
+            }
+            "face" => {
+                for _ in 0..element.count {
+                    for property in element.properties.iter() {
+                        match property {
+                            Property::List(name, count_kind, item_kind)
+                                if name == "vertex_indices" || name == "vertex_index" =>
+                            {
+                                let face = body.read_list(*count_kind, *item_kind)?;
+                                if face.iter().any(|&i| i < 0. || i as usize >= vertex_count) {
+                                    return Err(PlyError::Parse(String::from(
+                                        "face index out of range",
+                                    )));
+                                }
+                                for i in 1..face.len().saturating_sub(1) {
+                                    triangles.push([
+                                        face[0] as u32,
+                                        face[i] as u32,
+                                        face[i + 1] as u32,
+                                    ]);
+                                }
+                            }
+                            Property::List(_, count_kind, item_kind) => {
+                                body.read_list(*count_kind, *item_kind)?;
+                            }
+                            Property::Scalar(_, kind) => {
+                                body.read(*kind)?;
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    for property in element.properties.iter() {
+                        match property {
+                            Property::Scalar(_, kind) => {
+                                body.read(*kind)?;
+                            }
+                            Property::List(_, count_kind, item_kind) => {
+                                body.read_list(*count_kind, *item_kind)?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    into_mesh_data(columns, triangles)
+}
+
+fn parse_header(header: &str) -> Result<(Format, Vec<Element>), PlyError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in header.lines().skip(1) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", kind, ..] => {
+                format = Some(match *kind {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(PlyError::Unsupported(format!("format {}", kind))),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| PlyError::Parse(format!("bad element count {}", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_kind, item_kind, name] => elements
+                .last_mut()
+                .ok_or_else(|| PlyError::Parse(String::from("property before any element")))?
+                .properties
+                .push(Property::List(
+                    name.to_string(),
+                    ScalarType::parse(count_kind)?,
+                    ScalarType::parse(item_kind)?,
+                )),
+            ["property", kind, name] => elements
+                .last_mut()
+                .ok_or_else(|| PlyError::Parse(String::from("property before any element")))?
+                .properties
+                .push(Property::Scalar(name.to_string(), ScalarType::parse(kind)?)),
+            _ => {}
+        }
+    }
+    let format = format.ok_or_else(|| PlyError::Parse(String::from("no format")))?;
+    Ok((format, elements))
+}
+
+/// Positions from x, y & z, vectors from the properties named like their components, colors from
+/// red, green & blue, and every other property as a scalar
+fn into_mesh_data(
+    columns: Vec<Column>,
+    triangles: Vec<[u32; 3]>,
+) -> Result<MeshData, PlyError> {
+    let index: HashMap<String, usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| (column.name.to_lowercase(), i))
+        .collect();
+    let mut used = vec![false; columns.len()];
+    let interleave = |names: &[String], used: &mut Vec<bool>| -> Option<Vec<f32>> {
+        let found: Vec<usize> = names
+            .iter()
+            .map(|name| index.get(name).copied())
+            .collect::<Option<_>>()?;
+        found.iter().for_each(|&i| used[i] = true);
+        let len = columns[found[0]].values.len();
+        let mut values = Vec::with_capacity(len * found.len());
+        for row in 0..len {
+            values.extend(found.iter().map(|&i| columns[i].values[row]));
+        }
+        Some(values)
+    };
+
+    let axes = |prefix: &str| -> Vec<String> {
+        ["x", "y", "z"]
+            .iter()
+            .map(|axis| format!("{}{}", prefix, axis))
+            .collect()
+    };
+    let positions = interleave(&axes(""), &mut used)
+        .ok_or_else(|| PlyError::Parse(String::from("no x, y & z vertex properties")))?;
+    let mut data = MeshData {
+        positions: positions
+            .chunks_exact(3)
+            .map(|c| [c[0], c[1], c[2]])
+            .collect(),
+        triangles,
+        ..Default::default()
+    };
+
+    let color_names: Vec<String> = ["red", "green", "blue", "alpha"]
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    let color_components = if index.contains_key("alpha") { 4 } else { 3 };
+    let integer_colors = index
+        .get("red")
+        .map_or(false, |&i| columns[i].kind.is_integer());
+    if let Some(mut values) = interleave(&color_names[..color_components], &mut used) {
+        if integer_colors {
+            values.iter_mut().for_each(|v| *v /= 255.);
+        }
+        data.point_data.push(PointArray {
+            name: String::from("color"),
+            components: color_components,
+            values,
+        });
+    }
+
+    let prefixes: Vec<String> = columns
+        .iter()
+        .filter_map(|column| {
+            let name = column.name.to_lowercase();
+            name.strip_suffix('x').map(String::from)
+        })
+        .collect();
+    for prefix in prefixes {
+        let vector = prefix.trim_end_matches('_');
+        if vector.is_empty() {
+            continue;
+        }
+        if let Some(values) = interleave(&axes(&prefix), &mut used) {
+            data.point_data.push(PointArray {
+                name: vector.to_string(),
+                components: 3,
+                values,
+            });
+        }
+    }
+
+    for (column, used) in columns.into_iter().zip(used) {
+        if !used {
+            data.point_data.push(PointArray {
+                name: column.name,
+                components: 1,
+                values: column.values,
+            });
+        }
+    }
+    Ok(data)
+}
+
+fn find(
+    haystack: &[u8],
+    needle: &[u8],
+) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_properties() {
+        let ply = "ply
+format ascii 1.0
+comment written by a simulation
+element vertex 3
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property float vx
+property float vy
+property float vz
+property float density
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 0 1 0 1 2 3 1000
+1 0 0 0 1 0 4 5 6 1001
+0 0 1 0 1 0 7 8 9 1002
+3 0 1 2
+";
+        let data = parse(ply.as_bytes()).unwrap();
+        assert_eq!(
+            data.positions,
+            vec![[0., 0., 0.], [1., 0., 0.], [0., 0., 1.]]
+        );
+        assert_eq!(data.triangles, vec![[0, 1, 2]]);
+        let names: Vec<(&str, usize)> = data
+            .point_data
+            .iter()
+            .map(|a| (a.name.as_str(), a.components))
+            .collect();
+        assert_eq!(names, vec![("n", 3), ("v", 3), ("density", 1)]);
+        assert_eq!(
+            data.point_array("v").unwrap().values,
+            vec![1., 2., 3., 4., 5., 6., 7., 8., 9.]
+        );
+
+        let truncated = "ply
+format binary_little_endian 1.0
+element vertex 4000000000
+property float x
+property float y
+property float z
+end_header
+";
+        assert!(matches!(
+            parse(truncated.as_bytes()),
+            Err(PlyError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_binary_colors() {
+        let mut ply = b"ply
+format binary_big_endian 1.0
+element vertex 2
+property double x
+property double y
+property double z
+property uchar red
+property uchar green
+property uchar blue
+property int id
+end_header
+"
+        .to_vec();
+        for (i, position) in [[0f64, 1., 2.], [3., 4., 5.]].iter().enumerate() {
+            for value in position.iter() {
+                ply.extend_from_slice(&value.to_be_bytes());
+            }
+            ply.extend_from_slice(&[255, 0, 51]);
+            ply.extend_from_slice(&(i as i32 + 7).to_be_bytes());
+        }
+
+        let data = parse(&ply).unwrap();
+        assert_eq!(data.positions, vec![[0., 1., 2.], [3., 4., 5.]]);
+        assert!(data.triangles.is_empty());
+        assert_eq!(
+            data.point_array("color").unwrap().values,
+            vec![1., 0., 0.2, 1., 0., 0.2]
+        );
+        assert_eq!(data.point_array("id").unwrap().values, vec![7., 8.]);
+        assert!(parse(&ply[..ply.len() - 2]).is_err());
+    }
+}
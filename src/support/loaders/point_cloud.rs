@@ -0,0 +1,384 @@
+use std::{fmt, io::Read};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::BoxedFuture,
+};
+use serde::*;
+
+use super::mesh_data::{MeshData, PointArray};
+use crate::app::resources::dataset_manifest::DatasetManifest;
+
+// Point Clouds
+// Particles as columns of text, comma or whitespace separated, one particle per line:
+// ```
+// x, y, z, vx, vy, vz, id, pressure
+// 0.0, 1.0, 0.5, 0.1, 0.0, 0.0, 17, 101.3
+// ```
+// The header is optional, .pts/.xyz files may start with a point count, and any of them may be
+// gzipped. Which columns are what is guessed from the header, or set in the dataset's manifest:
+// ```
+// columns: Some((position: Some(("px", "py", "pz")), velocity: Some((3, 4, 5)), id: Some("tag"))),
+// ```
+// Every other column is kept as a scalar attribute
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Velocity column names, tried in order when the mapping doesn't say
+const VELOCITY_COLUMNS: [[&str; 3]; 4] = [
+    ["vx", "vy", "vz"],
+    ["velocity_x", "velocity_y", "velocity_z"],
+    ["vel_x", "vel_y", "vel_z"],
+    ["u", "v", "w"],
+];
+
+/// Leica's .pts columns, when there is no header
+const PTS_COLUMNS: [&str; 7] = ["x", "y", "z", "intensity", "r", "g", "b"];
+
+/// A column by its index, or its name in the header
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColumnMapping {
+    /// x, y and z, the x, y and z columns or the first three when None
+    pub position: Option<[Column; 3]>,
+    pub velocity: Option<[Column; 3]>,
+    pub id: Option<Column>,
+    /// Columns kept as scalars, all the unmapped ones when empty
+    pub scalars: Vec<Column>,
+    /// Whether the first line names the columns, detected when None
+    pub header: Option<bool>,
+}
+
+#[derive(Debug)]
+pub enum PointCloudError {
+    Io(std::io::Error),
+    Parse(usize, String),
+    MissingColumn(String),
+}
+
+impl fmt::Display for PointCloudError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            PointCloudError::Io(e) => write!(f, "couldn't decompress: {}", e),
+            PointCloudError::Parse(line, e) => write!(f, "line {}: {}", line, e),
+            PointCloudError::MissingColumn(column) => write!(f, "no column {}", column),
+        }
+    }
+}
+
+impl std::error::Error for PointCloudError {}
+
+#[derive(Default)]
+pub struct PointCloudLoader;
+
+impl AssetLoader for PointCloudLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let columns = DatasetManifest::beside(load_context)
+                .await
+                .columns
+                .unwrap_or_default();
+            let extension = load_context
+                .path()
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let mesh = parse(bytes, &extension, &columns)?.into_mesh();
+            load_context.set_default_asset(LoadedAsset::new(mesh));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["csv", "xyz", "pts"]
+    }
+}
+
+pub struct PointCloudPlugin;
+
+impl Plugin for PointCloudPlugin {
+    fn build(
+        &self,
+        app: &mut AppBuilder,
+    ) {
+        app.init_asset_loader::<PointCloudLoader>();
+    }
+}
+
+pub fn parse(
+    bytes: &[u8],
+    extension: &str,
+    columns: &ColumnMapping,
+) -> Result<MeshData, PointCloudError> {
+    let mut decompressed = Vec::new();
+    let bytes = if bytes.starts_with(&GZIP_MAGIC) {
+        flate2::read::MultiGzDecoder::new(bytes)
+            .read_to_end(&mut decompressed)
+            .map_err(PointCloudError::Io)?;
+        &decompressed[..]
+    } else {
+        bytes
+    };
+    let text = String::from_utf8_lossy(bytes);
+
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(number, line)| (number + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#') && !line.starts_with("//"))
+        .peekable();
+
+    // a point count, in .pts and .xyz files
+    if let Some((_, line)) = lines.peek() {
+        if extension != "csv" && split(line).len() == 1 && line.parse::<usize>().is_ok() {
+            lines.next();
+        }
+    }
+
+    let header = match lines.peek() {
+        Some((_, line)) if columns.header.unwrap_or_else(|| is_header(line)) => {
+            let names = split(line).iter().map(|n| n.to_string()).collect();
+            lines.next();
+            Some(names)
+        }
+        _ => None,
+    };
+
+    let mut rows: Vec<Vec<f64>> = Vec::new();
+    let mut expected = header.as_ref().map(Vec::len);
+    for (number, line) in lines {
+        let row = split(line)
+            .iter()
+            .map(|value| {
+                value
+                    .parse::<f64>()
+                    .map_err(|_| PointCloudError::Parse(number, format!("bad value {}", value)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        match expected {
+            Some(expected) if row.len() != expected => {
+                return Err(PointCloudError::Parse(
+                    number,
+                    format!("{} columns, expected {}", row.len(), expected),
+                ));
+            }
+            _ => expected = Some(row.len()),
+        }
+        rows.push(row);
+    }
+
+    let names: Vec<String> = header.unwrap_or_else(|| {
+        (0..expected.unwrap_or(3))
+            .map(|i| match (extension, PTS_COLUMNS.get(i)) {
+                ("pts", Some(name)) => name.to_string(),
+                (_, Some(name)) if i < 3 => name.to_string(),
+                _ => format!("column_{}", i),
+            })
+            .collect()
+    });
+    let mapping = ResolvedColumns::new(&names, columns)?;
+
+    let column = |index: usize| -> Vec<f32> { rows.iter().map(|r| r[index] as f32).collect() };
+    let mut data = MeshData {
+        positions: rows
+            .iter()
+            .map(|r| {
+                let [x, y, z] = mapping.position;
+                [r[x] as f32, r[y] as f32, r[z] as f32]
+            })
+            .collect(),
+        ..Default::default()
+    };
+    if let Some([x, y, z]) = mapping.velocity {
+        data.point_data.push(PointArray {
+            name: String::from("velocity"),
+            components: 3,
+            values: rows
+                .iter()
+                .flat_map(|r| vec![r[x] as f32, r[y] as f32, r[z] as f32])
+                .collect(),
+        });
+    }
+    if let Some(id) = mapping.id {
+        data.point_data.push(PointArray {
+            name: String::from("id"),
+            components: 1,
+            values: column(id),
+        });
+    }
+    for &scalar in mapping.scalars.iter() {
+        data.point_data.push(PointArray {
+            name: names[scalar].clone(),
+            components: 1,
+            values: column(scalar),
+        });
+    }
+    Ok(data)
+}
+
+fn split(line: &str) -> Vec<&str> {
+    if line.contains(',') {
+        line.split(',').map(str::trim).collect()
+    } else {
+        line.split_whitespace().collect()
+    }
+}
+
+fn is_header(line: &str) -> bool {
+    split(line)
+        .iter()
+        .any(|value| value.parse::<f64>().is_err())
+}
+
+/// Column indices of a mapping, for a file's column names
+#[derive(Debug, PartialEq)]
+struct ResolvedColumns {
+    position: [usize; 3],
+    velocity: Option<[usize; 3]>,
+    id: Option<usize>,
+    scalars: Vec<usize>,
+}
+
+impl ResolvedColumns {
+    fn new(
+        names: &[String],
+        mapping: &ColumnMapping,
+    ) -> Result<Self, PointCloudError> {
+        let find = |column: &Column| -> Result<usize, PointCloudError> {
+            match column {
+                Column::Index(index) if *index < names.len() => Ok(*index),
+                Column::Name(name) => names
+                    .iter()
+                    .position(|n| n.eq_ignore_ascii_case(name))
+                    .ok_or_else(|| PointCloudError::MissingColumn(name.clone())),
+                Column::Index(index) => Err(PointCloudError::MissingColumn(index.to_string())),
+            }
+        };
+        let find_all = |columns: &[Column; 3]| -> Result<[usize; 3], PointCloudError> {
+            Ok([find(&columns[0])?, find(&columns[1])?, find(&columns[2])?])
+        };
+        let named = |candidates: &[&str; 3]| -> Option<[usize; 3]> {
+            let columns = [
+                Column::Name(candidates[0].to_string()),
+                Column::Name(candidates[1].to_string()),
+                Column::Name(candidates[2].to_string()),
+            ];
+            find_all(&columns).ok()
+        };
+
+        let position = match &mapping.position {
+            Some(position) => find_all(position)?,
+            None => match named(&["x", "y", "z"]) {
+                Some(position) => position,
+                None => find_all(&[Column::Index(0), Column::Index(1), Column::Index(2)])?,
+            },
+        };
+        let velocity = match &mapping.velocity {
+            Some(velocity) => Some(find_all(velocity)?),
+            None => VELOCITY_COLUMNS.iter().find_map(named),
+        };
+        let id = match &mapping.id {
+            Some(id) => Some(find(id)?),
+            None => find(&Column::Name(String::from("id"))).ok(),
+        };
+
+        let scalars = if mapping.scalars.is_empty() {
+            let mapped: Vec<usize> = position
+                .iter()
+                .chain(velocity.iter().flatten())
+                .chain(id.iter())
+                .copied()
+                .collect();
+            (0..names.len()).filter(|i| !mapped.contains(i)).collect()
+        } else {
+            mapping
+                .scalars
+                .iter()
+                .map(find)
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        Ok(Self {
+            position,
+            velocity,
+            id,
+            scalars,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_with_header() {
+        let data = parse(
+            b"x, y, z, vx, vy, vz, id, pressure\n0, 1, 2, 1, 0, 0, 7, 101.3\n3, 4, 5, 0, 1, 0, 8, 99\n",
+            "csv",
+            &ColumnMapping::default(),
+        )
+        .unwrap();
+        assert_eq!(data.positions, vec![[0., 1., 2.], [3., 4., 5.]]);
+        assert_eq!(data.point_array("velocity").unwrap().values.len(), 6);
+        assert_eq!(data.point_array("id").unwrap().values, vec![7., 8.]);
+        assert_eq!(
+            data.point_array("pressure").unwrap().values,
+            vec![101.3, 99.]
+        );
+    }
+
+    #[test]
+    fn test_mapped_columns_without_header() {
+        let mapping = ColumnMapping {
+            position: Some([Column::Index(1), Column::Index(2), Column::Index(3)]),
+            id: Some(Column::Index(0)),
+            ..Default::default()
+        };
+        let data = parse(
+            b"2\n10 0.0 1.0 2.0 0.5\n11 3.0 4.0 5.0 0.25\n",
+            "xyz",
+            &mapping,
+        )
+        .unwrap();
+        assert_eq!(data.positions[1], [3., 4., 5.]);
+        assert_eq!(data.point_array("id").unwrap().values, vec![10., 11.]);
+        assert_eq!(
+            data.point_array("column_4").unwrap().values,
+            vec![0.5, 0.25]
+        );
+
+        let mapping: ColumnMapping =
+            ron::from_str(r#"(position: Some(("a", "b", "c")), id: Some(3))"#).unwrap();
+        assert_eq!(mapping.id, Some(Column::Index(3)));
+        assert!(matches!(
+            parse(b"x y z\n1 2 3\n", "xyz", &mapping),
+            Err(PointCloudError::MissingColumn(_))
+        ));
+    }
+
+    #[test]
+    fn test_gzipped() {
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"1 2 3 200 255 0 0\n").unwrap();
+        let data = parse(&encoder.finish().unwrap(), "pts", &ColumnMapping::default()).unwrap();
+        assert_eq!(data.positions, vec![[1., 2., 3.]]);
+        assert!(data.point_array("intensity").is_some());
+    }
+}
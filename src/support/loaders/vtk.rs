@@ -0,0 +1,625 @@
+use std::fmt;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::BoxedFuture,
+};
+
+use super::mesh_data::{CellTriangulator, MeshData, PointArray, VTK_POLYGON, VTK_TRIANGLE_STRIP};
+
+// Legacy VTK
+// Reads POLYDATA and UNSTRUCTURED_GRID datasets from .vtk files, ASCII or (big endian) BINARY,
+// including the OFFSETS / CONNECTIVITY cells written since VTK 9:
+// ```
+// # vtk DataFile Version 3.0
+// dam break
+// ASCII
+// DATASET POLYDATA
+// POINTS 2 float
+// 0 0 0 1 0 0
+// POINT_DATA 2
+// SCALARS pressure float 1
+// LOOKUP_TABLE default
+// 101.3 99.8
+// ```
+// Volume cells are reduced to their boundary surface, point data arrays become mesh attributes
+
+#[derive(Debug)]
+pub enum VtkError {
+    UnexpectedEof,
+    Parse(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for VtkError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            VtkError::UnexpectedEof => write!(f, "unexpected end of file"),
+            VtkError::Parse(e) => write!(f, "couldn't parse: {}", e),
+            VtkError::Unsupported(e) => write!(f, "unsupported: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for VtkError {}
+
+#[derive(Default)]
+pub struct VtkLoader;
+
+impl AssetLoader for VtkLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let mesh = parse(bytes)?.into_mesh();
+            load_context.set_default_asset(LoadedAsset::new(mesh));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vtk"]
+    }
+}
+
+pub struct VtkPlugin;
+
+impl Plugin for VtkPlugin {
+    fn build(
+        &self,
+        app: &mut AppBuilder,
+    ) {
+        app.init_asset_loader::<VtkLoader>();
+    }
+}
+
+/// Which points each cell uses, cell `i` being `connectivity[offsets[i]..offsets[i + 1]]`
+#[derive(Debug, Default)]
+struct Cells {
+    offsets: Vec<usize>,
+    connectivity: Vec<u32>,
+}
+
+impl Cells {
+    fn iter(&self) -> impl Iterator<Item = &[u32]> {
+        self.offsets
+            .windows(2)
+            .map(move |w| &self.connectivity[w[0]..w[1]])
+    }
+}
+
+/// Where the data arrays being read belong
+enum Attributes {
+    Points(usize),
+    Cells(usize),
+}
+
+pub fn parse(bytes: &[u8]) -> Result<MeshData, VtkError> {
+    let mut reader = Reader {
+        bytes,
+        position: 0,
+        binary: false,
+    };
+
+    if !reader.raw_line()?.starts_with("# vtk DataFile") {
+        return Err(VtkError::Parse(String::from("not a legacy vtk file")));
+    }
+    // title
+    reader.raw_line()?;
+    reader.binary = match reader.line()?.as_slice() {
+        ["ASCII"] => false,
+        ["BINARY"] => true,
+        other => return Err(VtkError::Parse(format!("bad file format {:?}", other))),
+    };
+    match reader.line()?.as_slice() {
+        ["DATASET", "POLYDATA"] | ["DATASET", "UNSTRUCTURED_GRID"] => {}
+        ["DATASET", other] => return Err(VtkError::Unsupported(format!("{} datasets", other))),
+        other => {
+            return Err(VtkError::Parse(format!(
+                "expected DATASET, got {:?}",
+                other
+            )))
+        }
+    }
+
+    let mut data = MeshData::default();
+    let mut triangulator = CellTriangulator::default();
+    let mut cells = None;
+    let mut cell_types = None;
+    let mut attributes = None;
+    let mut max_index = None;
+
+    while let Some(line) = reader.next_line()? {
+        match line.as_slice() {
+            ["POINTS", count, data_type] => {
+                let count = parse_count(count)?;
+                let values = reader.values(count * 3, data_type)?;
+                data.positions = values
+                    .chunks_exact(3)
+                    .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+                    .collect();
+            }
+            [kind @ "VERTICES", counts @ ..]
+            | [kind @ "LINES", counts @ ..]
+            | [kind @ "POLYGONS", counts @ ..]
+            | [kind @ "TRIANGLE_STRIPS", counts @ ..] => {
+                let polys = reader.cells(counts)?;
+                max_index = max_index.max(polys.connectivity.iter().max().copied());
+                let cell_type = match *kind {
+                    "POLYGONS" => VTK_POLYGON,
+                    "TRIANGLE_STRIPS" => VTK_TRIANGLE_STRIP,
+                    // points and lines aren't drawn
+                    _ => continue,
+                };
+                for cell in polys.iter() {
+                    triangulator.add(cell_type, cell);
+                }
+            }
+            ["CELLS", counts @ ..] => {
+                let read = reader.cells(counts)?;
+                max_index = max_index.max(read.connectivity.iter().max().copied());
+                cells = Some(read);
+            }
+            ["CELL_TYPES", count] => {
+                let count = parse_count(count)?;
+                let types: Vec<u8> = reader
+                    .values(count, "int")?
+                    .iter()
+                    .map(|&t| t as u8)
+                    .collect();
+                cell_types = Some(types);
+            }
+            ["POINT_DATA", count] => attributes = Some(Attributes::Points(parse_count(count)?)),
+            ["CELL_DATA", count] => attributes = Some(Attributes::Cells(parse_count(count)?)),
+            ["METADATA"] => reader.skip_metadata()?,
+            // field data of the whole dataset, i.e. its time
+            ["FIELD", _, count] if attributes.is_none() => {
+                reader.field(parse_count(count)?)?;
+            }
+            _ => match attributes {
+                Some(Attributes::Points(count)) => {
+                    let arrays = reader.attribute(&line, count)?;
+                    data.point_data.extend(
+                        arrays
+                            .into_iter()
+                            .filter(|a| a.values.len() == count * a.components),
+                    );
+                }
+                Some(Attributes::Cells(count)) => {
+                    reader.attribute(&line, count)?;
+                }
+                None => return Err(VtkError::Parse(format!("unexpected {:?}", line))),
+            },
+        }
+    }
+
+    if let Some(max_index) = max_index {
+        if max_index as usize >= data.positions.len() {
+            return Err(VtkError::Parse(format!(
+                "cells use point {}, of {} points",
+                max_index,
+                data.positions.len()
+            )));
+        }
+    }
+    match (cells, cell_types) {
+        (Some(cells), Some(cell_types)) => {
+            for (cell, &cell_type) in cells.iter().zip(cell_types.iter()) {
+                triangulator.add(cell_type, cell);
+            }
+        }
+        (Some(_), None) => return Err(VtkError::Parse(String::from("CELLS without CELL_TYPES"))),
+        _ => {}
+    }
+    for (cell_type, count) in triangulator.unsupported() {
+        warn!(
+            "Skipped {} vtk cells of unsupported type {}",
+            count, cell_type
+        );
+    }
+    data.triangles = triangulator.into_triangles();
+
+    Ok(data)
+}
+
+fn parse_count(count: &str) -> Result<usize, VtkError> {
+    count
+        .parse()
+        .map_err(|_| VtkError::Parse(format!("bad count {}", count)))
+}
+
+/// Legacy VTK escapes spaces in names
+fn array_name(name: &str) -> String {
+    name.replace("%20", " ")
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    binary: bool,
+}
+
+impl<'a> Reader<'a> {
+    /// The rest of the current line
+    fn raw_line(&mut self) -> Result<&'a str, VtkError> {
+        if self.position >= self.bytes.len() {
+            return Err(VtkError::UnexpectedEof);
+        }
+        let rest = &self.bytes[self.position..];
+        let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+        self.position += (end + 1).min(rest.len());
+        std::str::from_utf8(&rest[..end])
+            .map(|line| line.trim())
+            .map_err(|_| VtkError::Parse(String::from("header is not utf8")))
+    }
+
+    /// The words of the next line that isn't blank, None at the end of the file
+    fn next_line(&mut self) -> Result<Option<Vec<&'a str>>, VtkError> {
+        self.skip_whitespace();
+        if self.position >= self.bytes.len() {
+            return Ok(None);
+        }
+        Ok(Some(self.raw_line()?.split_whitespace().collect()))
+    }
+
+    fn line(&mut self) -> Result<Vec<&'a str>, VtkError> {
+        self.next_line()?.ok_or(VtkError::UnexpectedEof)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.position)
+            .map_or(false, |b| b.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    fn next_is(
+        &self,
+        keyword: &str,
+    ) -> bool {
+        let rest = &self.bytes[self.position.min(self.bytes.len())..];
+        let start = rest
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        rest[start..].starts_with(keyword.as_bytes())
+    }
+
+    /// METADATA blocks end with a blank line
+    fn skip_metadata(&mut self) -> Result<(), VtkError> {
+        while self.position < self.bytes.len() {
+            if self.raw_line()?.is_empty() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn token(&mut self) -> Result<&'a str, VtkError> {
+        self.skip_whitespace();
+        let rest = &self.bytes[self.position..];
+        let end = rest
+            .iter()
+            .position(|b| b.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        if end == 0 {
+            return Err(VtkError::UnexpectedEof);
+        }
+        self.position += end;
+        std::str::from_utf8(&rest[..end])
+            .map_err(|_| VtkError::Parse(String::from("data is not utf8")))
+    }
+
+    /// `count` values of `data_type`, binary data starting right after the line declaring it
+    fn values(
+        &mut self,
+        count: usize,
+        data_type: &str,
+    ) -> Result<Vec<f64>, VtkError> {
+        if !self.binary {
+            return (0..count)
+                .map(|_| {
+                    let token = self.token()?;
+                    token
+                        .parse::<f64>()
+                        .map_err(|_| VtkError::Parse(format!("bad value {}", token)))
+                })
+                .collect();
+        }
+
+        let size = match data_type {
+            "unsigned_char" | "char" => 1,
+            "unsigned_short" | "short" => 2,
+            "unsigned_int" | "int" | "float" => 4,
+            "unsigned_long" | "long" | "double" | "vtktypeint64" | "vtktypeuint64" => 8,
+            other => return Err(VtkError::Unsupported(format!("{} data", other))),
+        };
+        let end = self.position + count * size;
+        let bytes = self
+            .bytes
+            .get(self.position..end)
+            .ok_or(VtkError::UnexpectedEof)?;
+        self.position = end;
+
+        let values = bytes.chunks_exact(size).map(|b| match data_type {
+            "unsigned_char" => b[0] as f64,
+            "char" => b[0] as i8 as f64,
+            "unsigned_short" => u16::from_be_bytes([b[0], b[1]]) as f64,
+            "short" => i16::from_be_bytes([b[0], b[1]]) as f64,
+            "unsigned_int" => u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            "int" => i32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            "float" => f32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            _ => {
+                let b = [b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]];
+                match data_type {
+                    "double" => f64::from_be_bytes(b),
+                    "unsigned_long" | "vtktypeuint64" => u64::from_be_bytes(b) as f64,
+                    _ => i64::from_be_bytes(b) as f64,
+                }
+            }
+        });
+        Ok(values.collect())
+    }
+
+    fn indices(
+        &mut self,
+        count: usize,
+        data_type: &str,
+    ) -> Result<Vec<u32>, VtkError> {
+        let values = self.values(count, data_type)?;
+        if values.iter().any(|&v| v < 0.) {
+            return Err(VtkError::Parse(String::from("negative point index")));
+        }
+        Ok(values.into_iter().map(|v| v as u32).collect())
+    }
+
+    /// `<count> <size>` followed by cells as `n i0 .. in`, or, since VTK 9,
+    /// `<offsets> <connectivity>` followed by OFFSETS and CONNECTIVITY arrays
+    fn cells(
+        &mut self,
+        counts: &[&str],
+    ) -> Result<Cells, VtkError> {
+        let (count, size) = match counts {
+            [count, size] => (parse_count(count)?, parse_count(size)?),
+            _ => return Err(VtkError::Parse(format!("bad cell counts {:?}", counts))),
+        };
+
+        if self.next_is("OFFSETS") {
+            let data_type = self.line()?.get(1).copied().unwrap_or("vtktypeint64");
+            let offsets = self
+                .indices(count, data_type)?
+                .into_iter()
+                .map(|o| o as usize)
+                .collect::<Vec<_>>();
+            let data_type = match self.line()?.as_slice() {
+                ["CONNECTIVITY", data_type] => *data_type,
+                other => {
+                    return Err(VtkError::Parse(format!(
+                        "expected CONNECTIVITY, got {:?}",
+                        other
+                    )))
+                }
+            };
+            let connectivity = self.indices(size, data_type)?;
+            if offsets.windows(2).any(|w| w[0] > w[1])
+                || offsets.last().map_or(false, |&o| o > connectivity.len())
+            {
+                return Err(VtkError::Parse(String::from("bad cell offsets")));
+            }
+            return Ok(Cells {
+                offsets,
+                connectivity,
+            });
+        }
+
+        let legacy = self.indices(size, "int")?;
+        let mut cells = Cells::default();
+        let mut i = 0;
+        cells.offsets.push(0);
+        for _ in 0..count {
+            let n = *legacy.get(i).ok_or(VtkError::UnexpectedEof)? as usize;
+            let points = legacy
+                .get(i + 1..i + 1 + n)
+                .ok_or(VtkError::UnexpectedEof)?;
+            cells.connectivity.extend_from_slice(points);
+            cells.offsets.push(cells.connectivity.len());
+            i += n + 1;
+        }
+        Ok(cells)
+    }
+
+    /// `FIELD <name> <arrays>`, each array being `<name> <components> <tuples> <type>`
+    fn field(
+        &mut self,
+        arrays: usize,
+    ) -> Result<Vec<PointArray>, VtkError> {
+        let mut field = Vec::new();
+        while field.len() < arrays {
+            match self.line()?.as_slice() {
+                ["METADATA"] => self.skip_metadata()?,
+                // an array without data
+                ["NULL_ARRAY"] => field.push(PointArray {
+                    name: String::new(),
+                    components: 0,
+                    values: Vec::new(),
+                }),
+                [name, components, tuples, data_type] => {
+                    let components = parse_count(components)?;
+                    let values = self.values(components * parse_count(tuples)?, data_type)?;
+                    field.push(PointArray {
+                        name: array_name(name),
+                        components,
+                        values: values.into_iter().map(|v| v as f32).collect(),
+                    });
+                }
+                other => return Err(VtkError::Parse(format!("bad field array {:?}", other))),
+            }
+        }
+        Ok(field.into_iter().filter(|a| a.components > 0).collect())
+    }
+
+    /// The arrays of a POINT_DATA or CELL_DATA section, `count` being the number of points/cells
+    fn attribute(
+        &mut self,
+        line: &[&str],
+        count: usize,
+    ) -> Result<Vec<PointArray>, VtkError> {
+        let (name, components, values) = match line {
+            ["SCALARS", name, data_type, rest @ ..] => {
+                let components = match rest.first() {
+                    Some(components) => parse_count(components)?,
+                    None => 1,
+                };
+                if self.next_is("LOOKUP_TABLE") {
+                    self.line()?;
+                }
+                (
+                    name,
+                    components,
+                    self.values(count * components, data_type)?,
+                )
+            }
+            ["VECTORS", name, data_type] | ["NORMALS", name, data_type] => {
+                (name, 3, self.values(count * 3, data_type)?)
+            }
+            ["TENSORS", name, data_type] => (name, 9, self.values(count * 9, data_type)?),
+            ["TEXTURE_COORDINATES", name, components, data_type] => {
+                let components = parse_count(components)?;
+                (
+                    name,
+                    components,
+                    self.values(count * components, data_type)?,
+                )
+            }
+            ["COLOR_SCALARS", name, components] => {
+                let components = parse_count(components)?;
+                let mut values = self.values(count * components, "unsigned_char")?;
+                if self.binary {
+                    values.iter_mut().for_each(|v| *v /= 255.);
+                }
+                (name, components, values)
+            }
+            // the colors of a SCALARS LOOKUP_TABLE
+            ["LOOKUP_TABLE", _, size] => {
+                let size = parse_count(size)?;
+                self.values(size * 4, "unsigned_char")?;
+                return Ok(Vec::new());
+            }
+            ["FIELD", _, arrays] => return self.field(parse_count(arrays)?),
+            other => return Err(VtkError::Parse(format!("unexpected {:?}", other))),
+        };
+        Ok(vec![PointArray {
+            name: array_name(name),
+            components,
+            values: values.into_iter().map(|v| v as f32).collect(),
+        }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_polydata() {
+        let data = parse(
+            b"# vtk DataFile Version 3.0
+quad
+ASCII
+DATASET POLYDATA
+POINTS 4 float
+0 0 0 1 0 0 1 1 0
+0 1 0
+POLYGONS 1 5
+4 0 1 2 3
+POINT_DATA 4
+SCALARS pressure float
+LOOKUP_TABLE default
+1 2 3 4
+VECTORS velocity double
+1 0 0 1 0 0 1 0 0 1 0 0
+",
+        )
+        .unwrap();
+        assert_eq!(data.positions.len(), 4);
+        assert_eq!(data.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(
+            data.point_array("pressure").unwrap().values,
+            vec![1., 2., 3., 4.]
+        );
+        assert_eq!(data.point_array("velocity").unwrap().components, 3);
+    }
+
+    #[test]
+    fn test_unstructured_grid_boundary() {
+        // a hexahedron split in two along x, its shared face isn't drawn
+        let data = parse(
+            b"# vtk DataFile Version 4.2
+two hexahedra
+ASCII
+DATASET UNSTRUCTURED_GRID
+POINTS 12 float
+0 0 0 1 0 0 2 0 0 0 1 0 1 1 0 2 1 0
+0 0 1 1 0 1 2 0 1 0 1 1 1 1 1 2 1 1
+CELLS 2 18
+8 0 1 4 3 6 7 10 9
+8 1 2 5 4 7 8 11 10
+CELL_TYPES 2
+12
+12
+CELL_DATA 2
+SCALARS id int 1
+LOOKUP_TABLE default
+0 1
+",
+        )
+        .unwrap();
+        // 10 outer quads
+        assert_eq!(data.triangles.len(), 20);
+        assert!(data.point_data.is_empty());
+    }
+
+    #[test]
+    fn test_binary_points() {
+        let mut bytes = b"# vtk DataFile Version 5.1
+particles
+BINARY
+DATASET POLYDATA
+POINTS 2 float
+"
+        .to_vec();
+        for v in [0f32, 1., 2., 3., 4., 5.].iter() {
+            bytes.extend_from_slice(&v.to_be_bytes());
+        }
+        bytes.extend_from_slice(b"\nVERTICES 3 2\nOFFSETS vtktypeint64\n");
+        for v in [0i64, 1, 2].iter() {
+            bytes.extend_from_slice(&v.to_be_bytes());
+        }
+        bytes.extend_from_slice(b"\nCONNECTIVITY vtktypeint64\n");
+        for v in [0i64, 1].iter() {
+            bytes.extend_from_slice(&v.to_be_bytes());
+        }
+        bytes.extend_from_slice(b"\nPOINT_DATA 2\nFIELD FieldData 1\ndensity 1 2 double\n");
+        for v in [1000f64, 998.].iter() {
+            bytes.extend_from_slice(&v.to_be_bytes());
+        }
+        bytes.extend_from_slice(b"\nMETADATA\nINFORMATION 0\n\n");
+
+        let data = parse(&bytes).unwrap();
+        assert_eq!(data.positions, vec![[0., 1., 2.], [3., 4., 5.]]);
+        assert!(data.triangles.is_empty());
+        assert_eq!(
+            data.point_array("density").unwrap().values,
+            vec![1000., 998.]
+        );
+    }
+}
@@ -0,0 +1,525 @@
+use std::io::Read;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::BoxedFuture,
+};
+use roxmltree::Node;
+
+use super::{
+    mesh_data::{CellTriangulator, MeshData, PointArray, VTK_POLYGON, VTK_TRIANGLE_STRIP},
+    vtk::VtkError,
+};
+
+// VTK XML
+// Reads UnstructuredGrid (.vtu) and PolyData (.vtp) files, with ascii, inline base64 or appended
+// (raw or base64) data arrays, optionally zlib compressed:
+// ```
+// <VTKFile type="UnstructuredGrid" byte_order="LittleEndian" header_type="UInt64">
+//   <UnstructuredGrid>
+//     <Piece NumberOfPoints="8" NumberOfCells="1">
+//       <PointData><DataArray type="Float32" Name="pressure" format="appended" offset="0"/></PointData>
+//       <Points><DataArray type="Float32" NumberOfComponents="3" format="ascii">...</DataArray></Points>
+//       <Cells>connectivity, offsets and types DataArrays</Cells>
+//     </Piece>
+//   </UnstructuredGrid>
+//   <AppendedData encoding="raw">_...</AppendedData>
+// </VTKFile>
+// ```
+
+const APPENDED_DATA: &[u8] = b"<AppendedData";
+
+#[derive(Default)]
+pub struct VtkXmlLoader;
+
+impl AssetLoader for VtkXmlLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let mesh = parse(bytes)?.into_mesh();
+            load_context.set_default_asset(LoadedAsset::new(mesh));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vtu", "vtp"]
+    }
+}
+
+pub struct VtkXmlPlugin;
+
+impl Plugin for VtkXmlPlugin {
+    fn build(
+        &self,
+        app: &mut AppBuilder,
+    ) {
+        app.init_asset_loader::<VtkXmlLoader>();
+    }
+}
+
+/// How the file's binary data is laid out
+struct Encoding<'a> {
+    little_endian: bool,
+    /// bytes per header word, UInt32 or UInt64
+    header_size: usize,
+    compressed: bool,
+    /// the bytes after the `_` that starts <AppendedData>
+    appended: &'a [u8],
+    appended_base64: bool,
+}
+
+pub fn parse(bytes: &[u8]) -> Result<MeshData, VtkError> {
+    // raw appended data isn't valid xml, so it is cut off before parsing
+    let (xml, appended, appended_base64) = match find(bytes, APPENDED_DATA) {
+        Some(start) => {
+            let tag_end = start + find(&bytes[start..], b">").ok_or(VtkError::UnexpectedEof)?;
+            let underscore =
+                tag_end + find(&bytes[tag_end..], b"_").ok_or(VtkError::UnexpectedEof)?;
+            let tag = String::from_utf8_lossy(&bytes[start..tag_end]);
+            let appended = &bytes[underscore + 1..];
+            let appended = match find(appended, b"</AppendedData>") {
+                Some(end) if tag.contains("base64") => &appended[..end],
+                _ => appended,
+            };
+            let mut xml = String::from_utf8_lossy(&bytes[..start]).to_string();
+            xml.push_str("</VTKFile>");
+            (xml, appended, tag.contains("base64"))
+        }
+        None => (
+            String::from_utf8_lossy(bytes).to_string(),
+            &bytes[..0],
+            false,
+        ),
+    };
+
+    let document = roxmltree::Document::parse(&xml).map_err(|e| VtkError::Parse(e.to_string()))?;
+    let root = document.root_element();
+    if !root.has_tag_name("VTKFile") {
+        return Err(VtkError::Parse(String::from("not a vtk xml file")));
+    }
+    let encoding = Encoding {
+        little_endian: root.attribute("byte_order") != Some("BigEndian"),
+        header_size: match root.attribute("header_type") {
+            Some("UInt64") => 8,
+            _ => 4,
+        },
+        compressed: match root.attribute("compressor") {
+            None | Some("") => false,
+            Some("vtkZLibDataCompressor") => true,
+            Some(other) => return Err(VtkError::Unsupported(format!("{} data", other))),
+        },
+        appended,
+        appended_base64,
+    };
+
+    let kind = root.attribute("type").unwrap_or_default();
+    if kind != "UnstructuredGrid" && kind != "PolyData" {
+        return Err(VtkError::Unsupported(format!("{} datasets", kind)));
+    }
+    let dataset =
+        child(root, kind).ok_or_else(|| VtkError::Parse(format!("missing <{}>", kind)))?;
+
+    let mut data = MeshData::default();
+    let mut triangulator = CellTriangulator::default();
+    for piece in dataset.children().filter(|n| n.has_tag_name("Piece")) {
+        let first_point = data.positions.len();
+        let points = child(piece, "Points")
+            .and_then(|points| child(points, "DataArray"))
+            .map(|array| encoding.values(array))
+            .transpose()?
+            .unwrap_or_default();
+        data.positions.extend(
+            points
+                .chunks_exact(3)
+                .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32]),
+        );
+        let point_count = data.positions.len() - first_point;
+
+        let sections: &[(&str, Option<u8>)] = if kind == "UnstructuredGrid" {
+            &[("Cells", None)]
+        } else {
+            &[
+                ("Polys", Some(VTK_POLYGON)),
+                ("Strips", Some(VTK_TRIANGLE_STRIP)),
+            ]
+        };
+        for (section, cell_type) in sections.iter() {
+            if let Some(cells) = child(piece, section) {
+                add_cells(
+                    &encoding,
+                    cells,
+                    *cell_type,
+                    first_point,
+                    point_count,
+                    &mut triangulator,
+                )?;
+            }
+        }
+
+        if let Some(point_data) = child(piece, "PointData") {
+            for array in point_data
+                .children()
+                .filter(|n| n.has_tag_name("DataArray"))
+            {
+                let name = array.attribute("Name").unwrap_or_default().to_string();
+                let components = array
+                    .attribute("NumberOfComponents")
+                    .and_then(|c| c.parse().ok())
+                    .unwrap_or(1);
+                let values = encoding.values(array)?.into_iter().map(|v| v as f32);
+                // pieces are appended, so are their arrays
+                match data
+                    .point_data
+                    .iter_mut()
+                    .find(|a| a.name == name && a.components == components)
+                {
+                    Some(existing) => existing.values.extend(values),
+                    None if first_point == 0 => data.point_data.push(PointArray {
+                        name,
+                        components,
+                        values: values.collect(),
+                    }),
+                    None => {}
+                }
+            }
+        }
+    }
+
+    let point_count = data.positions.len();
+    data.point_data
+        .retain(|a| a.values.len() == point_count * a.components);
+    for (cell_type, count) in triangulator.unsupported() {
+        warn!(
+            "Skipped {} vtk cells of unsupported type {}",
+            count, cell_type
+        );
+    }
+    data.triangles = triangulator.into_triangles();
+    Ok(data)
+}
+
+/// Adds a piece's cells, their types read from a `types` array when `cell_type` is None
+fn add_cells(
+    encoding: &Encoding,
+    cells: Node,
+    cell_type: Option<u8>,
+    first_point: usize,
+    point_count: usize,
+    triangulator: &mut CellTriangulator,
+) -> Result<(), VtkError> {
+    let array = |name: &str| -> Result<Vec<f64>, VtkError> {
+        let array = cells
+            .children()
+            .find(|n| n.has_tag_name("DataArray") && n.attribute("Name") == Some(name))
+            .ok_or_else(|| VtkError::Parse(format!("cells without {}", name)))?;
+        encoding.values(array)
+    };
+    let connectivity = array("connectivity")?;
+    // where each cell ends
+    let offsets = array("offsets")?;
+    let types = match cell_type {
+        Some(cell_type) => vec![cell_type; offsets.len()],
+        None => array("types")?.into_iter().map(|t| t as u8).collect(),
+    };
+
+    if connectivity
+        .iter()
+        .any(|&i| i < 0. || i as usize >= point_count)
+    {
+        return Err(VtkError::Parse(String::from(
+            "cells use points they don't have",
+        )));
+    }
+    let connectivity: Vec<u32> = connectivity
+        .into_iter()
+        .map(|i| (i as usize + first_point) as u32)
+        .collect();
+
+    let mut start = 0;
+    for (&end, &cell_type) in offsets.iter().zip(types.iter()) {
+        let end = end as usize;
+        let cell = connectivity
+            .get(start..end)
+            .ok_or_else(|| VtkError::Parse(String::from("bad cell offsets")))?;
+        triangulator.add(cell_type, cell);
+        start = end;
+    }
+    Ok(())
+}
+
+fn child<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &str,
+) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn find(
+    bytes: &[u8],
+    needle: &[u8],
+) -> Option<usize> {
+    bytes.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Characters of base64 encoding `bytes` bytes
+fn base64_len(bytes: usize) -> usize {
+    (bytes + 2) / 3 * 4
+}
+
+fn decode_base64(text: &[u8]) -> Result<Vec<u8>, VtkError> {
+    base64::decode(text).map_err(|e| VtkError::Parse(format!("bad base64: {}", e)))
+}
+
+impl<'a> Encoding<'a> {
+    fn values(
+        &self,
+        array: Node,
+    ) -> Result<Vec<f64>, VtkError> {
+        let data_type = array.attribute("type").unwrap_or("Float32");
+        let bytes = match array.attribute("format").unwrap_or("ascii") {
+            "ascii" => {
+                return array
+                    .text()
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(|v| {
+                        v.parse::<f64>()
+                            .map_err(|_| VtkError::Parse(format!("bad value {}", v)))
+                    })
+                    .collect();
+            }
+            "binary" => {
+                let text: Vec<u8> = array
+                    .text()
+                    .unwrap_or_default()
+                    .bytes()
+                    .filter(|b| !b.is_ascii_whitespace())
+                    .collect();
+                self.base64_block(&text)?
+            }
+            "appended" => {
+                let offset: usize = array
+                    .attribute("offset")
+                    .and_then(|o| o.parse().ok())
+                    .ok_or_else(|| VtkError::Parse(String::from("appended without offset")))?;
+                let appended = self.appended.get(offset..).ok_or(VtkError::UnexpectedEof)?;
+                if self.appended_base64 {
+                    self.base64_block(appended)?
+                } else {
+                    self.raw_block(appended)?
+                }
+            }
+            other => return Err(VtkError::Unsupported(format!("{} data arrays", other))),
+        };
+        self.decode(&bytes, data_type)
+    }
+
+    /// Header words to read, once the first is known
+    fn header_words(
+        &self,
+        first: u64,
+    ) -> usize {
+        if self.compressed {
+            // block count, block size, last block size, then each block's compressed size
+            3 + first as usize
+        } else {
+            // data size
+            1
+        }
+    }
+
+    fn word(
+        &self,
+        bytes: &[u8],
+        index: usize,
+    ) -> Result<u64, VtkError> {
+        let size = self.header_size;
+        let bytes = bytes
+            .get(index * size..(index + 1) * size)
+            .ok_or(VtkError::UnexpectedEof)?;
+        let data_type = if size == 8 { "UInt64" } else { "UInt32" };
+        Ok(self.decode(bytes, data_type)?[0] as u64)
+    }
+
+    /// How many bytes of data follow the header
+    fn data_len(
+        &self,
+        header: &[u8],
+    ) -> Result<usize, VtkError> {
+        if !self.compressed {
+            return Ok(self.word(header, 0)? as usize);
+        }
+        let blocks = self.word(header, 0)? as usize;
+        (0..blocks).try_fold(0, |len, block| {
+            Ok(len + self.word(header, 3 + block)? as usize)
+        })
+    }
+
+    fn raw_block(
+        &self,
+        bytes: &[u8],
+    ) -> Result<Vec<u8>, VtkError> {
+        let header_len = self.header_words(self.word(bytes, 0)?) * self.header_size;
+        let header = bytes.get(..header_len).ok_or(VtkError::UnexpectedEof)?;
+        let data_len = self.data_len(header)?;
+        let data = bytes
+            .get(header_len..header_len + data_len)
+            .ok_or(VtkError::UnexpectedEof)?;
+        self.decompress(header, data)
+    }
+
+    /// The header and data are base64 encoded separately, as VTK writes them
+    fn base64_block(
+        &self,
+        text: &[u8],
+    ) -> Result<Vec<u8>, VtkError> {
+        let first = text
+            .get(..base64_len(self.header_size))
+            .ok_or(VtkError::UnexpectedEof)?;
+        let header_len =
+            self.header_words(self.word(&decode_base64(first)?, 0)?) * self.header_size;
+        let header_chars = base64_len(header_len);
+        let header = decode_base64(text.get(..header_chars).ok_or(VtkError::UnexpectedEof)?)?;
+        let data_chars = base64_len(self.data_len(&header)?);
+        let data = text
+            .get(header_chars..header_chars + data_chars)
+            .ok_or(VtkError::UnexpectedEof)?;
+        self.decompress(&header, &decode_base64(data)?)
+    }
+
+    fn decompress(
+        &self,
+        header: &[u8],
+        data: &[u8],
+    ) -> Result<Vec<u8>, VtkError> {
+        if !self.compressed {
+            return Ok(data.to_vec());
+        }
+        let blocks = self.word(header, 0)? as usize;
+        let mut decompressed = Vec::new();
+        let mut start = 0;
+        for block in 0..blocks {
+            let end = start + self.word(header, 3 + block)? as usize;
+            let compressed = data.get(start..end).ok_or(VtkError::UnexpectedEof)?;
+            flate2::read::ZlibDecoder::new(compressed)
+                .read_to_end(&mut decompressed)
+                .map_err(|e| VtkError::Parse(format!("bad compressed data: {}", e)))?;
+            start = end;
+        }
+        Ok(decompressed)
+    }
+
+    fn decode(
+        &self,
+        bytes: &[u8],
+        data_type: &str,
+    ) -> Result<Vec<f64>, VtkError> {
+        let size = match data_type {
+            "Int8" | "UInt8" => 1,
+            "Int16" | "UInt16" => 2,
+            "Int32" | "UInt32" | "Float32" => 4,
+            "Int64" | "UInt64" | "Float64" => 8,
+            other => return Err(VtkError::Unsupported(format!("{} data", other))),
+        };
+        let little_endian = self.little_endian;
+        let values = bytes.chunks_exact(size).map(|b| {
+            let mut word = [0u8; 8];
+            word[..size].copy_from_slice(b);
+            if !little_endian {
+                word[..size].reverse();
+            }
+            let w = word;
+            match data_type {
+                "Int8" => w[0] as i8 as f64,
+                "UInt8" => w[0] as f64,
+                "Int16" => i16::from_le_bytes([w[0], w[1]]) as f64,
+                "UInt16" => u16::from_le_bytes([w[0], w[1]]) as f64,
+                "Int32" => i32::from_le_bytes([w[0], w[1], w[2], w[3]]) as f64,
+                "UInt32" => u32::from_le_bytes([w[0], w[1], w[2], w[3]]) as f64,
+                "Float32" => f32::from_le_bytes([w[0], w[1], w[2], w[3]]) as f64,
+                "Int64" => i64::from_le_bytes(w) as f64,
+                "UInt64" => u64::from_le_bytes(w) as f64,
+                _ => f64::from_le_bytes(w),
+            }
+        });
+        Ok(values.collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_polydata() {
+        let data = parse(
+            br#"<?xml version="1.0"?>
+<VTKFile type="PolyData" version="1.0" byte_order="LittleEndian">
+  <PolyData>
+    <Piece NumberOfPoints="3" NumberOfPolys="1">
+      <PointData>
+        <DataArray type="Float32" Name="velocity" NumberOfComponents="3" format="ascii">
+          1 0 0 1 0 0 1 0 0
+        </DataArray>
+      </PointData>
+      <Points>
+        <DataArray type="Float32" NumberOfComponents="3" format="ascii">0 0 0 1 0 0 0 1 0</DataArray>
+      </Points>
+      <Polys>
+        <DataArray type="Int32" Name="connectivity" format="ascii">0 1 2</DataArray>
+        <DataArray type="Int32" Name="offsets" format="ascii">3</DataArray>
+      </Polys>
+    </Piece>
+  </PolyData>
+</VTKFile>"#,
+        )
+        .unwrap();
+        assert_eq!(data.triangles, vec![[0, 1, 2]]);
+        assert_eq!(data.point_array("velocity").unwrap().values.len(), 9);
+    }
+
+    #[test]
+    fn test_appended_and_inline_binary() {
+        let mut points = 24u32.to_le_bytes().to_vec();
+        for v in [0f32, 1., 2., 3., 4., 5.].iter() {
+            points.extend_from_slice(&v.to_le_bytes());
+        }
+        let mut density = base64::encode(8u32.to_le_bytes());
+        density.push_str(&base64::encode(
+            [1000f32.to_le_bytes(), 998f32.to_le_bytes()].concat(),
+        ));
+
+        let mut file = format!(
+            r#"<VTKFile type="UnstructuredGrid" byte_order="LittleEndian" header_type="UInt32">
+  <UnstructuredGrid>
+    <Piece NumberOfPoints="2" NumberOfCells="0">
+      <PointData>
+        <DataArray type="Float32" Name="density" format="binary">{}</DataArray>
+      </PointData>
+      <Points>
+        <DataArray type="Float32" NumberOfComponents="3" format="appended" offset="0"/>
+      </Points>
+    </Piece>
+  </UnstructuredGrid>
+  <AppendedData encoding="raw">
+   _"#,
+            density
+        )
+        .into_bytes();
+        file.extend_from_slice(&points);
+        file.extend_from_slice(b"\n  </AppendedData>\n</VTKFile>");
+
+        let data = parse(&file).unwrap();
+        assert_eq!(data.positions, vec![[0., 1., 2.], [3., 4., 5.]]);
+        assert!(data.triangles.is_empty());
+        assert_eq!(
+            data.point_array("density").unwrap().values,
+            vec![1000., 998.]
+        );
+    }
+}
@@ -0,0 +1,465 @@
+use std::{
+    convert::TryInto,
+    fmt, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use super::asset_io::ASSETS_DIR;
+use super::loader_fu::features::{attribute_names, record_attribute};
+use crate::app::resources::dataset_manifest::MANIFEST_FILE;
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        pipeline::PrimitiveTopology,
+    },
+    utils::BoxedFuture,
+};
+
+// Mesh Cache
+// Parsed meshes, written to disk so a dataset opens again without parsing each frame. A frame is
+// cached in a file named for the hash of its path, headed by the size and modification time the
+// frame had, and the hash of the manifest beside it, so a frame that changes on disk, or whose
+// manifest does, is parsed (and cached) again:
+// ```
+// magic, source size: u64, source mtime: u64 secs + u32 nanos, manifest hash: u64,
+// topology: u32, attribute count: u32, index width: u32 (0, 2 or 4), index count: u32
+// per attribute: name length: u32, name (padded to 4), format: u32, values: u32, data
+// indices
+// ```
+// Everything is little endian, and 4 byte aligned, so the arrays could be mapped straight from
+// the file. The options a loader read from the dataset's manifest are baked in, which is why the
+// manifest is part of the header
+
+pub const MESH_CACHE_EXTENSION: &str = "mrmesh";
+const MAGIC: &[u8; 8] = b"MRMESH02";
+const HEADER_LEN: usize = 52;
+
+#[derive(Debug)]
+pub enum MeshCacheError {
+    Io(io::Error),
+    Parse(String),
+}
+
+impl fmt::Display for MeshCacheError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            MeshCacheError::Io(e) => write!(f, "couldn't read cached mesh: {}", e),
+            MeshCacheError::Parse(e) => write!(f, "couldn't parse cached mesh: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MeshCacheError {}
+
+impl From<io::Error> for MeshCacheError {
+    fn from(e: io::Error) -> Self {
+        MeshCacheError::Io(e)
+    }
+}
+
+/// The size & modification time of a frame when it was cached, and its manifest
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SourceStamp {
+    pub size: u64,
+    pub secs: u64,
+    pub nanos: u32,
+    /// the hash of the manifest beside the frame, 0 without one
+    pub manifest: u64,
+}
+
+impl SourceStamp {
+    pub fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok().filter(|m| m.is_file())?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        let manifest = path
+            .parent()
+            .and_then(|dir| fs::read(dir.join(MANIFEST_FILE)).ok())
+            .map_or(0, |manifest| fnv1a(&manifest));
+        Some(Self {
+            size: metadata.len(),
+            secs: modified.as_secs(),
+            nanos: modified.subsec_nanos(),
+            manifest,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MeshCache {
+    dir: PathBuf,
+}
+
+impl MeshCache {
+    pub fn new(dir: &Path) -> Self {
+        // cached meshes are loaded by their absolute path
+        let _ = fs::create_dir_all(dir);
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        Self { dir }
+    }
+
+    /// Where the mesh of the frame at `asset_path` is cached
+    pub fn path_of(
+        &self,
+        asset_path: &str,
+    ) -> PathBuf {
+        self.dir.join(format!(
+            "{:016x}.{}",
+            fnv1a(asset_path.as_bytes()),
+            MESH_CACHE_EXTENSION
+        ))
+    }
+
+    /// The cached mesh of the frame at `asset_path`, if it was cached since the frame last changed
+    pub fn valid_path(
+        &self,
+        asset_path: &str,
+    ) -> Option<PathBuf> {
+        let stamp = SourceStamp::of(&source_path(asset_path))?;
+        let cached = self.path_of(asset_path);
+        let mut header = [0u8; HEADER_LEN];
+        fs::File::open(&cached)
+            .and_then(|mut f| f.read_exact(&mut header))
+            .ok()?;
+        match read_header(&header) {
+            Ok((cached_stamp, _)) if cached_stamp == stamp => Some(cached),
+            _ => None,
+        }
+    }
+
+    /// Encodes the mesh of the frame at `asset_path`, to be written to `path_of`. None if the
+    /// frame isn't a file on disk, or the mesh has attributes that can't be cached
+    pub fn encode(
+        &self,
+        asset_path: &str,
+        mesh: &Mesh,
+    ) -> Option<Vec<u8>> {
+        let stamp = SourceStamp::of(&source_path(asset_path))?;
+        encode(stamp, mesh)
+    }
+}
+
+/// Where a frame is on disk. Frames within archives have no file of their own
+fn source_path(asset_path: &str) -> PathBuf {
+    let path = Path::new(asset_path);
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        Path::new(ASSETS_DIR).join(path)
+    }
+}
+
+/// A hash that stays the same between builds, unlike std's
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn topology_code(topology: PrimitiveTopology) -> u32 {
+    match topology {
+        PrimitiveTopology::PointList => 0,
+        PrimitiveTopology::LineList => 1,
+        PrimitiveTopology::LineStrip => 2,
+        PrimitiveTopology::TriangleList => 3,
+        PrimitiveTopology::TriangleStrip => 4,
+    }
+}
+
+fn topology_from_code(code: u32) -> Option<PrimitiveTopology> {
+    match code {
+        0 => Some(PrimitiveTopology::PointList),
+        1 => Some(PrimitiveTopology::LineList),
+        2 => Some(PrimitiveTopology::LineStrip),
+        3 => Some(PrimitiveTopology::TriangleList),
+        4 => Some(PrimitiveTopology::TriangleStrip),
+        _ => None,
+    }
+}
+
+/// (format, values, the bytes of every value), for the 32 bit formats
+fn attribute_bytes(values: &VertexAttributeValues) -> Option<(u32, usize, Vec<u8>)> {
+    fn floats(v: &[f32]) -> Vec<u8> {
+        v.iter().flat_map(|f| f.to_le_bytes().to_vec()).collect()
+    }
+    fn ints(v: &[i32]) -> Vec<u8> {
+        v.iter().flat_map(|i| i.to_le_bytes().to_vec()).collect()
+    }
+    fn uints(v: &[u32]) -> Vec<u8> {
+        v.iter().flat_map(|u| u.to_le_bytes().to_vec()).collect()
+    }
+    Some(match values {
+        VertexAttributeValues::Float(v) => (0, v.len(), floats(v)),
+        VertexAttributeValues::Float2(v) => (1, v.len(), floats(&v.concat())),
+        VertexAttributeValues::Float3(v) => (2, v.len(), floats(&v.concat())),
+        VertexAttributeValues::Float4(v) => (3, v.len(), floats(&v.concat())),
+        VertexAttributeValues::Int(v) => (4, v.len(), ints(v)),
+        VertexAttributeValues::Int2(v) => (5, v.len(), ints(&v.concat())),
+        VertexAttributeValues::Int3(v) => (6, v.len(), ints(&v.concat())),
+        VertexAttributeValues::Int4(v) => (7, v.len(), ints(&v.concat())),
+        VertexAttributeValues::Uint(v) => (8, v.len(), uints(v)),
+        VertexAttributeValues::Uint2(v) => (9, v.len(), uints(&v.concat())),
+        VertexAttributeValues::Uint3(v) => (10, v.len(), uints(&v.concat())),
+        VertexAttributeValues::Uint4(v) => (11, v.len(), uints(&v.concat())),
+        _ => return None,
+    })
+}
+
+fn attribute_values(
+    format: u32,
+    count: usize,
+    input: &mut &[u8],
+) -> Result<VertexAttributeValues, MeshCacheError> {
+    let components = format as usize % 4 + 1;
+    let words = read_words(input, count * components)?;
+    let f = |w: &[u8; 4]| f32::from_le_bytes(*w);
+    let i = |w: &[u8; 4]| i32::from_le_bytes(*w);
+    let u = |w: &[u8; 4]| u32::from_le_bytes(*w);
+    fn chunked<T: Copy, const N: usize>(
+        words: &[[u8; 4]],
+        read: impl Fn(&[u8; 4]) -> T,
+    ) -> Vec<[T; N]> {
+        words
+            .chunks_exact(N)
+            .map(|c| {
+                let values: Vec<T> = c.iter().map(&read).collect();
+                values.try_into().unwrap_or_else(|_| unreachable!())
+            })
+            .collect()
+    }
+    Ok(match format {
+        0 => VertexAttributeValues::Float(words.iter().map(f).collect()),
+        1 => VertexAttributeValues::Float2(chunked(&words, f)),
+        2 => VertexAttributeValues::Float3(chunked(&words, f)),
+        3 => VertexAttributeValues::Float4(chunked(&words, f)),
+        4 => VertexAttributeValues::Int(words.iter().map(i).collect()),
+        5 => VertexAttributeValues::Int2(chunked(&words, i)),
+        6 => VertexAttributeValues::Int3(chunked(&words, i)),
+        7 => VertexAttributeValues::Int4(chunked(&words, i)),
+        8 => VertexAttributeValues::Uint(words.iter().map(u).collect()),
+        9 => VertexAttributeValues::Uint2(chunked(&words, u)),
+        10 => VertexAttributeValues::Uint3(chunked(&words, u)),
+        11 => VertexAttributeValues::Uint4(chunked(&words, u)),
+        other => return Err(MeshCacheError::Parse(format!("unknown format {}", other))),
+    })
+}
+
+pub fn encode(
+    stamp: SourceStamp,
+    mesh: &Mesh,
+) -> Option<Vec<u8>> {
+    let mut attributes = Vec::new();
+    for name in attribute_names(mesh) {
+        let values = attribute_bytes(mesh.attribute(name.clone())?)?;
+        attributes.push((name, values));
+    }
+    let (index_width, index_count, index_bytes) = match mesh.indices() {
+        None => (0u32, 0, Vec::new()),
+        Some(Indices::U16(indices)) => {
+            let mut bytes: Vec<u8> = indices
+                .iter()
+                .flat_map(|i| i.to_le_bytes().to_vec())
+                .collect();
+            // keeps the file's length a multiple of 4
+            bytes.resize((bytes.len() + 3) / 4 * 4, 0);
+            (2, indices.len(), bytes)
+        }
+        Some(Indices::U32(indices)) => (
+            4,
+            indices.len(),
+            indices
+                .iter()
+                .flat_map(|i| i.to_le_bytes().to_vec())
+                .collect(),
+        ),
+    };
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&stamp.size.to_le_bytes());
+    bytes.extend_from_slice(&stamp.secs.to_le_bytes());
+    bytes.extend_from_slice(&stamp.nanos.to_le_bytes());
+    bytes.extend_from_slice(&stamp.manifest.to_le_bytes());
+    bytes.extend_from_slice(&topology_code(mesh.primitive_topology()).to_le_bytes());
+    bytes.extend_from_slice(&(attributes.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&index_width.to_le_bytes());
+    bytes.extend_from_slice(&(index_count as u32).to_le_bytes());
+    for (name, (format, count, data)) in attributes {
+        bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.resize((bytes.len() + 3) / 4 * 4, 0);
+        bytes.extend_from_slice(&format.to_le_bytes());
+        bytes.extend_from_slice(&(count as u32).to_le_bytes());
+        bytes.extend_from_slice(&data);
+    }
+    bytes.extend_from_slice(&index_bytes);
+    Some(bytes)
+}
+
+/// (source stamp, topology)
+fn read_header(header: &[u8]) -> Result<(SourceStamp, u32), MeshCacheError> {
+    let mut input = header;
+    let mut magic = [0u8; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(MeshCacheError::Parse(String::from("not a cached mesh")));
+    }
+    let stamp = SourceStamp {
+        size: read_u64(&mut input)?,
+        secs: read_u64(&mut input)?,
+        nanos: read_u32(&mut input)?,
+        manifest: read_u64(&mut input)?,
+    };
+    Ok((stamp, read_u32(&mut input)?))
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Mesh, MeshCacheError> {
+    let (_, topology) = read_header(bytes)?;
+    let topology = topology_from_code(topology)
+        .ok_or_else(|| MeshCacheError::Parse(format!("unknown topology {}", topology)))?;
+    let mut input = &bytes[HEADER_LEN - 12..];
+    let attribute_count = read_u32(&mut input)?;
+    let index_width = read_u32(&mut input)?;
+    let index_count = read_u32(&mut input)? as usize;
+
+    let mut mesh = Mesh::new(topology);
+    for _ in 0..attribute_count {
+        let name_len = read_u32(&mut input)? as usize;
+        let name_words = read_words(&mut input, (name_len + 3) / 4)?;
+        let name_bytes: Vec<u8> = name_words.concat().into_iter().take(name_len).collect();
+        let name = String::from_utf8(name_bytes)
+            .map_err(|_| MeshCacheError::Parse(String::from("attribute name isn't utf8")))?;
+        let format = read_u32(&mut input)?;
+        let count = read_u32(&mut input)? as usize;
+        record_attribute(&name);
+        mesh.set_attribute(name, attribute_values(format, count, &mut input)?);
+    }
+    match index_width {
+        0 => {}
+        2 => {
+            let words = read_words(&mut input, (index_count + 1) / 2)?;
+            let indices = words
+                .iter()
+                .flat_map(|w| {
+                    vec![
+                        u16::from_le_bytes([w[0], w[1]]),
+                        u16::from_le_bytes([w[2], w[3]]),
+                    ]
+                })
+                .take(index_count)
+                .collect();
+            mesh.set_indices(Some(Indices::U16(indices)));
+        }
+        4 => {
+            let words = read_words(&mut input, index_count)?;
+            let indices = words.iter().map(|w| u32::from_le_bytes(*w)).collect();
+            mesh.set_indices(Some(Indices::U32(indices)));
+        }
+        other => {
+            return Err(MeshCacheError::Parse(format!(
+                "unknown index width {}",
+                other
+            )))
+        }
+    }
+    Ok(mesh)
+}
+
+fn read_words(
+    input: &mut &[u8],
+    count: usize,
+) -> Result<Vec<[u8; 4]>, MeshCacheError> {
+    if input.len() < count * 4 {
+        return Err(MeshCacheError::Parse(String::from("truncated")));
+    }
+    let (words, rest) = input.split_at(count * 4);
+    *input = rest;
+    Ok(words
+        .chunks_exact(4)
+        .map(|w| [w[0], w[1], w[2], w[3]])
+        .collect())
+}
+
+fn read_u32(input: &mut &[u8]) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut &[u8]) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[derive(Default)]
+pub struct MeshCacheLoader;
+
+impl AssetLoader for MeshCacheLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let mesh = decode(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(mesh));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &[MESH_CACHE_EXTENSION]
+    }
+}
+
+pub struct MeshCachePlugin;
+
+impl Plugin for MeshCachePlugin {
+    fn build(
+        &self,
+        app: &mut AppBuilder,
+    ) {
+        app.init_asset_loader::<MeshCacheLoader>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::support::loader_fu::features::{point_data_attribute, set_point_data};
+
+    #[test]
+    fn test_round_trip() {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            vec![[0f32, 0., 0.], [1., 0., 0.], [0., 1., 0.]],
+        );
+        set_point_data(&mut mesh, "density", vec![1f32, 2., 3.]);
+        mesh.set_indices(Some(Indices::U16(vec![0, 1, 2])));
+        let stamp = SourceStamp {
+            size: 10,
+            secs: 20,
+            nanos: 30,
+            manifest: 40,
+        };
+
+        let bytes = encode(stamp, &mesh).unwrap();
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(read_header(&bytes).unwrap(), (stamp, 3));
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded.count_vertices(), 3);
+        assert!(matches!(
+            decoded.attribute(point_data_attribute("density")),
+            Some(VertexAttributeValues::Float(v)) if *v == vec![1., 2., 3.]
+        ));
+        assert!(matches!(decoded.indices(), Some(Indices::U16(i)) if *i == vec![0, 1, 2]));
+        assert!(decode(&bytes[..bytes.len() - 4]).is_err());
+    }
+}